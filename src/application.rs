@@ -17,6 +17,11 @@
  *
  * SPDX-License-Identifier: GPL-3.0-or-later
  */
+use std::error::Error;
+use std::io::Write;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+
 use adw::prelude::AdwDialogExt;
 use adw::subclass::prelude::*;
 use gettextrs::gettext;
@@ -24,6 +29,7 @@ use gtk4::prelude::*;
 use gtk4::{gio, glib};
 
 use crate::config::{APP_ID, VERSION};
+use crate::message::message::{Message, MessageParser};
 use crate::MailViewerWindow;
 
 mod imp {
@@ -33,7 +39,7 @@ mod imp {
 
   #[derive(Debug, Default)]
   pub struct MailViewerApplication {
-    pub(crate) filename: RefCell<Option<String>>,
+    pub(crate) filenames: RefCell<Vec<String>>,
   }
 
   #[glib::object_subclass]
@@ -50,18 +56,44 @@ mod imp {
       self.parent_constructed();
       let obj = self.obj();
       obj.setup_gactions();
+      obj.add_main_option(
+        "to-eml",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::Filename,
+        &gettext("Write MESSAGE converted to EML on the standard output"),
+        Some("MESSAGE"),
+      );
       obj.set_accels_for_action("app.quit", &["<primary>q"]);
       obj.set_accels_for_action("win.open-file-dialog", &["<primary>o"]);
+      obj.set_accels_for_action("win.open-folder-dialog", &["<primary><shift>o"]);
+      obj.set_accels_for_action("win.search-folder", &["<primary><shift>f"]);
       obj.set_accels_for_action("win.print", &["<primary>p"]);
       obj.set_accels_for_action("win.reset-zoom", &["<primary>r"]);
       obj.set_accels_for_action("win.search", &["<primary>f"]);
+      obj.set_accels_for_action("win.previous-message", &["<alt>Left"]);
+      obj.set_accels_for_action("win.next-message", &["<alt>Right"]);
       obj.set_accels_for_action("app.shortcuts", &["<primary>question"]);
     }
   }
 
   impl ApplicationImpl for MailViewerApplication {
+    /// `--to-eml` converts without opening any window.
+    fn handle_local_options(&self, options: &glib::VariantDict) -> ControlFlow<glib::ExitCode> {
+      let Ok(Some(path)) = options.lookup::<PathBuf>("to-eml") else {
+        return self.parent_handle_local_options(options);
+      };
+      if let Err(e) = super::MailViewerApplication::print_eml(&path) {
+        eprintln!("{}: {}", path.display(), e);
+        return ControlFlow::Break(glib::ExitCode::FAILURE);
+      }
+      ControlFlow::Break(glib::ExitCode::SUCCESS)
+    }
+
     fn activate(&self) {
       let application = self.obj();
+      let filenames = self.filenames.take();
+      let is_new = application.active_window().is_none();
 
       let window: MailViewerWindow = if let Some(window) = application.active_window() {
         window.downcast::<MailViewerWindow>().ok().unwrap()
@@ -81,10 +113,14 @@ mod imp {
       };
       window.present();
 
+      // A running instance only opens the files it is handed.
+      if !is_new && filenames.is_empty() {
+        return;
+      }
       if let Err(e) = adw::prelude::WidgetExt::activate_action(
         &window,
-        "win.open-file",
-        Some(&glib::Variant::from(self.filename.borrow().clone())),
+        "win.open-files",
+        Some(&filenames.to_variant()),
       ) {
         log::debug!("open_file_dialog({e})");
         window.alert_error(&gettext("File Error"), &e.to_string(), false);
@@ -97,9 +133,9 @@ mod imp {
       }
 
       // A path is not necessarily valid utf-8, the uri always is.
-      if let Some(file) = files.first() {
-        self.filename.replace(Some(file.uri().to_string()));
-      }
+      self
+        .filenames
+        .replace(files.iter().map(|file| file.uri().to_string()).collect());
       self.activate();
     }
   }
@@ -135,6 +171,13 @@ impl MailViewerApplication {
     self.add_action_entries([quit_action, about_action, shortcuts_action]);
   }
 
+  fn print_eml(path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut parser = MessageParser::from_content(Some(path), std::fs::read(path)?);
+    parser.parse(None)?;
+    std::io::stdout().write_all(&parser.to_eml())?;
+    Ok(())
+  }
+
   fn show_shortcuts(&self) {
     let window = self.active_window().unwrap();
     let builder = gtk4::Builder::from_string(gtk4::include_blueprint!("src/gtk/help-overlay.blp"));
@@ -0,0 +1,112 @@
+/* attachmentcontent.rs
+ *
+ * Copyright 2024 Alexandre Del Bigio
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+use std::cell::{OnceCell, RefCell};
+use std::future::Future;
+use std::pin::Pin;
+
+use gtk4::gdk::subclass::prelude::*;
+use gtk4::prelude::*;
+use gtk4::{gdk, gio, glib};
+
+use crate::message::attachment::Attachment;
+
+const URI_LIST: &str = "text/uri-list";
+
+mod imp {
+  use super::*;
+
+  #[derive(Debug, Default)]
+  pub struct AttachmentContent {
+    pub(super) attachment: OnceCell<Attachment>,
+    /// Where the attachment was written, once a drop target asked for it.
+    pub(super) file: RefCell<Option<gio::File>>,
+  }
+
+  #[glib::object_subclass]
+  impl ObjectSubclass for AttachmentContent {
+    type ParentType = gdk::ContentProvider;
+    type Type = super::AttachmentContent;
+
+    const NAME: &'static str = "MailViewerAttachmentContent";
+  }
+
+  impl ObjectImpl for AttachmentContent {}
+
+  impl ContentProviderImpl for AttachmentContent {
+    fn formats(&self) -> gdk::ContentFormats {
+      gdk::ContentFormats::new(&[URI_LIST])
+    }
+
+    fn write_mime_type_future(
+      &self,
+      mime_type: &str,
+      stream: &gio::OutputStream,
+      io_priority: glib::Priority,
+    ) -> Pin<Box<dyn Future<Output = Result<(), glib::Error>> + 'static>> {
+      if mime_type != URI_LIST {
+        return self.parent_write_mime_type_future(mime_type, stream, io_priority);
+      }
+      let content = self.obj().clone();
+      let stream = stream.clone();
+      Box::pin(async move {
+        let file = content.file().await?;
+        stream
+          .write_all_future(format!("{}\r\n", file.uri()), io_priority)
+          .await
+          .map_err(|(_, e)| e)?;
+        Ok(())
+      })
+    }
+  }
+}
+
+glib::wrapper! {
+  /// An attachment dragged out of its row. It is only written to the
+  /// temporary folder when a drop target asks for it, without blocking the
+  /// window.
+  pub struct AttachmentContent(ObjectSubclass<imp::AttachmentContent>)
+    @extends gdk::ContentProvider;
+}
+
+impl AttachmentContent {
+  pub fn new(attachment: &Attachment) -> Self {
+    let content: Self = glib::Object::new();
+    content
+      .imp()
+      .attachment
+      .set(attachment.clone())
+      .expect("attachment is only set once");
+    content
+  }
+
+  /// The file the attachment is written to, the first time it is asked for.
+  async fn file(&self) -> Result<gio::File, glib::Error> {
+    if let Some(file) = self.imp().file.borrow().clone() {
+      return Ok(file);
+    }
+    let attachment = self.imp().attachment.get().expect("attachment is set");
+    let file = attachment.write_to_tmp().await.map_err(|e| {
+      log::error!("write_to_tmp({})", e);
+      glib::Error::new(gio::IOErrorEnum::Failed, &e.to_string())
+    })?;
+    self.imp().file.replace(Some(file.clone()));
+    Ok(file)
+  }
+}
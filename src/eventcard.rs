@@ -0,0 +1,212 @@
+/* eventcard.rs
+ *
+ * Copyright 2024 Alexandre Del Bigio
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+use adw::prelude::*;
+use gettextrs::gettext;
+use gtk4::glib;
+
+use crate::message::calendar::{self, CalendarEvent, Civil, EventTime};
+
+const DATE_FORMAT: &str = "%a %Y-%m-%d";
+const DATE_TIME_FORMAT: &str = "%a %Y-%m-%d %H:%M";
+const TIME_FORMAT: &str = "%H:%M";
+
+/// A summary of the event an invitation is about. `add_to_calendar` is called
+/// with the event when the user asks for it.
+pub fn event_card<F: Fn(&CalendarEvent) + 'static>(
+  event: &CalendarEvent,
+  add_to_calendar: F,
+) -> gtk4::Widget {
+  let icon = gtk4::Image::from_icon_name("x-office-calendar-symbolic");
+  icon.set_pixel_size(24);
+
+  let summary = if event.summary.is_empty() {
+    gettext("Untitled Event")
+  } else {
+    event.summary.clone()
+  };
+  let title = gtk4::Label::new(Some(&summary));
+  title.set_xalign(0.0);
+  title.set_hexpand(true);
+  title.set_wrap(true);
+  title.set_selectable(true);
+  title.add_css_class("title-4");
+
+  let header = gtk4::Box::new(gtk4::Orientation::Horizontal, 12);
+  header.append(&icon);
+  header.append(&title);
+  if event.is_cancelled() {
+    let badge = gtk4::Label::new(Some(&gettext("Cancelled")));
+    badge.set_valign(gtk4::Align::Center);
+    badge.add_css_class("risk-badge");
+    badge.add_css_class("error");
+    header.append(&badge);
+  }
+  let add = gtk4::Button::with_label(&gettext("Add to Calendar"));
+  add.set_valign(gtk4::Align::Center);
+  add.set_tooltip_text(Some(&gettext("Export the event as an iCalendar file")));
+  let event_to_add = event.clone();
+  add.connect_clicked(move |_| add_to_calendar(&event_to_add));
+  header.append(&add);
+
+  let attendees: Vec<String> = event
+    .attendees
+    .iter()
+    .map(|attendee| match attendee.status_label() {
+      Some(status) => format!("{} ({})", attendee.label(), status),
+      None => attendee.label(),
+    })
+    .collect();
+  let rows = [
+    (gettext("When"), when(event)),
+    (gettext("Where"), Some(event.location.clone())),
+    (
+      gettext("Organizer"),
+      event.organizer.as_ref().map(|organizer| organizer.label()),
+    ),
+    (gettext("Attendees"), Some(attendees.join("\n"))),
+    (gettext("Repeats"), event.recurrence()),
+  ];
+
+  let grid = gtk4::Grid::new();
+  grid.set_row_spacing(6);
+  grid.set_column_spacing(12);
+  let mut row = 0;
+  for (name, value) in rows {
+    let Some(value) = value.filter(|value| !value.is_empty()) else {
+      continue;
+    };
+    let name = gtk4::Label::new(Some(&name));
+    name.set_xalign(0.0);
+    name.set_yalign(0.0);
+    name.add_css_class("dim-label");
+    grid.attach(&name, 0, row, 1, 1);
+
+    let label = gtk4::Label::new(Some(&value));
+    label.set_xalign(0.0);
+    label.set_wrap(true);
+    label.set_selectable(true);
+    grid.attach(&label, 1, row, 1, 1);
+    row += 1;
+  }
+
+  let card = gtk4::Box::new(gtk4::Orientation::Vertical, 12);
+  card.add_css_class("card");
+  card.add_css_class("event-card");
+  card.append(&header);
+  card.append(&grid);
+  card.upcast()
+}
+
+fn civil_date_time(civil: &Civil) -> Option<glib::DateTime> {
+  glib::DateTime::from_local(
+    civil.year,
+    civil.month,
+    civil.day,
+    civil.hour,
+    civil.minute,
+    civil.second as f64,
+  )
+  .ok()
+}
+
+/// The zone of a TZID the invitation doesn't describe, if glib knows it by
+/// its IANA name.
+fn time_zone(zone: &str) -> Option<glib::TimeZone> {
+  glib::TimeZone::from_identifier(Some(calendar::iana_zone(zone)))
+}
+
+/// A time of the event in the local time zone. A time in a zone glib doesn't
+/// know is left as it is, see `unknown_zone`.
+fn local_date_time(time: &EventTime) -> Option<glib::DateTime> {
+  match time {
+    EventTime::Utc(time) | EventTime::Described(_, _, time) => {
+      glib::DateTime::from_unix_local(*time).ok()
+    }
+    EventTime::Zoned(civil, zone) => time_zone(zone)
+      .and_then(|zone| {
+        glib::DateTime::new(
+          &zone,
+          civil.year,
+          civil.month,
+          civil.day,
+          civil.hour,
+          civil.minute,
+          civil.second as f64,
+        )
+        .ok()
+      })
+      .and_then(|date_time| date_time.to_local().ok())
+      .or_else(|| civil_date_time(civil)),
+    EventTime::Date(civil) | EventTime::Floating(civil) => civil_date_time(civil),
+  }
+}
+
+/// The TZID of a time which couldn't be converted to the local time zone.
+fn unknown_zone(time: &EventTime) -> Option<&str> {
+  match time {
+    EventTime::Zoned(_, zone) if time_zone(zone).is_none() => Some(zone),
+    _ => None,
+  }
+}
+
+fn format(date_time: &glib::DateTime, format: &str) -> String {
+  date_time
+    .format(format)
+    .map(|text| text.to_string())
+    .unwrap_or_default()
+}
+
+/// When the event takes place, in the local time zone.
+fn when(event: &CalendarEvent) -> Option<String> {
+  let start_time = event.start.as_ref()?;
+  let start = local_date_time(start_time)?;
+  let end = event.end.as_ref().and_then(local_date_time);
+
+  if matches!(start_time, EventTime::Date(_)) {
+    // The end of a whole day event is the day after its last one.
+    let dates = match end
+      .and_then(|end| end.add_days(-1).ok())
+      .filter(|last| last.ymd() > start.ymd())
+    {
+      Some(last) => format!(
+        "{} – {}",
+        format(&start, DATE_FORMAT),
+        format(&last, DATE_FORMAT)
+      ),
+      None => format(&start, DATE_FORMAT),
+    };
+    return Some(gettext("{dates} (all day)").replace("{dates}", &dates));
+  }
+
+  let mut when = format(&start, DATE_TIME_FORMAT);
+  if let Some(end) = end {
+    let end_format = if end.ymd() == start.ymd() {
+      TIME_FORMAT
+    } else {
+      DATE_TIME_FORMAT
+    };
+    when = format!("{} – {}", when, format(&end, end_format));
+  }
+  let zone = match unknown_zone(start_time) {
+    Some(zone) => zone.to_string(),
+    None => format(&start, "%Z"),
+  };
+  Some(format!("{} {}", when, zone))
+}
@@ -25,6 +25,7 @@ use base64::engine::general_purpose;
 use base64::Engine;
 
 use crate::message::attachment::Attachment;
+use crate::{htmltext, richtext};
 
 /// The style that replaces the one of the message when the css is forced.
 /// There are two, so that forcing it on a dark desktop does not turn the
@@ -51,6 +52,30 @@ const CSS_DARK: &str = r#"
 </style>
 "#;
 
+/// Dark rendering keeps the layout of the message and only replaces its text
+/// and background colours, the #242424 of the window around it. Nothing is
+/// turned over, so images stay as they are, background images included
+/// however the message sets them. !important wins over inline styles.
+const CSS_DARK_RENDERING: &str = r#"
+<style>
+  :root {
+    color-scheme: dark;
+  }
+  html, body, body :not(img, video, picture, canvas, svg, svg *) {
+    color: #ffffff !important;
+    background-color: #242424 !important;
+    border-color: #4a4a4a !important;
+  }
+  a, a * {
+    color: #78aeed !important;
+  }
+</style>
+"#;
+
+/// Under this luminance a background is dark, and the message already fits a
+/// dark desktop.
+const DARK_LUMINANCE: f64 = 0.2;
+
 pub fn forced_css(dark: bool) -> &'static str {
   if dark {
     CSS_DARK
@@ -70,6 +95,60 @@ const CSP_ALLOW_REMOTE: &str = "default-src 'none'; style-src 'unsafe-inline' ht
                                 img-src data: http: https:; font-src http: https:; \
                                 media-src data: http: https:";
 
+/// Hosts that only serve to tell a sender when, where and how often a message
+/// is read. A subdomain of one of them counts too.
+const TRACKER_HOSTS: [&str; 14] = [
+  "google-analytics.com",
+  "doubleclick.net",
+  "list-manage.com",
+  "mailtrack.io",
+  "mandrillapp.com",
+  "sendgrid.net",
+  "exacttarget.com",
+  "hubspotemail.net",
+  "pardot.com",
+  "mixpanel.com",
+  "yesware.com",
+  "bananatag.com",
+  "getnotify.com",
+  "mailstat.us",
+];
+
+/// Query parameters a link carries only to report the click back. Names ending
+/// with a `_` are prefixes.
+const TRACKING_PARAMETERS: [&str; 14] = [
+  "utm_",
+  "fbclid",
+  "gclid",
+  "dclid",
+  "msclkid",
+  "yclid",
+  "igshid",
+  "mc_cid",
+  "mc_eid",
+  "_hsenc",
+  "_hsmi",
+  "mkt_tok",
+  "oly_anon_id",
+  "oly_enc_id",
+];
+
+/// A query value or a path segment at least this long, mixing letters and
+/// digits, is taken for an id minted for a single recipient.
+const UNIQUE_ID_MIN_LEN: usize = 20;
+
+/// Classes of the elements that hold the quoted history of a reply: Gmail,
+/// Yahoo and Thunderbird when it forwards.
+const QUOTE_CLASSES: [&str; 3] = ["gmail_quote", "yahoo_quoted", "moz-forward-container"];
+
+/// Ids of the element where Outlook starts the quoted history. It is not
+/// closed around it, everything up to the end of the body is quoted.
+const QUOTE_START_IDS: [&str; 3] = [
+  "divrplyfwdmsg",
+  "appendonsend",
+  "mail-editor-reference-message-container",
+];
+
 #[derive(Clone)]
 struct InlineImage {
   mime_type: String,
@@ -81,8 +160,13 @@ pub struct Html {
   strip_css: bool,
   allow_remote: bool,
   dark: bool,
+  dark_rendering: bool,
   inline_images: HashMap<String, InlineImage>,
   encoded: Arc<Mutex<HashMap<String, String>>>,
+  trackers: Arc<Mutex<HashSet<String>>>,
+  tracking_links: Arc<Mutex<HashSet<String>>>,
+  remote_images: Arc<Mutex<HashSet<String>>>,
+  quote_summary: Option<String>,
 }
 
 impl Html {
@@ -92,11 +176,24 @@ impl Html {
       strip_css,
       allow_remote: false,
       dark: false,
+      dark_rendering: false,
       inline_images: HashMap::new(),
       encoded: Arc::new(Mutex::new(HashMap::new())),
+      trackers: Arc::new(Mutex::new(HashSet::new())),
+      tracking_links: Arc::new(Mutex::new(HashSet::new())),
+      remote_images: Arc::new(Mutex::new(HashSet::new())),
+      quote_summary: None,
     }
   }
 
+  /// Folds the quoted history of a reply in a `<details>`, behind `summary`,
+  /// for the new content not to be buried under it. A printed page leaves it
+  /// unfolded.
+  pub fn collapse_quotes(mut self, summary: &str) -> Self {
+    self.quote_summary = Some(summary.to_string());
+    self
+  }
+
   /// Whether the message may pull content from the network, i.e. the state of
   /// the "Show remote images" button.
   pub fn allow_remote(mut self, allow_remote: bool) -> Self {
@@ -112,6 +209,19 @@ impl Html {
     self
   }
 
+  /// Renders the message in dark colours without touching its layout, unless
+  /// its own background is dark already. Has no effect when the css is
+  /// forced, the forced css has its own dark colours.
+  pub fn dark_rendering(mut self, enabled: bool) -> Self {
+    self.dark_rendering = enabled;
+    self
+  }
+
+  /// Whether the colours of the message are replaced by `safe()`.
+  pub fn is_dark_rendered(&self) -> bool {
+    self.dark_rendering && !self.strip_css && !has_dark_background(&self.body)
+  }
+
   /// The attachments a `cid:` source can point at, keyed by content id in
   /// lower case, since the scheme and the id are matched without case.
   ///
@@ -158,6 +268,24 @@ impl Html {
     self.encoded.lock().unwrap().len()
   }
 
+  /// How many remote images were taken for tracking pixels and dropped. Only
+  /// known once the body has been cleaned.
+  pub fn tracker_count(&self) -> usize {
+    self.trackers.lock().unwrap().len()
+  }
+
+  /// How many remote images the body shows, trackers left aside. Only known
+  /// once the body has been cleaned.
+  pub fn remote_image_count(&self) -> usize {
+    self.remote_images.lock().unwrap().len()
+  }
+
+  /// How many links had their tracking parameters removed. Only known once the
+  /// body has been cleaned.
+  pub fn tracking_link_count(&self) -> usize {
+    self.tracking_links.lock().unwrap().len()
+  }
+
   pub fn url_encode(content_id: &str) -> String {
     crate::glib::uri_escape_string(content_id, Some("@"), true).to_string()
   }
@@ -189,6 +317,8 @@ impl Html {
       policy,
       if self.strip_css {
         forced_css(self.dark)
+      } else if self.is_dark_rendered() {
+        CSS_DARK_RENDERING
       } else {
         ""
       },
@@ -225,7 +355,30 @@ impl Html {
 
     let inline_images = self.inline_images.clone();
     let encoded = Arc::clone(&self.encoded);
+    let hidden_images = hidden_image_sources(&self.body);
+    let trackers = Arc::clone(&self.trackers);
+    let tracking_links = Arc::clone(&self.tracking_links);
+    let remote_images = Arc::clone(&self.remote_images);
     builder.attribute_filter(move |element, attribute, value| {
+      // A tracking pixel stays out even when remote content is allowed, the
+      // policy cannot tell it from a real image.
+      if element == "img" && attribute == "src" && is_remote(value) {
+        if is_tracker(value, hidden_images.contains(value)) {
+          trackers.lock().unwrap().insert(value.to_string());
+          return None;
+        }
+        remote_images.lock().unwrap().insert(value.to_string());
+        return Some(Cow::Borrowed(value));
+      }
+      if element == "a" && attribute == "href" && is_remote(value) {
+        let stripped = strip_tracking_parameters(value);
+        if stripped != value {
+          tracking_links.lock().unwrap().insert(value.to_string());
+          return Some(Cow::Owned(stripped));
+        }
+        return Some(Cow::Borrowed(value));
+      }
+
       let Some(content_id) = content_id_of(value) else {
         return Some(Cow::Borrowed(value));
       };
@@ -251,27 +404,50 @@ impl Html {
       Some(Cow::Owned(uri.clone()))
     });
 
-    builder.clean(&self.body).to_string()
+    let body = match &self.quote_summary {
+      Some(summary) => Cow::Owned(collapse_quotes(&self.body, summary)),
+      None => Cow::Borrowed(self.body.as_str()),
+    };
+    builder.clean(&body).to_string()
   }
 
-  /// The `<li>` list of attachments for the printed page. Both the file name and
-  /// the mime type come from the message, so both are escaped.
-  fn print_attachment_list(attachments: &[Attachment]) -> String {
+  /// The `<li>` list of attachments for the printed page, each one a link to
+  /// download it when `hrefs` has its address. Both the file name and the mime
+  /// type come from the message, so both are escaped.
+  fn print_attachment_list(attachments: &[Attachment], hrefs: &[String]) -> String {
     attachments
       .iter()
-      .map(|attachment| {
+      .enumerate()
+      .map(|(index, attachment)| {
         let filename = Html::escape(&attachment.filename);
+        let name = match hrefs.get(index) {
+          Some(href) => format!(
+            "<a download=\"{}\" href=\"{}\">{filename}</a>",
+            Html::escape(&attachment.safe_filename()),
+            href.replace('&', "&amp;").replace('"', "&quot;")
+          ),
+          None => filename,
+        };
         match attachment.mime_type.as_deref() {
           Some(mime_type) if !mime_type.is_empty() => {
-            format!("<li>{filename} ({})</li>", Html::escape(mime_type))
+            format!("<li>{name} ({})</li>", Html::escape(mime_type))
           }
-          _ => format!("<li>{filename}</li>"),
+          _ => format!("<li>{name}</li>"),
         }
       })
       .collect::<Vec<_>>()
       .join("\n")
   }
 
+  /// The attachment as a `data:` address, for a page that carries it.
+  pub fn data_uri(attachment: &Attachment) -> String {
+    format!(
+      "data:{};base64,{}",
+      export_mime_type(attachment),
+      general_purpose::STANDARD.encode(&attachment.body)
+    )
+  }
+
   pub fn safe_print(
     &self,
     from: &str,
@@ -279,6 +455,20 @@ impl Html {
     date: &str,
     subject: &str,
     attachments: &[Attachment],
+  ) -> String {
+    self.safe_export(from, to, date, subject, attachments, &[])
+  }
+
+  /// The page that is printed, saved on its own: the headers, the body and
+  /// the attachments, each one a link to `hrefs` when it has one.
+  pub fn safe_export(
+    &self,
+    from: &str,
+    to: &str,
+    date: &str,
+    subject: &str,
+    attachments: &[Attachment],
+    hrefs: &[String],
   ) -> String {
     let from = Self::escape(from);
     let to = Self::escape(to);
@@ -286,7 +476,7 @@ impl Html {
     let subject = Self::escape(subject);
     let policy = self.policy();
     let content = self.clean().to_string();
-    let attachments = Self::print_attachment_list(attachments);
+    let attachments = Self::print_attachment_list(attachments, hrefs);
 
     format!(
       r#"<!doctype html>
@@ -327,6 +517,25 @@ impl Html {
   }
 }
 
+/// The declared type of an attachment when it looks like one, since it ends
+/// up in an address or a header.
+pub(crate) fn export_mime_type(attachment: &Attachment) -> &str {
+  attachment
+    .mime_type
+    .as_deref()
+    .filter(|mime_type| {
+      mime_type.split_once('/').is_some_and(|(kind, subtype)| {
+        [kind, subtype].iter().all(|part| {
+          !part.is_empty()
+            && part
+              .chars()
+              .all(|c| c.is_ascii_alphanumeric() || "!#$&-^_.+".contains(c))
+        })
+      })
+    })
+    .unwrap_or("application/octet-stream")
+}
+
 /// The part after `cid:`, if that is the scheme of `value`. Schemes are not
 /// case sensitive, so `CID:` counts too.
 fn content_id_of(value: &str) -> Option<&str> {
@@ -337,12 +546,482 @@ fn content_id_of(value: &str) -> Option<&str> {
   }
 }
 
+fn is_remote(value: &str) -> bool {
+  value.starts_with("//")
+    || crate::utils::uri_scheme(value).is_some_and(|scheme| scheme == "http" || scheme == "https")
+}
+
+/// The query of `uri` split into its `name=value` pairs, along with what comes
+/// before and after it.
+fn split_query(uri: &str) -> (&str, Vec<&str>, &str) {
+  let (uri, fragment) = match uri.find('#') {
+    Some(index) => uri.split_at(index),
+    None => (uri, ""),
+  };
+  match uri.split_once('?') {
+    Some((base, query)) => (
+      base,
+      query.split('&').filter(|pair| !pair.is_empty()).collect(),
+      fragment,
+    ),
+    None => (uri, vec![], fragment),
+  }
+}
+
+/// Whether a remote image is a tracker: served by a known tracker host, or
+/// `hidden` and carrying an id only the recipient has. An id alone tells
+/// nothing, CDNs put content hashes and versions in their addresses too.
+fn is_tracker(src: &str, hidden: bool) -> bool {
+  let uri = if src.starts_with("//") {
+    Cow::Owned(format!("https:{src}"))
+  } else {
+    Cow::Borrowed(src)
+  };
+  let tracker_host = crate::utils::link_host(&uri).is_some_and(|host| {
+    TRACKER_HOSTS
+      .iter()
+      .any(|tracker| host == *tracker || host.ends_with(&format!(".{tracker}")))
+  });
+
+  if tracker_host {
+    return true;
+  }
+  if !hidden {
+    return false;
+  }
+  let (base, query, _) = split_query(src);
+  let path = base.splitn(4, '/').nth(3).unwrap_or_default();
+  query
+    .iter()
+    .map(|pair| pair.split_once('=').map_or("", |(_, value)| value))
+    .chain(
+      path
+        .split('/')
+        .map(|segment| segment.rsplit_once('.').map_or(segment, |(name, _)| name)),
+    )
+    .any(is_unique_id)
+}
+
+fn is_unique_id(value: &str) -> bool {
+  value.len() >= UNIQUE_ID_MIN_LEN
+    && value
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '%' | '='))
+    && value.chars().any(|c| c.is_ascii_digit())
+    && value.chars().any(|c| c.is_ascii_alphabetic())
+}
+
+/// `uri` without the query parameters that only serve to track the click.
+fn strip_tracking_parameters(uri: &str) -> String {
+  let (base, query, fragment) = split_query(uri);
+  let kept: Vec<&str> = query
+    .iter()
+    .copied()
+    .filter(|pair| {
+      let name = pair
+        .split('=')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+      !TRACKING_PARAMETERS.iter().any(|parameter| {
+        if parameter.ends_with('_') {
+          name.starts_with(parameter)
+        } else {
+          name == *parameter
+        }
+      })
+    })
+    .collect();
+
+  if kept.len() == query.len() {
+    return uri.to_string();
+  }
+  if kept.is_empty() {
+    format!("{base}{fragment}")
+  } else {
+    format!("{base}?{}{fragment}", kept.join("&"))
+  }
+}
+
+/// The sources of the images the body makes too small, or hides, to be seen:
+/// the usual shape of a tracking pixel. The sanitizer looks at one attribute at
+/// a time, so the size of an image has to be known before its source is.
+fn hidden_image_sources(body: &str) -> HashSet<String> {
+  // Only ascii is lowered, the offsets in both strings are the same.
+  let lower = body.to_ascii_lowercase();
+  let mut sources = HashSet::new();
+  let mut offset = 0;
+
+  while let Some(found) = lower[offset..].find("<img") {
+    let start = offset + found + "<img".len();
+    if !lower[start..].starts_with(|c: char| c.is_ascii_whitespace() || c == '/') {
+      offset = start;
+      continue;
+    }
+    let end = tag_end(&body[start..]).map_or(body.len(), |end| start + end);
+    let attributes = tag_attributes(&body[start..end]);
+    let attribute = |name: &str| {
+      attributes
+        .iter()
+        .find(|(attribute, _)| attribute == name)
+        .map(|(_, value)| value.as_str())
+    };
+
+    if let Some(src) = attribute("src") {
+      let tiny = |size: Option<&str>| {
+        size
+          .and_then(|size| size.trim().trim_end_matches("px").parse::<f64>().ok())
+          .is_some_and(|size| size <= 1.0)
+      };
+      let width = attribute("width");
+      let height = attribute("height");
+      let style: String = attribute("style")
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_ascii_lowercase();
+
+      let hidden = (tiny(width) && tiny(height))
+        || [width, height]
+          .iter()
+          .any(|size| size.is_some_and(|size| size.trim() == "0"))
+        || style.contains("display:none")
+        || style.contains("visibility:hidden")
+        || style.contains("width:0")
+        || style.contains("height:0")
+        || (style.contains("width:1px") && style.contains("height:1px"));
+      if hidden {
+        sources.insert(src.to_string());
+      }
+    }
+    offset = end;
+  }
+  sources
+}
+
+/// Whether the first background `body` gives, on its `<body>` or on the outer
+/// table of a newsletter, is a dark colour. The sanitizer drops `<body>`, so
+/// this is read before.
+fn has_dark_background(body: &str) -> bool {
+  let lower = body.to_ascii_lowercase();
+  let mut offset = 0;
+
+  while let Some(found) = lower[offset..].find('<') {
+    let start = offset + found + 1;
+    let name_end = lower[start..]
+      .find(|c: char| !c.is_ascii_alphanumeric())
+      .map_or(lower.len(), |end| start + end);
+    let end = tag_end(&body[name_end..]).map_or(body.len(), |end| name_end + end);
+    offset = end.max(start);
+
+    if !matches!(
+      &lower[start..name_end],
+      "body" | "table" | "tbody" | "tr" | "td" | "div" | "center"
+    ) {
+      continue;
+    }
+    let attributes = tag_attributes(&body[name_end..end]);
+    let style_background = attributes
+      .iter()
+      .filter(|(name, _)| name == "style")
+      .flat_map(|(_, style)| style.split(';'))
+      .filter_map(|declaration| declaration.split_once(':'))
+      .filter(|(property, _)| {
+        matches!(
+          property.trim().to_ascii_lowercase().as_str(),
+          "background" | "background-color"
+        )
+      })
+      .find_map(|(_, value)| match value.find("rgb") {
+        Some(rgb) => luminance(value[rgb..].split_inclusive(')').next()?),
+        None => value.split_whitespace().find_map(luminance),
+      });
+    let background = style_background.or_else(|| {
+      attributes
+        .iter()
+        .find(|(name, _)| name == "bgcolor")
+        .and_then(|(_, value)| luminance(value))
+    });
+    if let Some(luminance) = background {
+      return luminance < DARK_LUMINANCE;
+    }
+  }
+  false
+}
+
+/// The relative luminance of a css colour, from 0 for black to 1 for white,
+/// written `#rgb`, `#rrggbb`, `rgb()` or as one of the names mails use most.
+fn luminance(colour: &str) -> Option<f64> {
+  let colour = colour.trim().to_ascii_lowercase();
+  let hex = |digits: &str| u8::from_str_radix(digits, 16).ok();
+  let (red, green, blue) = match colour.as_str() {
+    "black" => (0, 0, 0),
+    "white" => (255, 255, 255),
+    _ if colour.starts_with("rgb") => {
+      let values: Vec<u8> = colour
+        .split_once('(')?
+        .1
+        .trim_end_matches(')')
+        .split([',', ' ', '/'])
+        .filter(|value| !value.is_empty())
+        .take(3)
+        .map(|value| {
+          value
+            .parse::<f64>()
+            .ok()
+            .map(|value| value.clamp(0.0, 255.0) as u8)
+        })
+        .collect::<Option<_>>()?;
+      (*values.first()?, *values.get(1)?, *values.get(2)?)
+    }
+    _ => {
+      let digits = colour.strip_prefix('#').unwrap_or(&colour);
+      // Sliced by bytes below.
+      if !digits.is_ascii() {
+        return None;
+      }
+      match digits.len() {
+        3 => {
+          let short: Vec<u8> = digits
+            .chars()
+            .map(|c| hex(&c.to_string()).map(|value| value * 17))
+            .collect::<Option<_>>()?;
+          (short[0], short[1], short[2])
+        }
+        6 => (
+          hex(&digits[0..2])?,
+          hex(&digits[2..4])?,
+          hex(&digits[4..6])?,
+        ),
+        _ => return None,
+      }
+    }
+  };
+  let linear = |value: u8| {
+    let value = value as f64 / 255.0;
+    if value <= 0.03928 {
+      value / 12.92
+    } else {
+      ((value + 0.055) / 1.055).powf(2.4)
+    }
+  };
+  Some(0.2126 * linear(red) + 0.7152 * linear(green) + 0.0722 * linear(blue))
+}
+
+/// Wraps the quoted history of `body` in `<details>` elements: the quotes
+/// the mail clients mark as such, each with the line that introduces it. The
+/// markers are attributes the sanitizer drops, so this is done before.
+fn collapse_quotes(body: &str, summary: &str) -> String {
+  // Only ascii is lowered, the offsets in both strings are the same.
+  let lower = body.to_ascii_lowercase();
+  let mut quotes: Vec<(usize, usize)> = vec![];
+  let mut offset = 0;
+
+  while let Some(found) = lower[offset..].find('<') {
+    let start = offset + found;
+    offset = start + 1;
+    let Some(name) = ["blockquote", "div"].into_iter().find(|name| {
+      lower[start + 1..].starts_with(name)
+        && lower[start + 1 + name.len()..].starts_with(|c: char| c.is_ascii_whitespace())
+    }) else {
+      continue;
+    };
+    let attributes_start = start + 1 + name.len();
+    let end = tag_end(&body[attributes_start..]).map_or(body.len(), |end| attributes_start + end);
+    let attributes = tag_attributes(&body[attributes_start..end]);
+    let attribute = |wanted: &str| {
+      attributes
+        .iter()
+        .find(|(name, _)| name == wanted)
+        .map(|(_, value)| value.to_ascii_lowercase())
+        .unwrap_or_default()
+    };
+
+    let quote_end = if QUOTE_START_IDS.contains(&attribute("id").as_str()) {
+      lower.rfind("</body").unwrap_or(body.len()).max(start)
+    } else if (name == "blockquote" && attribute("type") == "cite")
+      || attribute("class")
+        .split_whitespace()
+        .any(|class| QUOTE_CLASSES.contains(&class))
+    {
+      element_end(&lower, name, end)
+    } else {
+      continue;
+    };
+    let floor = quotes.last().map_or(0, |(_, end)| *end);
+    quotes.push((attribution_start(body, &lower, floor, start), quote_end));
+    // A quote in a quote goes with the outer one.
+    offset = quote_end.max(offset);
+  }
+
+  let mut collapsed = String::with_capacity(body.len());
+  let mut copied = 0;
+  for (start, end) in quotes {
+    collapsed.push_str(&body[copied..start]);
+    collapsed.push_str(&format!(
+      "<details><summary>{}</summary>{}</details>",
+      Html::escape(summary),
+      &body[start..end]
+    ));
+    copied = end;
+  }
+  collapsed.push_str(&body[copied..]);
+  collapsed
+}
+
+/// The offset just after the tag closing the `name` element whose opening
+/// tag ends at `offset`, counting the elements of the same name it holds.
+fn element_end(lower: &str, name: &str, offset: usize) -> usize {
+  let open = format!("<{}", name);
+  let close = format!("</{}", name);
+  let mut depth = 1;
+  let mut offset = offset;
+  loop {
+    let next_open = lower[offset..].find(&open).map(|found| offset + found);
+    let Some(next_close) = lower[offset..].find(&close).map(|found| offset + found) else {
+      return lower.len();
+    };
+    match next_open {
+      Some(next_open) if next_open < next_close => {
+        depth += 1;
+        offset = next_open + open.len();
+      }
+      _ => {
+        depth -= 1;
+        offset = next_close + close.len();
+        if depth == 0 {
+          return lower[offset..]
+            .find('>')
+            .map_or(lower.len(), |end| offset + end + 1);
+        }
+      }
+    }
+  }
+}
+
+/// Where the line introducing the quote that starts at `start` begins, if it
+/// is right before the quote, otherwise `start`. The line is taken from the
+/// end of the previous block, and never before `floor`, where the previous
+/// quote ends.
+fn attribution_start(body: &str, lower: &str, floor: usize, start: usize) -> usize {
+  let mut boundary = start;
+  // The boundaries right before the quote may close the line itself.
+  for _ in 0..4 {
+    let previous = ["<br", "</div", "</p", "</blockquote", "</table", "<body"]
+      .iter()
+      .filter_map(|tag| {
+        let found = floor + lower[floor..boundary].rfind(tag)?;
+        lower[found..boundary]
+          .find('>')
+          .map(|end| (found, found + end + 1))
+      })
+      .max_by_key(|(_, end)| *end)
+      .unwrap_or((floor, floor));
+    let text = htmltext::to_text(&body[previous.1..start]);
+    let text = text.trim();
+    if text.is_empty() {
+      if previous.0 == floor {
+        return start;
+      }
+      boundary = previous.0;
+      continue;
+    }
+    let attribution = text.lines().count() <= 2 && richtext::is_attribution(text);
+    return if attribution { previous.1 } else { start };
+  }
+  start
+}
+
+/// The address and the text of each link of a page, the text as it reads.
+/// WebKit only tells where a followed link leads, not what it showed.
+pub fn link_labels(html: &str) -> Vec<(String, String)> {
+  let lower = html.to_ascii_lowercase();
+  let mut links = vec![];
+  let mut offset = 0;
+  while let Some(found) = lower[offset..].find("<a") {
+    let start = offset + found + 2;
+    offset = start;
+    if !lower[start..].starts_with(|c: char| c.is_ascii_whitespace()) {
+      continue;
+    }
+    let Some(end) = tag_end(&html[start..]).map(|end| start + end) else {
+      break;
+    };
+    offset = end;
+    let Some((_, href)) = tag_attributes(&html[start..end])
+      .into_iter()
+      .find(|(name, _)| name == "href")
+    else {
+      continue;
+    };
+    let text_end = lower[end..]
+      .find("</a")
+      .map_or(lower.len(), |close| end + close);
+    let text = htmltext::to_text(&html[end + 1..text_end]);
+    links.push((
+      href.trim().to_string(),
+      text.split_whitespace().collect::<Vec<_>>().join(" "),
+    ));
+  }
+  links
+}
+
+/// The offset of the `>` closing a tag, skipping over quoted values.
+pub(crate) fn tag_end(tag: &str) -> Option<usize> {
+  let mut quote: Option<char> = None;
+  for (index, c) in tag.char_indices() {
+    match (quote, c) {
+      (Some(q), c) if c == q => quote = None,
+      (Some(_), _) => (),
+      (None, '"' | '\'') => quote = Some(c),
+      (None, '>') => return Some(index),
+      _ => (),
+    }
+  }
+  None
+}
+
+/// The attributes of a tag, names in lower case, with the `&amp;` the parser
+/// would decode already decoded.
+pub(crate) fn tag_attributes(tag: &str) -> Vec<(String, String)> {
+  let mut attributes = vec![];
+  let mut chars = tag.chars().peekable();
+
+  loop {
+    while chars.next_if(|c| c.is_whitespace() || *c == '/').is_some() {}
+    let name: String =
+      std::iter::from_fn(|| chars.next_if(|c| !c.is_whitespace() && !matches!(c, '=' | '/' | '>')))
+        .collect();
+    if name.is_empty() {
+      break;
+    }
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+    let mut value = String::new();
+    if chars.next_if_eq(&'=').is_some() {
+      while chars.next_if(|c| c.is_whitespace()).is_some() {}
+      match chars.next_if(|c| *c == '"' || *c == '\'') {
+        Some(quote) => {
+          value.extend(std::iter::from_fn(|| chars.next_if(|c| *c != quote)));
+          chars.next();
+        }
+        None => value.extend(std::iter::from_fn(|| {
+          chars.next_if(|c| !c.is_whitespace() && *c != '>')
+        })),
+      }
+    }
+    attributes.push((name.to_ascii_lowercase(), value.replace("&amp;", "&")));
+  }
+  attributes
+}
+
 #[cfg(test)]
 mod tests {
   use std::error::Error;
   use std::fs;
 
-  use crate::html::Html;
+  use crate::html::{collapse_quotes, has_dark_background, link_labels, luminance, Html};
   use crate::message::message::{Message, MessageParser};
   use crate::{gio, utils};
 
@@ -465,6 +1144,87 @@ mod tests {
     assert!(!body.contains("http://"));
   }
 
+  #[test]
+  fn a_tracking_pixel_stays_blocked_when_remote_content_is_allowed() {
+    let html = Html::new(
+      "<img src=\"https://news.example/o.gif?m=8f14e45fceea167a5a36\" width=\"1\" height=\"1\">\
+       <img src=\"https://news.example/logo.png\" width=\"120\" height=\"40\">",
+      false,
+    )
+    .allow_remote(true);
+    let body = html.safe();
+
+    assert!(!body.contains("o.gif"));
+    assert!(body.contains("https://news.example/logo.png"));
+    assert_eq!(html.tracker_count(), 1);
+    assert_eq!(html.remote_image_count(), 1);
+  }
+
+  #[test]
+  fn hidden_and_known_trackers_are_blocked() {
+    let html = Html::new(
+      "<img style=\"display: none\" src='https://news.example/o/c81e728d9d4c2f636f06.gif'>\
+       <img src=\"https://www.google-analytics.com/collect?v=1&amp;t=event\">\
+       <img src=\"//news.example/open.gif?u=8f14e45fceea167a5a36dedd4bea2543\" width=\"0\">\
+       <img src=\"https://news.example/banner.png?v=2\">",
+      false,
+    )
+    .allow_remote(true);
+    let body = html.safe();
+
+    assert_eq!(html.tracker_count(), 3);
+    assert!(!body.contains("c81e728d9d4c2f636f06"));
+    assert!(!body.contains("google-analytics"));
+    assert!(!body.contains("open.gif"));
+    assert!(body.contains("banner.png?v=2"));
+  }
+
+  #[test]
+  fn an_id_alone_does_not_make_a_tracker() {
+    // Content hashes and cache busting versions, on images shown as they are.
+    let html = Html::new(
+      "<img src=\"https://cdn.example/files/hero-3f9a1c2b7d8e4f60a1b2.jpg?v=1614300000a1b2c3\">\
+       <img src=\"https://news.example/logo.png?u=8f14e45fceea167a5a36dedd4bea2543\">\
+       <img src=\"https://news.example/spacer.gif\" width=\"1\" height=\"1\">",
+      false,
+    )
+    .allow_remote(true);
+    let body = html.safe();
+
+    assert_eq!(html.tracker_count(), 0);
+    assert_eq!(html.remote_image_count(), 3);
+    assert!(body.contains("hero-3f9a1c2b7d8e4f60a1b2.jpg"));
+  }
+
+  #[test]
+  fn tracking_parameters_are_stripped_from_links() {
+    let html = Html::new(
+      "<a href=\"https://shop.example/item?id=3&amp;utm_source=news&amp;fbclid=abc#top\">a</a>\
+       <a href=\"https://shop.example/?utm_medium=mail\">b</a>\
+       <a href=\"https://shop.example/plain?id=4\">c</a>",
+      false,
+    );
+    let body = html.safe();
+
+    assert!(body.contains("href=\"https://shop.example/item?id=3#top\""));
+    assert!(body.contains("href=\"https://shop.example/\""));
+    assert!(body.contains("href=\"https://shop.example/plain?id=4\""));
+    assert!(!body.contains("utm_"));
+    assert_eq!(html.tracking_link_count(), 2);
+  }
+
+  #[test]
+  fn image_attributes_are_read_from_the_raw_body() {
+    let sources = super::hidden_image_sources(
+      "<IMG SRC=\"https://x.example/a?b=1&amp;c=2\" WIDTH=1 HEIGHT=\"1px\" alt=\"a > b\">\
+       <img src=\"https://x.example/big.png\" width=\"1\" height=\"300\">\
+       <imgfoo src=\"https://x.example/no\" width=0>",
+    );
+
+    assert_eq!(sources.len(), 1);
+    assert!(sources.contains("https://x.example/a?b=1&c=2"));
+  }
+
   #[test]
   fn a_javascript_link_does_not_survive() {
     let body = Html::new("<a href=\"javascript:alert(1)\">x</a>", false).safe();
@@ -595,13 +1355,16 @@ mod tests {
 
   #[test]
   fn print_attachment_list_escapes_both_fields() {
-    let list = Html::print_attachment_list(&[
-      attachment("Deus_Gnome.png", Some("image/png")),
-      attachment("a&b<c>.txt", Some("text/plain")),
-      attachment("report.pdf", Some("application/pdf\"><img src=x>")),
-      attachment("no-mime.bin", None),
-      attachment("empty-mime.bin", Some("")),
-    ]);
+    let list = Html::print_attachment_list(
+      &[
+        attachment("Deus_Gnome.png", Some("image/png")),
+        attachment("a&b<c>.txt", Some("text/plain")),
+        attachment("report.pdf", Some("application/pdf\"><img src=x>")),
+        attachment("no-mime.bin", None),
+        attachment("empty-mime.bin", Some("")),
+      ],
+      &[],
+    );
 
     assert_eq!(
       list,
@@ -646,6 +1409,34 @@ mod tests {
     assert_eq!(page.matches("Content-Security-Policy").count(), 1);
   }
 
+  #[test]
+  fn exported_page_links_attachments() {
+    let mut pdf = attachment("../report.pdf", Some("application/pdf"));
+    pdf.body = b"%PDF".to_vec();
+    let mut notes = attachment("notes.txt", Some("text/plain\"><script>"));
+    notes.body = b"hi".to_vec();
+    let attachments = [pdf, notes];
+    let hrefs: Vec<String> = attachments.iter().map(Html::data_uri).collect();
+    let page = Html::new("<p>hi</p>", false).safe_export(
+      "from",
+      "to",
+      "date",
+      "subject",
+      &attachments,
+      &hrefs,
+    );
+
+    assert!(page.contains(
+      "<a download=\"report.pdf\" href=\"data:application/pdf;base64,JVBERg==\">\
+       ..&#47;report.pdf</a>"
+    ));
+    assert!(page.contains("href=\"data:application/octet-stream;base64,aGk=\""));
+    assert!(!page.contains("<script>"));
+    assert!(!Html::new("<p>hi</p>", false)
+      .safe_print("from", "to", "date", "subject", &attachments)
+      .contains("<a download"));
+  }
+
 
   // RFC 2392 -> Errata 454
   // https://errata.rfc-editor.org/search/?rfc_number=2392&presentation=records
@@ -670,4 +1461,111 @@ mod tests {
 
     assert!(body.contains("<img src=\"data:image/png;base64,"));
   }
+
+  #[test]
+  fn collapse_cited_quotes() {
+    let body = "<p>Sure.</p><div class=\"moz-cite-prefix\">On Wed, John Doe wrote:<br>\
+                </div><blockquote type=\"cite\">Coming?<blockquote type=\"cite\">Dinner\
+                </blockquote></blockquote><p>See you</p>";
+    assert_eq!(
+      collapse_quotes(body, "Quoted <text>"),
+      "<p>Sure.</p><details><summary>Quoted&#32;&lt;text&gt;</summary><div \
+       class=\"moz-cite-prefix\">On Wed, John Doe wrote:<br></div><blockquote \
+       type=\"cite\">Coming?<blockquote type=\"cite\">Dinner</blockquote></blockquote>\
+       </details><p>See you</p>"
+    );
+  }
+
+  #[test]
+  fn collapse_client_quotes() {
+    let gmail = "<div dir=\"ltr\">New</div><br><div class=\"gmail_quote\"><div \
+                 class=\"gmail_attr\">On Wed, John wrote:<br></div><blockquote \
+                 class=\"gmail_quote\">Old</blockquote></div>";
+    assert_eq!(
+      collapse_quotes(gmail, "Quoted"),
+      "<div dir=\"ltr\">New</div><br><details><summary>Quoted</summary><div \
+       class=\"gmail_quote\"><div class=\"gmail_attr\">On Wed, John wrote:<br></div><blockquote \
+       class=\"gmail_quote\">Old</blockquote></div></details>"
+    );
+
+    let outlook = "<body><p>Hi</p><hr><div id=\"divRplyFwdMsg\"><b>From:</b> John</div>\
+                   <div>Old</div></body>";
+    assert_eq!(
+      collapse_quotes(outlook, "Quoted"),
+      "<body><p>Hi</p><hr><details><summary>Quoted</summary><div \
+       id=\"divRplyFwdMsg\"><b>From:</b> John</div><div>Old</div></details></body>"
+    );
+
+    let plain = "<p>Nothing <blockquote>quoted</blockquote></p>";
+    assert_eq!(collapse_quotes(plain, "Quoted"), plain);
+  }
+
+  #[test]
+  fn links_and_their_text() {
+    let page = "<p><a href=\"https://evil.example/?a=1&amp;b=2\" title=\">\">\
+                <b>www.bank.com</b>\n login</a> <a name=\"top\">top</a> <abbr>x</abbr> \
+                <A HREF='mailto:john@moon.space'>John</A></p>";
+    assert_eq!(link_labels(page), [
+      (
+        "https://evil.example/?a=1&b=2".to_string(),
+        "www.bank.com login".to_string()
+      ),
+      ("mailto:john@moon.space".to_string(), "John".to_string()),
+    ]);
+  }
+
+  #[test]
+  fn consecutive_quotes_do_not_overlap() {
+    let body = "<blockquote type=\"cite\">On Mon, X wrote:</blockquote><blockquote \
+                type=\"cite\">b</blockquote>";
+    assert_eq!(
+      collapse_quotes(body, "Quoted"),
+      "<details><summary>Quoted</summary><blockquote type=\"cite\">On Mon, X \
+       wrote:</blockquote></details><details><summary>Quoted</summary><blockquote \
+       type=\"cite\">b</blockquote></details>"
+    );
+  }
+
+  #[test]
+  fn quotes_are_collapsed_when_asked() {
+    let body = "<p>New</p><blockquote type=\"cite\">Old</blockquote>";
+    let collapsed = Html::new(body, false).collapse_quotes("Quoted").safe();
+    assert!(collapsed.contains("<details><summary>Quoted</summary><blockquote>Old</blockquote>"));
+    assert!(!Html::new(body, false).safe().contains("<details>"));
+  }
+
+  #[test]
+  fn dark_rendering() {
+    let body = "<table bgcolor=\"#FFF\"><tr><td><img src=\"data:,\">Hi</td></tr></table>";
+    assert!(Html::new(body, false)
+      .dark_rendering(true)
+      .safe()
+      .contains("color-scheme: dark"));
+    assert!(!Html::new(body, false).safe().contains("color-scheme"));
+    assert!(!Html::new(body, true)
+      .dark_rendering(true)
+      .safe()
+      .contains("color-scheme"));
+  }
+
+  #[test]
+  fn dark_backgrounds_are_kept() {
+    assert!(has_dark_background("<body bgcolor=#111>Hi</body>"));
+    assert!(has_dark_background(
+      "<div style=\"color: white; background: rgb(20, 20, 30) url(a.png)\">Hi</div>"
+    ));
+    assert!(has_dark_background(
+      "<p>Hi</p><table style=\"background-color:#1e1e1e !important\">"
+    ));
+    assert!(!has_dark_background(
+      "<body><table bgcolor=\"#f4f4f4\"><td bgcolor=black>"
+    ));
+    assert!(!has_dark_background(
+      "<p style=\"background: black\">Hi</p>"
+    ));
+    assert!(!has_dark_background("<div>Hi</div>"));
+    assert_eq!(luminance("nonsense"), None);
+    assert_eq!(luminance("1é234"), None);
+    assert!(!has_dark_background("<body bgcolor=\"1é234\">Hi</body>"));
+  }
 }
@@ -0,0 +1,712 @@
+/* htmltext.rs
+ *
+ * Copyright 2024 Alexandre Del Bigio
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+use std::borrow::Cow;
+
+use crate::html::{tag_attributes, tag_end};
+
+/// Tags whose content is not part of the text.
+const SKIPPED: [&str; 4] = ["head", "script", "style", "title"];
+
+/// Tags that start and end on a line of their own.
+const BLOCKS: [&str; 20] = [
+  "address",
+  "article",
+  "aside",
+  "caption",
+  "center",
+  "dd",
+  "div",
+  "dl",
+  "dt",
+  "fieldset",
+  "figure",
+  "footer",
+  "form",
+  "header",
+  "li",
+  "main",
+  "nav",
+  "section",
+  "table",
+  "tr",
+];
+
+/// Tags that are set apart from the text around by an empty line.
+const PARAGRAPHS: [&str; 9] = ["p", "h1", "h2", "h3", "h4", "h5", "h6", "blockquote", "pre"];
+
+/// Table cells longer than this are not lined up in columns: such a table
+/// lays the message out rather than holds data.
+const MAX_CELL_WIDTH: usize = 40;
+
+/// Renders the body of a message that only has an html part as plain text.
+/// Links become numbered footnotes, lists are indented, the cells of a table
+/// are lined up in columns and quotes keep their `>` levels.
+pub fn to_text(html: &str) -> String {
+  let mut renderer = Renderer::default();
+  renderer.render(html);
+  renderer.finish()
+}
+
+/// Where the text goes: the body, or a table cell rendered on its own, to be
+/// lined up with the others once the table is complete.
+#[derive(Default)]
+struct Frame {
+  text: String,
+  /// A space is due before the next word.
+  space: bool,
+  /// Line breaks are due before the next word.
+  breaks: usize,
+  quote: usize,
+  /// The quote level of the last line written, an empty line between two
+  /// levels belongs to the outer one.
+  line_quote: usize,
+  /// The open lists, with the number of the next item of an ordered one.
+  lists: Vec<Option<usize>>,
+  /// The marker of a list item, written with its first word.
+  bullet: Option<String>,
+}
+
+impl Frame {
+  fn prefix(&self) -> String {
+    let mut prefix = "> ".repeat(self.quote);
+    prefix.push_str(&"  ".repeat(self.lists.len()));
+    if let Some(bullet) = &self.bullet {
+      // The marker takes the place of the indent of its level.
+      prefix.truncate(prefix.len().saturating_sub(2));
+      prefix.push_str(bullet);
+    }
+    prefix
+  }
+
+  /// Writes what is due before new content: the line breaks and the prefix of
+  /// the line, or the space between two words.
+  fn start(&mut self) {
+    if self.breaks > 0 || self.text.is_empty() {
+      if !self.text.is_empty() {
+        for _ in 1..self.breaks {
+          self.text.push('\n');
+          self
+            .text
+            .push_str(&"> ".repeat(self.quote.min(self.line_quote)));
+        }
+        self.text.push('\n');
+      }
+      let prefix = self.prefix();
+      self.text.push_str(&prefix);
+      self.line_quote = self.quote;
+      self.bullet = None;
+      self.breaks = 0;
+    } else if self.space {
+      self.text.push(' ');
+    }
+    self.space = false;
+  }
+
+  /// Text whose white space only separates words. A no-break space is not
+  /// white space there.
+  fn write(&mut self, text: &str) {
+    if text.starts_with(|c: char| c.is_ascii_whitespace()) {
+      self.space = true;
+    }
+    for word in text.split_ascii_whitespace() {
+      self.start();
+      self.text.push_str(word);
+      self.space = true;
+    }
+    if !text.ends_with(|c: char| c.is_ascii_whitespace()) {
+      self.space = false;
+    }
+  }
+
+  /// Text whose lines are kept as they are.
+  fn write_lines(&mut self, text: &str) {
+    for (index, line) in text.lines().enumerate() {
+      if index > 0 {
+        self.breaks = self.breaks.max(1);
+      }
+      self.start();
+      self.text.push_str(line);
+    }
+    if text.ends_with('\n') {
+      self.breaks = self.breaks.max(1);
+    }
+  }
+
+  /// Text stuck to the previous word, a footnote reference.
+  fn append(&mut self, text: &str) {
+    let space = self.space;
+    self.space = false;
+    self.start();
+    self.text.push_str(text);
+    self.space = space;
+  }
+
+  fn block(&mut self, breaks: usize) {
+    self.breaks = self.breaks.max(breaks);
+    self.space = false;
+  }
+
+  fn line_break(&mut self) {
+    self.breaks += 1;
+    self.space = false;
+  }
+
+  /// The text without the spaces that end its lines, nor more than one empty
+  /// line in a row.
+  fn finish(self) -> String {
+    let mut text = String::with_capacity(self.text.len());
+    let mut empty = 0;
+    for line in self.text.lines() {
+      let line = line.trim_end();
+      if line.trim_start_matches(['>', ' ']).is_empty() {
+        empty += 1;
+        if empty > 1 || text.is_empty() {
+          continue;
+        }
+      } else {
+        empty = 0;
+      }
+      text.push_str(line);
+      text.push('\n');
+    }
+    text.trim_end().to_string()
+  }
+}
+
+#[derive(Default)]
+struct Table {
+  rows: Vec<Vec<String>>,
+  /// A cell is being rendered, in the frame on top.
+  in_cell: bool,
+}
+
+#[derive(Default)]
+struct Renderer {
+  /// The body, then a frame per open table cell.
+  frames: Vec<Frame>,
+  tables: Vec<Table>,
+  /// The open links, with where their text starts.
+  links: Vec<Option<(String, usize)>>,
+  footnotes: Vec<String>,
+  pre: usize,
+}
+
+impl Renderer {
+  fn frame(&mut self) -> &mut Frame {
+    if self.frames.is_empty() {
+      self.frames.push(Frame::default());
+    }
+    self.frames.last_mut().unwrap()
+  }
+
+  fn render(&mut self, html: &str) {
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+      self.text(&rest[..start]);
+      rest = &rest[start..];
+
+      if rest.starts_with("<!--") {
+        rest = rest.find("-->").map_or("", |end| &rest[end + 3..]);
+        continue;
+      }
+      let closing = rest[1..].starts_with('/');
+      let name_start = if closing { 2 } else { 1 };
+      let name: String = rest[name_start..]
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_ascii_lowercase();
+      if name.is_empty() && !rest[1..].starts_with('!') {
+        // A lone `<`, as text.
+        self.text("<");
+        rest = &rest[1..];
+        continue;
+      }
+
+      let end = tag_end(rest).unwrap_or(rest.len());
+      let attributes = &rest[(name_start + name.len()).min(end)..end];
+      rest = &rest[(end + 1).min(rest.len())..];
+      if closing {
+        self.close(&name);
+      } else if SKIPPED.contains(&name.as_str()) {
+        let lower = rest.to_ascii_lowercase();
+        rest = lower
+          .find(&format!("</{}", name))
+          .map_or("", |end| &rest[end..]);
+      } else {
+        self.open(&name, attributes);
+      }
+    }
+    self.text(rest);
+  }
+
+  fn text(&mut self, text: &str) {
+    if text.is_empty() {
+      return;
+    }
+    let text = decode_entities(text);
+    if self.pre > 0 {
+      self.frame().write_lines(&text);
+    } else {
+      self.frame().write(&text);
+    }
+  }
+
+  fn open(&mut self, name: &str, attributes: &str) {
+    let attribute = |wanted: &str| {
+      tag_attributes(attributes)
+        .into_iter()
+        .find(|(name, _)| name == wanted)
+        .map(|(_, value)| value)
+    };
+    match name {
+      "br" => self.frame().line_break(),
+      "hr" => {
+        let frame = self.frame();
+        frame.block(1);
+        frame.write("-----");
+        frame.block(1);
+      }
+      "blockquote" => {
+        let frame = self.frame();
+        frame.block(2);
+        frame.quote += 1;
+      }
+      "pre" => {
+        self.frame().block(2);
+        self.pre += 1;
+      }
+      "ul" | "ol" => {
+        let frame = self.frame();
+        frame.block(if frame.lists.is_empty() { 2 } else { 1 });
+        let start = attribute("start").and_then(|start| start.trim().parse().ok());
+        frame
+          .lists
+          .push((name == "ol").then_some(start.unwrap_or(1)));
+      }
+      "li" => {
+        let frame = self.frame();
+        frame.block(1);
+        let bullet = match frame.lists.last_mut() {
+          Some(Some(number)) => {
+            *number += 1;
+            format!("{}. ", *number - 1)
+          }
+          _ => "* ".to_string(),
+        };
+        if frame.lists.is_empty() {
+          frame.lists.push(None);
+        }
+        frame.bullet = Some(bullet);
+      }
+      "a" => {
+        let href = attribute("href")
+          .map(|href| href.trim().to_string())
+          .filter(|href| !href.is_empty() && !href.starts_with('#'));
+        let frame = self.frame();
+        let start = frame.text.len();
+        self.links.push(href.map(|href| (href, start)));
+      }
+      "img" => {
+        if let Some(alt) = attribute("alt").filter(|alt| !alt.trim().is_empty()) {
+          self.frame().write(&format!(" [{}] ", alt.trim()));
+        }
+      }
+      "table" => {
+        self.frame().block(1);
+        self.tables.push(Table::default());
+      }
+      "tr" => {
+        self.close_cell();
+        if let Some(table) = self.tables.last_mut() {
+          table.rows.push(vec![]);
+        }
+      }
+      "td" | "th" => {
+        self.close_cell();
+        if let Some(table) = self.tables.last_mut() {
+          if table.rows.is_empty() {
+            table.rows.push(vec![]);
+          }
+          table.in_cell = true;
+          self.frames.push(Frame::default());
+        }
+      }
+      name if PARAGRAPHS.contains(&name) => self.frame().block(2),
+      name if BLOCKS.contains(&name) => self.frame().block(1),
+      _ => (),
+    }
+  }
+
+  fn close(&mut self, name: &str) {
+    match name {
+      "blockquote" => {
+        let frame = self.frame();
+        frame.block(2);
+        frame.quote = frame.quote.saturating_sub(1);
+      }
+      "pre" => {
+        self.frame().block(2);
+        self.pre = self.pre.saturating_sub(1);
+      }
+      "ul" | "ol" => {
+        let frame = self.frame();
+        frame.lists.pop();
+        frame.bullet = None;
+        frame.block(if frame.lists.is_empty() { 2 } else { 1 });
+      }
+      // The marker of an empty item is not carried over to what follows.
+      "li" => {
+        let frame = self.frame();
+        frame.bullet = None;
+        frame.block(1);
+      }
+      "a" => self.close_link(),
+      "td" | "th" => self.close_cell(),
+      "table" => self.close_table(),
+      name if PARAGRAPHS.contains(&name) => self.frame().block(2),
+      name if BLOCKS.contains(&name) => self.frame().block(1),
+      _ => (),
+    }
+  }
+
+  /// Refers to the target of the link from its text, unless the text already
+  /// is the target.
+  fn close_link(&mut self) {
+    let Some(Some((href, start))) = self.links.pop() else {
+      return;
+    };
+    let frame = self.frame();
+    let text = frame.text.get(start..).unwrap_or_default().trim();
+    let target = href.strip_prefix("mailto:").unwrap_or(&href);
+    if text.is_empty()
+      || text == target
+      || text.trim_end_matches('/') == target.trim_end_matches('/')
+    {
+      return;
+    }
+    let number = match self.footnotes.iter().position(|footnote| *footnote == href) {
+      Some(index) => index + 1,
+      None => {
+        self.footnotes.push(href);
+        self.footnotes.len()
+      }
+    };
+    self.frame().append(&format!("[{}]", number));
+  }
+
+  fn close_cell(&mut self) {
+    let Some(table) = self.tables.last_mut() else {
+      return;
+    };
+    if !table.in_cell {
+      return;
+    }
+    table.in_cell = false;
+    let cell = self.frames.pop().unwrap_or_default().finish();
+    if let Some(row) = table.rows.last_mut() {
+      row.push(cell);
+    }
+  }
+
+  /// Lines the cells up in columns when they are short enough, otherwise
+  /// writes them one after the other.
+  fn close_table(&mut self) {
+    self.close_cell();
+    let Some(table) = self.tables.pop() else {
+      return;
+    };
+    let rows: Vec<Vec<String>> = table
+      .rows
+      .into_iter()
+      .filter(|row| row.iter().any(|cell| !cell.is_empty()))
+      .collect();
+    let tabular = rows
+      .iter()
+      .flatten()
+      .all(|cell| !cell.contains('\n') && cell.chars().count() <= MAX_CELL_WIDTH);
+
+    let frame = self.frame();
+    frame.block(1);
+    if tabular {
+      let mut widths: Vec<usize> = vec![];
+      for row in &rows {
+        for (column, cell) in row.iter().enumerate() {
+          let width = cell.chars().count();
+          match widths.get_mut(column) {
+            Some(max) => *max = (*max).max(width),
+            None => widths.push(width),
+          }
+        }
+      }
+      for row in &rows {
+        let line: Vec<String> = row
+          .iter()
+          .enumerate()
+          .map(|(column, cell)| format!("{:width$}", cell, width = widths[column]))
+          .collect();
+        frame.block(1);
+        frame.write_lines(line.join(" | ").trim_end());
+      }
+    } else {
+      for cell in rows.iter().flatten().filter(|cell| !cell.is_empty()) {
+        frame.block(1);
+        frame.write_lines(cell);
+      }
+    }
+    frame.block(1);
+  }
+
+  fn finish(mut self) -> String {
+    // Tables left open by a truncated message.
+    while !self.tables.is_empty() {
+      self.close_table();
+    }
+    let mut text = self.frames.drain(..).next().unwrap_or_default().finish();
+    if !self.footnotes.is_empty() {
+      text.push_str("\n\n");
+      let footnotes: Vec<String> = self
+        .footnotes
+        .iter()
+        .enumerate()
+        .map(|(index, footnote)| format!("[{}] {}", index + 1, footnote))
+        .collect();
+      text.push_str(&footnotes.join("\n"));
+    }
+    text
+  }
+}
+
+/// Replaces the character references of `text` with the characters they
+/// stand for. Only the names mail is commonly written with are known.
+fn decode_entities(text: &str) -> Cow<'_, str> {
+  if !text.contains('&') {
+    return Cow::Borrowed(text);
+  }
+  let mut decoded = String::with_capacity(text.len());
+  let mut rest = text;
+  while let Some(start) = rest.find('&') {
+    decoded.push_str(&rest[..start]);
+    rest = &rest[start..];
+    let reference = rest[1..]
+      .find(';')
+      .filter(|end| *end <= 10)
+      .map(|end| &rest[1..end + 1]);
+    let character = reference.and_then(|reference| match reference.strip_prefix('#') {
+      Some(number) => match number.strip_prefix(['x', 'X']) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => number.parse().ok(),
+      }
+      .and_then(char::from_u32),
+      None => named_entity(reference),
+    });
+    match (reference, character) {
+      (Some(reference), Some(character)) => {
+        decoded.push(character);
+        rest = &rest[reference.len() + 2..];
+      }
+      _ => {
+        decoded.push('&');
+        rest = &rest[1..];
+      }
+    }
+  }
+  decoded.push_str(rest);
+  Cow::Owned(decoded)
+}
+
+fn named_entity(name: &str) -> Option<char> {
+  let character = match name {
+    "amp" => '&',
+    "lt" => '<',
+    "gt" => '>',
+    "quot" => '"',
+    "apos" => '\'',
+    "nbsp" => '\u{a0}',
+    "shy" | "zwnj" | "zwj" => '\u{200c}',
+    "copy" => '©',
+    "reg" => '®',
+    "trade" => '™',
+    "euro" => '€',
+    "pound" => '£',
+    "deg" => '°',
+    "middot" => '·',
+    "bull" => '•',
+    "hellip" => '…',
+    "ndash" => '–',
+    "mdash" => '—',
+    "lsquo" => '‘',
+    "rsquo" => '’',
+    "ldquo" => '“',
+    "rdquo" => '”',
+    "laquo" => '«',
+    "raquo" => '»',
+    _ => return accented_letter(name),
+  };
+  Some(character)
+}
+
+/// The accented letters of Latin-1, `&eacute;` or `&Ccedil;`, which French,
+/// German or Spanish mails are full of.
+fn accented_letter(name: &str) -> Option<char> {
+  const LETTERS: [(&str, char); 30] = [
+    ("aacute", 'á'),
+    ("acirc", 'â'),
+    ("aelig", 'æ'),
+    ("agrave", 'à'),
+    ("aring", 'å'),
+    ("atilde", 'ã'),
+    ("auml", 'ä'),
+    ("ccedil", 'ç'),
+    ("eacute", 'é'),
+    ("ecirc", 'ê'),
+    ("egrave", 'è'),
+    ("euml", 'ë'),
+    ("iacute", 'í'),
+    ("icirc", 'î'),
+    ("igrave", 'ì'),
+    ("iuml", 'ï'),
+    ("ntilde", 'ñ'),
+    ("oacute", 'ó'),
+    ("ocirc", 'ô'),
+    ("ograve", 'ò'),
+    ("oslash", 'ø'),
+    ("otilde", 'õ'),
+    ("ouml", 'ö'),
+    ("szlig", 'ß'),
+    ("uacute", 'ú'),
+    ("ucirc", 'û'),
+    ("ugrave", 'ù'),
+    ("uuml", 'ü'),
+    ("yacute", 'ý'),
+    ("yuml", 'ÿ'),
+  ];
+  let (_, letter) = LETTERS
+    .iter()
+    .find(|(entity, _)| entity.eq_ignore_ascii_case(name))?;
+  if name.starts_with(|c: char| c.is_ascii_lowercase()) {
+    return Some(*letter);
+  }
+  // ß has no capital entity, it would become "SS".
+  let mut upper = letter.to_uppercase();
+  (upper.len() == 1).then(|| upper.next()).flatten()
+}
+
+#[cfg(test)]
+mod tests {
+  use std::error::Error;
+  use std::fs;
+
+  use crate::htmltext::*;
+
+  #[test]
+  fn paragraphs_and_entities() {
+    assert_eq!(
+      to_text(
+        "<html><head><title>Hi</title><style>p { color: red }</style></head>\
+         <body><p>Hello&nbsp;Lucas,</p>\n<p>Tom &amp; Jerry\n  say   hi<br>twice&#33;</p>\
+         <!-- <p>hidden</p> --><script>alert(1)</script></body></html>"
+      ),
+      "Hello\u{a0}Lucas,\n\nTom & Jerry say hi\ntwice!"
+    );
+    assert_eq!(
+      to_text("Caf&eacute; &Agrave; &AElig;&szlig; &Szlig;"),
+      "Café À Æß &Szlig;"
+    );
+  }
+
+  #[test]
+  fn links_as_footnotes() {
+    assert_eq!(
+      to_text(
+        "<p>Read <a href=\"https://moon.space/news\">the news</a>, \
+         <a href=\"https://moon.space/\">https://moon.space</a>, \
+         <a href=\"mailto:john@moon.space\">john@moon.space</a> and \
+         <a href='https://moon.space/news'>again</a>.</p>"
+      ),
+      "Read the news[1], https://moon.space, john@moon.space and again[1].\n\n\
+       [1] https://moon.space/news"
+    );
+  }
+
+  #[test]
+  fn lists() {
+    assert_eq!(
+      to_text(
+        "<p>Todo:</p><ul><li>one</li><li>two<ol start=\"3\"><li>three</li>\
+         <li>four</li></ol></li></ul><p>Done</p>"
+      ),
+      "Todo:\n\n* one\n* two\n  3. three\n  4. four\n\nDone"
+    );
+  }
+
+  #[test]
+  fn empty_list_items() {
+    assert_eq!(to_text("<ul><li></li></ul><p>Hi</p>"), "Hi");
+    assert_eq!(to_text("<ul><li></li><li>two</li></ul>"), "* two");
+  }
+
+  #[test]
+  fn tables() {
+    assert_eq!(
+      to_text(
+        "<table><tr><th>Name</th><th>Size</th></tr>\
+         <tr><td>report.pdf</td><td>12 kB</td></tr></table>"
+      ),
+      "Name       | Size\nreport.pdf | 12 kB"
+    );
+    // A layout table is only a way to place paragraphs.
+    assert_eq!(
+      to_text(
+        "<table><tr><td><p>A first paragraph</p><p>and a second one</p></td>\
+         <td>beside</td></tr></table>"
+      ),
+      "A first paragraph\n\nand a second one\nbeside"
+    );
+  }
+
+  #[test]
+  fn quotes() {
+    assert_eq!(
+      to_text(
+        "<p>Sure.</p><blockquote><p>Coming?</p>\
+         <blockquote>Dinner at 8<br>at home</blockquote></blockquote><p>See you</p>"
+      ),
+      "Sure.\n\n> Coming?\n>\n> > Dinner at 8\n> > at home\n\nSee you"
+    );
+  }
+
+  #[test]
+  fn preformatted() {
+    assert_eq!(
+      to_text("<p>Log:</p><pre>a  b\n  c</pre>"),
+      "Log:\n\na  b\n  c"
+    );
+  }
+
+  #[test]
+  fn sample() -> Result<(), Box<dyn Error>> {
+    let text = to_text(&fs::read_to_string("tests/test.html")?);
+
+    assert!(!text.is_empty());
+    assert!(!text.contains('<'));
+    Ok(())
+  }
+}
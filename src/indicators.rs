@@ -0,0 +1,394 @@
+/* indicators.rs
+ *
+ * Copyright 2024 Alexandre Del Bigio
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+use std::net::IpAddr;
+
+use crate::message::attachment::{Attachment, Hashes};
+use crate::trustedsenders::TrustedSenders;
+
+const SENDER_HEADERS: [&str; 4] = ["From", "Sender", "Reply-To", "Return-Path"];
+
+const URL_SCHEMES: [&str; 3] = ["http://", "https://", "ftp://"];
+
+/// Characters a URL found in text or markup can't contain.
+const URL_TERMINATORS: [char; 6] = ['<', '>', '"', '\'', '`', '\\'];
+
+/// Characters which separate the tokens of a Received header.
+const RECEIVED_SEPARATORS: [char; 8] = ['(', ')', '[', ']', ';', ',', '=', '"'];
+
+pub struct AttachmentIndicator {
+  pub filename: String,
+  pub size: usize,
+  pub hashes: Hashes,
+}
+
+/// What a message gives away about where it comes from and where it leads,
+/// in a shape a SOC can feed to its tools.
+#[derive(Default)]
+pub struct Indicators {
+  pub senders: Vec<String>,
+  pub ips: Vec<String>,
+  pub urls: Vec<String>,
+  pub attachments: Vec<AttachmentIndicator>,
+}
+
+impl Indicators {
+  pub fn collect(
+    from: &str,
+    headers: &[(String, String)],
+    bodies: &[&str],
+    attachments: &[Attachment],
+  ) -> Self {
+    let mut indicators = Self::default();
+
+    let senders = headers
+      .iter()
+      .filter(|(name, _)| SENDER_HEADERS.iter().any(|h| h.eq_ignore_ascii_case(name)))
+      .map(|(_, value)| value.as_str());
+    for value in std::iter::once(from).chain(senders) {
+      for address in value.split(',').filter_map(TrustedSenders::sender_address) {
+        push_unique(&mut indicators.senders, address);
+      }
+    }
+
+    for (_, value) in headers
+      .iter()
+      .filter(|(name, _)| name.eq_ignore_ascii_case("Received"))
+    {
+      for ip in ip_addresses(value) {
+        push_unique(&mut indicators.ips, ip.to_string());
+      }
+    }
+
+    for body in bodies {
+      for url in urls(body) {
+        push_unique(&mut indicators.urls, url);
+      }
+    }
+
+    indicators.attachments = attachments
+      .iter()
+      .map(|attachment| AttachmentIndicator {
+        filename: attachment.filename.clone(),
+        size: attachment.body.len(),
+        hashes: attachment.hashes(),
+      })
+      .collect();
+
+    indicators
+  }
+
+  pub fn to_json(&self) -> String {
+    let list = |values: &[String]| {
+      values
+        .iter()
+        .map(|value| json_string(value))
+        .collect::<Vec<_>>()
+        .join(", ")
+    };
+    let attachments = self
+      .attachments
+      .iter()
+      .map(|attachment| {
+        format!(
+          "    {{\"filename\": {}, \"size\": {}, \"sha256\": {}, \"sha1\": {}, \"md5\": {}}}",
+          json_string(&attachment.filename),
+          attachment.size,
+          json_string(&attachment.hashes.sha256),
+          json_string(&attachment.hashes.sha1),
+          json_string(&attachment.hashes.md5)
+        )
+      })
+      .collect::<Vec<_>>();
+    let attachments = if attachments.is_empty() {
+      "[]".to_string()
+    } else {
+      format!("[\n{}\n  ]", attachments.join(",\n"))
+    };
+
+    format!(
+      "{{\n  \"senders\": [{}],\n  \"ips\": [{}],\n  \"urls\": [{}],\n  \"attachments\": {}\n}}\n",
+      list(&self.senders),
+      list(&self.ips),
+      list(&self.urls),
+      attachments
+    )
+  }
+
+  /// One indicator per row: its type, its value, and the attachment it belongs
+  /// to for hashes.
+  pub fn to_csv(&self) -> String {
+    let mut rows: Vec<[&str; 3]> = vec![["type", "value", "attachment"]];
+    rows.extend(self.senders.iter().map(|v| ["sender", v.as_str(), ""]));
+    rows.extend(self.ips.iter().map(|v| ["ip", v.as_str(), ""]));
+    rows.extend(self.urls.iter().map(|v| ["url", v.as_str(), ""]));
+    for attachment in &self.attachments {
+      let filename = attachment.filename.as_str();
+      rows.push(["sha256", &attachment.hashes.sha256, filename]);
+      rows.push(["sha1", &attachment.hashes.sha1, filename]);
+      rows.push(["md5", &attachment.hashes.md5, filename]);
+    }
+
+    rows
+      .iter()
+      .map(|row| {
+        row
+          .iter()
+          .map(|field| csv_field(field))
+          .collect::<Vec<_>>()
+          .join(",")
+          + "\r\n"
+      })
+      .collect()
+  }
+}
+
+fn push_unique(list: &mut Vec<String>, value: String) {
+  if !list.contains(&value) {
+    list.push(value);
+  }
+}
+
+/// The addresses a Received header mentions, leaving out loopback and
+/// unspecified ones which say nothing about the route.
+fn ip_addresses(value: &str) -> Vec<IpAddr> {
+  value
+    .split(|c: char| c.is_whitespace() || RECEIVED_SEPARATORS.contains(&c))
+    .filter_map(|token| {
+      let token = token.trim_end_matches('.');
+      let token = match token.get(..5) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("ipv6:") => &token[5..],
+        _ => token,
+      };
+      token.parse::<IpAddr>().ok()
+    })
+    .filter(|ip| !ip.is_loopback() && !ip.is_unspecified())
+    .collect()
+}
+
+/// The URLs found in plain text or in markup, where `&amp;` is decoded.
+fn urls(text: &str) -> Vec<String> {
+  // ASCII lowercasing keeps the byte offsets.
+  let lower = text.to_ascii_lowercase();
+  let mut urls = Vec::new();
+  let mut position = 0;
+
+  while let Some((start, scheme)) = URL_SCHEMES
+    .iter()
+    .filter_map(|scheme| {
+      lower[position..]
+        .find(scheme)
+        .map(|i| (position + i, scheme))
+    })
+    .min_by_key(|(start, _)| *start)
+  {
+    let rest = &text[start..];
+    let end = rest
+      .find(|c: char| c.is_whitespace() || c.is_control() || URL_TERMINATORS.contains(&c))
+      .unwrap_or(rest.len());
+    let mut url = rest[..end].trim_end_matches(['.', ',', ';', ':', '!', '?']);
+    if url.ends_with(')') && !url.contains('(') {
+      url = &url[..url.len() - 1];
+    }
+    if url.len() > scheme.len() {
+      urls.push(url.replace("&amp;", "&"));
+    }
+    position = start + end.max(scheme.len());
+  }
+  urls
+}
+
+fn json_string(value: &str) -> String {
+  let mut json = String::with_capacity(value.len() + 2);
+  json.push('"');
+  for c in value.chars() {
+    match c {
+      '"' => json.push_str("\\\""),
+      '\\' => json.push_str("\\\\"),
+      '\n' => json.push_str("\\n"),
+      '\r' => json.push_str("\\r"),
+      '\t' => json.push_str("\\t"),
+      c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+      c => json.push(c),
+    }
+  }
+  json.push('"');
+  json
+}
+
+/// Quotes a field when needed. A field a spreadsheet would take for a formula
+/// (the file name of an attachment is whatever the sender chose) is prefixed
+/// with a quote so it stays text.
+fn csv_field(value: &str) -> String {
+  let value = if value.starts_with(['=', '+', '-', '@']) {
+    format!("'{value}")
+  } else {
+    value.to_string()
+  };
+  if value.contains([',', '"', '\n', '\r']) {
+    format!("\"{}\"", value.replace('"', "\"\""))
+  } else {
+    value
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::indicators::*;
+
+  fn headers(headers: &[(&str, &str)]) -> Vec<(String, String)> {
+    headers
+      .iter()
+      .map(|(name, value)| (name.to_string(), value.to_string()))
+      .collect()
+  }
+
+  fn attachment(filename: &str, body: &[u8]) -> Attachment {
+    Attachment {
+      filename: filename.to_string(),
+      content_id: String::new(),
+      body: body.to_vec(),
+      mime_type: None,
+    }
+  }
+
+  #[test]
+  fn received_ip_addresses() {
+    assert_eq!(
+      ip_addresses(
+        "from mx.moon.space (mx.moon.space [192.0.2.1]) by mx.mercure.space (Postfix) with \
+         ESMTPS id 4F2; Tue, 1 Oct 2024 10:00:00 +0000"
+      ),
+      vec!["192.0.2.1".parse::<IpAddr>().unwrap()]
+    );
+    assert_eq!(
+      ip_addresses("from [IPv6:2001:db8::1] (helo=198.51.100.7) by localhost [127.0.0.1]"),
+      vec![
+        "2001:db8::1".parse::<IpAddr>().unwrap(),
+        "198.51.100.7".parse::<IpAddr>().unwrap(),
+      ]
+    );
+    assert_eq!(
+      ip_addresses("by 10.220.191.194. with HTTP; version 1.2").len(),
+      1
+    );
+  }
+
+  #[test]
+  fn urls_in_text_and_markup() {
+    assert_eq!(
+      urls("Go to https://moon.space/a?b=1. Or (see http://mercure.space/x)"),
+      vec!["https://moon.space/a?b=1", "http://mercure.space/x"]
+    );
+    assert_eq!(
+      urls("<a href=\"HTTPS://moon.space/?a=1&amp;b=2\">here</a> ftp://files.moon.space/f.zip"),
+      vec![
+        "HTTPS://moon.space/?a=1&b=2",
+        "ftp://files.moon.space/f.zip"
+      ]
+    );
+    assert_eq!(
+      urls("https://en.wikipedia.org/wiki/Moon_(disambiguation)"),
+      vec!["https://en.wikipedia.org/wiki/Moon_(disambiguation)"]
+    );
+    assert!(urls("http:// nothing here").is_empty());
+  }
+
+  #[test]
+  fn collect_indicators() {
+    let indicators = Indicators::collect(
+      "John Doe <John@moon.space>",
+      &headers(&[
+        ("Return-Path", "<bounce@moon.space>"),
+        ("Reply-To", "a <john@moon.space>, b <other@mercure.space>"),
+        ("Received", "from mx.moon.space [192.0.2.1]"),
+        ("Received", "from laptop [192.0.2.1]"),
+        ("X-Originating-IP", "[203.0.113.9]"),
+      ]),
+      &["https://moon.space", "<a href=\"https://moon.space\">"],
+      &[attachment("abc.txt", b"abc")],
+    );
+
+    assert_eq!(indicators.senders, vec![
+      "john@moon.space",
+      "bounce@moon.space",
+      "other@mercure.space"
+    ]);
+    assert_eq!(indicators.ips, vec!["192.0.2.1"]);
+    assert_eq!(indicators.urls, vec!["https://moon.space"]);
+    assert_eq!(indicators.attachments.len(), 1);
+    assert_eq!(indicators.attachments[0].size, 3);
+  }
+
+  #[test]
+  fn export_to_json() {
+    let indicators = Indicators {
+      senders: vec!["john@moon.space".to_string()],
+      ips: vec![],
+      urls: vec!["https://moon.space/?q=\"x\"".to_string()],
+      attachments: vec![AttachmentIndicator {
+        filename: "a\\b.txt".to_string(),
+        size: 3,
+        hashes: Hashes {
+          sha256: "256".to_string(),
+          sha1: "1".to_string(),
+          md5: "5".to_string(),
+        },
+      }],
+    };
+
+    assert_eq!(
+      indicators.to_json(),
+      "{\n  \"senders\": [\"john@moon.space\"],\n  \"ips\": [],\n  \"urls\": \
+       [\"https://moon.space/?q=\\\"x\\\"\"],\n  \"attachments\": [\n    {\"filename\": \
+       \"a\\\\b.txt\", \"size\": 3, \"sha256\": \"256\", \"sha1\": \"1\", \"md5\": \"5\"}\n  \
+       ]\n}\n"
+    );
+    assert_eq!(json_string("\u{1}\n"), "\"\\u0001\\n\"");
+  }
+
+  #[test]
+  fn export_to_csv() {
+    let indicators = Indicators {
+      senders: vec!["john@moon.space".to_string()],
+      ips: vec!["192.0.2.1".to_string()],
+      urls: vec!["https://moon.space/?a=1,2".to_string()],
+      attachments: vec![AttachmentIndicator {
+        filename: "=HYPERLINK(\"x\").txt".to_string(),
+        size: 3,
+        hashes: Hashes {
+          sha256: "256".to_string(),
+          sha1: "1".to_string(),
+          md5: "5".to_string(),
+        },
+      }],
+    };
+
+    assert_eq!(
+      indicators.to_csv(),
+      "type,value,attachment\r\n\
+       sender,john@moon.space,\r\n\
+       ip,192.0.2.1,\r\n\
+       url,\"https://moon.space/?a=1,2\",\r\n\
+       sha256,256,\"'=HYPERLINK(\"\"x\"\").txt\"\r\n\
+       sha1,1,\"'=HYPERLINK(\"\"x\"\").txt\"\r\n\
+       md5,5,\"'=HYPERLINK(\"\"x\"\").txt\"\r\n"
+    );
+  }
+}
@@ -0,0 +1,135 @@
+/* lightbox.rs
+ *
+ * Copyright 2024 Alexandre Del Bigio
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+use std::cell::Cell;
+use std::rc::Rc;
+
+use adw::glib::clone;
+use adw::prelude::*;
+use gtk4::{gdk, glib};
+
+use crate::message::attachment::Attachment;
+use crate::utils::image::{load_pixbuf, texture};
+
+/// Shows the image attachments one at a time, starting with `index`. The arrow
+/// keys, Page Up/Down, Space, Home and End go from one to the other.
+pub fn show(parent: &impl IsA<gtk4::Widget>, images: Vec<Attachment>, index: usize) {
+  let builder = gtk4::Builder::from_string(gtk4::include_blueprint!("src/lightbox.blp"));
+  let dialog: adw::Dialog = builder.object("lightbox").unwrap();
+  let stack: adw::ViewStack = builder.object("stack").unwrap();
+  let picture: gtk4::Picture = builder.object("picture").unwrap();
+  let error: adw::StatusPage = builder.object("error").unwrap();
+  let previous: gtk4::Button = builder.object("previous").unwrap();
+  let next: gtk4::Button = builder.object("next").unwrap();
+
+  let images = Rc::new(images);
+  let current = Rc::new(Cell::new(index));
+  let show_image: Rc<dyn Fn(usize)> = Rc::new(clone!(
+    #[weak]
+    dialog,
+    #[weak]
+    stack,
+    #[weak]
+    picture,
+    #[weak]
+    error,
+    #[weak]
+    previous,
+    #[weak]
+    next,
+    #[strong]
+    images,
+    #[strong]
+    current,
+    move |index: usize| {
+      let Some(image) = images.get(index) else {
+        return;
+      };
+      current.set(index);
+      dialog.set_title(&format!(
+        "{} ({}/{})",
+        image.filename,
+        index + 1,
+        images.len()
+      ));
+      previous.set_sensitive(index > 0);
+      next.set_sensitive(index + 1 < images.len());
+
+      match load_pixbuf(&image.body) {
+        Ok(pixbuf) => {
+          picture.set_paintable(Some(&texture(&pixbuf)));
+          stack.set_visible_child_name("image");
+        }
+        Err(e) => {
+          log::error!("load_pixbuf({}): {}", image.filename, e);
+          picture.set_paintable(None::<&gdk::Paintable>);
+          error.set_description(Some(&e.to_string()));
+          stack.set_visible_child_name("error");
+        }
+      }
+    }
+  ));
+
+  let go_to = Rc::new(clone!(
+    #[strong]
+    show_image,
+    #[strong]
+    images,
+    #[strong]
+    current,
+    move |key: gdk::Key| {
+      let index = current.get();
+      let target = match key {
+        gdk::Key::Left | gdk::Key::Page_Up | gdk::Key::BackSpace => index.checked_sub(1),
+        gdk::Key::Right | gdk::Key::Page_Down | gdk::Key::space => Some(index + 1),
+        gdk::Key::Home => Some(0),
+        gdk::Key::End => images.len().checked_sub(1),
+        _ => return glib::Propagation::Proceed,
+      };
+      if let Some(target) = target.filter(|target| *target < images.len()) {
+        show_image(target);
+      }
+      glib::Propagation::Stop
+    }
+  ));
+
+  // In the capture phase, so that the arrows don't move the focus instead.
+  let keys = gtk4::EventControllerKey::new();
+  keys.set_propagation_phase(gtk4::PropagationPhase::Capture);
+  keys.connect_key_pressed(clone!(
+    #[strong]
+    go_to,
+    move |_, key, _, _| go_to(key)
+  ));
+  dialog.add_controller(keys);
+
+  previous.connect_clicked(clone!(
+    #[strong]
+    go_to,
+    move |_| {
+      go_to(gdk::Key::Left);
+    }
+  ));
+  next.connect_clicked(move |_| {
+    go_to(gdk::Key::Right);
+  });
+
+  show_image(index);
+  dialog.present(Some(parent));
+}
@@ -0,0 +1,300 @@
+/* mailfolder.rs
+ *
+ * Copyright 2024 Alexandre Del Bigio
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+use std::error::Error;
+
+use crate::gio::prelude::*;
+use crate::message::message::MessageParser;
+use crate::message::summary::MessageSummary;
+use crate::{gio, glib};
+
+const ATTRIBUTES: &str = "standard::name,standard::display-name,standard::type,\
+                          standard::is-symlink,standard::is-hidden,\
+                          standard::fast-content-type,time::modified";
+
+const MAIL_EXTENSIONS: [&str; 2] = ["eml", "msg"];
+
+const FILES_PER_REQUEST: i32 = 100;
+
+/// How the messages of a folder are ordered, the "folder-sort" setting.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FolderSort {
+  Name,
+  /// By the date the messages were sent, read in the background. A message
+  /// without one goes by the modification time of its file.
+  Date,
+}
+
+impl FolderSort {
+  pub fn from_setting(value: &str) -> Self {
+    match value {
+      "date" => Self::Date,
+      _ => Self::Name,
+    }
+  }
+}
+
+/// The mail files of the folder a message was opened from, to go through them
+/// one after the other.
+#[derive(Debug, Default)]
+pub struct MailFolder {
+  files: Vec<gio::File>,
+}
+
+impl MailFolder {
+  /// Lists the mail files next to `file`, `file` included.
+  pub async fn list(file: &gio::File, sort: FolderSort) -> Result<Self, Box<dyn Error>> {
+    let folder = file.parent().ok_or("The file is not in a folder")?;
+    let mut entries = Self::entries(&folder, false).await?;
+    match sort {
+      FolderSort::Name => entries
+        .sort_by_cached_key(|(_, info)| glib::FilenameCollationKey::from(info.display_name())),
+      FolderSort::Date => {
+        let paths: Vec<_> = entries.iter().map(|(file, _)| file.path()).collect();
+        let dates = gio::spawn_blocking(move || {
+          paths
+            .iter()
+            .map(|path| {
+              let summary = MessageSummary::read(path.as_deref()?).ok()?;
+              summary.date.map(|date| date.to_unix())
+            })
+            .collect::<Vec<Option<i64>>>()
+        })
+        .await
+        .map_err(|_| "MessageSummary::read() panicked")?;
+        let mut dated: Vec<_> = entries.into_iter().zip(dates).collect();
+        dated.sort_by_cached_key(|((_, info), date)| {
+          (
+            date.or_else(|| info.modification_date_time().map(|date| date.to_unix())),
+            glib::FilenameCollationKey::from(info.display_name()),
+          )
+        });
+        entries = dated.into_iter().map(|(entry, _)| entry).collect();
+      }
+    }
+
+    Ok(Self {
+      files: entries.into_iter().map(|(file, _)| file).collect(),
+    })
+  }
+
+  /// The mail files of `folder`, and of its subfolders when `recursive`, in no
+  /// particular order.
+  pub async fn scan(folder: &gio::File, recursive: bool) -> Result<Vec<gio::File>, Box<dyn Error>> {
+    let entries = Self::entries(folder, recursive).await?;
+    Ok(entries.into_iter().map(|(file, _)| file).collect())
+  }
+
+  async fn entries(
+    root: &gio::File,
+    recursive: bool,
+  ) -> Result<Vec<(gio::File, gio::FileInfo)>, Box<dyn Error>> {
+    let mut entries: Vec<(gio::File, gio::FileInfo)> = vec![];
+    let mut folders = vec![root.clone()];
+    while let Some(folder) = folders.pop() {
+      let enumerator = match folder
+        .enumerate_children_future(
+          ATTRIBUTES,
+          gio::FileQueryInfoFlags::NONE,
+          glib::Priority::DEFAULT,
+        )
+        .await
+      {
+        Ok(enumerator) => enumerator,
+        Err(e) if folder.equal(root) => return Err(e.into()),
+        // A subfolder that can't be read doesn't hide the rest.
+        Err(e) => {
+          log::debug!("entries({:?}): {}", folder.peek_path(), e);
+          continue;
+        }
+      };
+
+      loop {
+        let infos = enumerator
+          .next_files_future(FILES_PER_REQUEST, glib::Priority::DEFAULT)
+          .await?;
+        if infos.is_empty() {
+          break;
+        }
+        for info in infos {
+          // Symbolic links to folders are not followed, they could loop.
+          if recursive
+            && info.file_type() == gio::FileType::Directory
+            && !info.is_symlink()
+            && !info.is_hidden()
+          {
+            folders.push(folder.child(info.name()));
+          } else if Self::is_mail_file(&info) {
+            entries.push((folder.child(info.name()), info));
+          }
+        }
+      }
+      enumerator.close_future(glib::Priority::DEFAULT).await?;
+    }
+    Ok(entries)
+  }
+
+  fn is_mail_file(info: &gio::FileInfo) -> bool {
+    if info.file_type() != gio::FileType::Regular {
+      return false;
+    }
+    let extension = info
+      .name()
+      .extension()
+      .map(|extension| extension.to_string_lossy().to_lowercase())
+      .unwrap_or_default();
+    MAIL_EXTENSIONS.contains(&extension.as_str())
+      || info
+        .attribute_string(gio::FILE_ATTRIBUTE_STANDARD_FAST_CONTENT_TYPE.as_str())
+        .is_some_and(|content_type| {
+          MessageParser::supported_mime_types().contains(&content_type.as_str())
+        })
+  }
+
+  /// The message `offset` places away from `file`: -1 for the previous one,
+  /// 1 for the next one.
+  pub fn neighbour(&self, file: &gio::File, offset: isize) -> Option<gio::File> {
+    let index = self.files.iter().position(|other| other.equal(file))?;
+    self.files.get(index.checked_add_signed(offset)?).cloned()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::utils;
+
+  fn file_name(file: Option<gio::File>) -> Option<String> {
+    file
+      .and_then(|file| file.basename())
+      .map(|name| name.to_string_lossy().to_string())
+  }
+
+  /// A folder of the temporary directory holding empty files named `names`.
+  fn temp_folder(name: &str, names: &[&str]) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!(
+      "mailviewer-folder-test-{}-{}",
+      name,
+      std::process::id()
+    ));
+    std::fs::create_dir_all(&path).unwrap();
+    for name in names {
+      std::fs::write(path.join(name), b"").unwrap();
+    }
+    path
+  }
+
+  #[test]
+  fn neighbours_by_name() {
+    let path = temp_folder("by-name", &[
+      "mail 10.eml",
+      "notes.txt",
+      "a.msg",
+      "mail 2.eml",
+      "archive.zip",
+    ]);
+    let root = gio::File::for_path(&path);
+    utils::spawn_and_wait_new_ctx(async move {
+      let file = root.child("mail 2.eml");
+      let folder = MailFolder::list(&file, FolderSort::Name).await.unwrap();
+      // Only mail files are listed, numbers in names are sorted as numbers.
+      let names: Vec<String> = folder
+        .files
+        .iter()
+        .filter_map(|file| file_name(Some(file.clone())))
+        .collect();
+      assert_eq!(names, ["a.msg", "mail 2.eml", "mail 10.eml"]);
+
+      assert_eq!(
+        file_name(folder.neighbour(&file, -1)).as_deref(),
+        Some("a.msg")
+      );
+      assert_eq!(
+        file_name(folder.neighbour(&file, 1)).as_deref(),
+        Some("mail 10.eml")
+      );
+      assert_eq!(folder.neighbour(&root.child("a.msg"), -1), None);
+      assert_eq!(folder.neighbour(&root.child("mail 10.eml"), 1), None);
+    });
+    std::fs::remove_dir_all(&path).unwrap();
+  }
+
+  #[test]
+  fn sorted_by_sent_date() {
+    let path = temp_folder("by-date", &[]);
+    // Written in the other order, the modification times would sort them the
+    // other way.
+    for (name, date) in [
+      ("new.eml", "Tue, 2 Jan 2024 10:00:00 +0000"),
+      ("old.eml", "Wed, 1 Jan 2020 10:00:00 +0000"),
+    ] {
+      std::fs::write(
+        path.join(name),
+        format!("Date: {date}\r\nSubject: {name}\r\n\r\nHello\r\n"),
+      )
+      .unwrap();
+    }
+    let root = gio::File::for_path(&path);
+    utils::spawn_and_wait_new_ctx(async move {
+      let folder = MailFolder::list(&root.child("new.eml"), FolderSort::Date)
+        .await
+        .unwrap();
+      let names: Vec<String> = folder
+        .files
+        .iter()
+        .filter_map(|file| file_name(Some(file.clone())))
+        .collect();
+      assert_eq!(names, ["old.eml", "new.eml"]);
+    });
+    std::fs::remove_dir_all(&path).unwrap();
+  }
+
+  #[test]
+  fn neighbours_by_date() {
+    utils::spawn_and_wait_new_ctx(async move {
+      let file = gio::File::for_path("tests/html.eml");
+      let by_name = MailFolder::list(&file, FolderSort::Name).await.unwrap();
+      let by_date = MailFolder::list(&file, FolderSort::Date).await.unwrap();
+      assert_eq!(by_date.files.len(), by_name.files.len());
+      assert_eq!(
+        MailFolder::default().neighbour(&file, 1),
+        None,
+        "a file that isn't listed has no neighbour"
+      );
+    });
+  }
+
+  #[test]
+  fn scan_folder() {
+    utils::spawn_and_wait_new_ctx(async move {
+      let folder = gio::File::for_path("tests");
+      let files = MailFolder::scan(&folder, true).await.unwrap();
+      let listed = MailFolder::list(&folder.child("html.eml"), FolderSort::Name)
+        .await
+        .unwrap();
+      assert_eq!(files.len(), listed.files.len());
+      assert!(files
+        .iter()
+        .any(|file| file.equal(&folder.child("bounce.eml"))));
+      assert!(MailFolder::scan(&folder.child("missing"), false)
+        .await
+        .is_err());
+    });
+  }
+}
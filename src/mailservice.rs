@@ -22,16 +22,22 @@ use std::cell::RefCell;
 use crate::config::VERSION;
 use crate::gio::prelude::*;
 use crate::message::attachment::Attachment;
+use crate::message::calendar::CalendarEvent;
+use crate::message::dsn::DeliveryReport;
 use crate::message::message::{Message, MessageParser};
 use crate::{gio, glib};
 
 type TitleChangedCallback = Box<dyn Fn(&MailService, &str) + 'static>;
 
+/// The previous and the next message of a folder.
+const PRELOAD_COUNT: usize = 2;
+
 pub struct MailService {
   parser: RefCell<Option<MessageParser>>,
   file: RefCell<Option<gio::File>>,
   show_file_name: RefCell<bool>,
   signal_title_changed: RefCell<Option<TitleChangedCallback>>,
+  preloaded: RefCell<Vec<(gio::File, MessageParser)>>,
 }
 
 impl MailService {
@@ -41,6 +47,7 @@ impl MailService {
       file: RefCell::new(None),
       show_file_name: RefCell::new(true),
       signal_title_changed: RefCell::new(None),
+      preloaded: RefCell::new(vec![]),
     }
   }
 
@@ -49,7 +56,54 @@ impl MailService {
     file: &gio::File,
     cancellable: Option<&gio::Cancellable>,
   ) -> Result<(), Box<dyn std::error::Error>> {
+    let preloaded = {
+      let mut preloaded = self.preloaded.borrow_mut();
+      preloaded
+        .iter()
+        .position(|(other, _)| other.equal(file))
+        .map(|index| preloaded.remove(index).1)
+    };
+    let parser = match preloaded {
+      Some(parser) => parser,
+      None => Self::parse_message(file, cancellable).await?,
+    };
+    // Only replaced once the message is read: the previous one is still shown
+    // when this one fails.
+    self.parser.borrow_mut().replace(parser);
     self.file.borrow_mut().replace(file.clone());
+
+    self.update_title();
+    Ok(())
+  }
+
+  /// Parses a message ahead of time, for `open_message()` to show it at once.
+  /// Only the last few are kept.
+  pub async fn preload(
+    &self,
+    file: &gio::File,
+    cancellable: Option<&gio::Cancellable>,
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    if self
+      .preloaded
+      .borrow()
+      .iter()
+      .any(|(other, _)| other.equal(file))
+    {
+      return Ok(());
+    }
+    let parser = Self::parse_message(file, cancellable).await?;
+    let mut preloaded = self.preloaded.borrow_mut();
+    preloaded.push((file.clone(), parser));
+    if preloaded.len() > PRELOAD_COUNT {
+      preloaded.remove(0);
+    }
+    Ok(())
+  }
+
+  async fn parse_message(
+    file: &gio::File,
+    cancellable: Option<&gio::Cancellable>,
+  ) -> Result<MessageParser, Box<dyn std::error::Error>> {
     let mut parser = MessageParser::new(file, cancellable).await?;
 
     let parse_thread = {
@@ -69,13 +123,7 @@ impl MailService {
       .unwrap()
     };
 
-    match parse_thread {
-      Ok(parser) => self.parser.borrow_mut().replace(parser),
-      Err(e) => return Err(Box::new(e)),
-    };
-
-    self.update_title();
-    Ok(())
+    Ok(parse_thread?)
   }
 
   pub fn from(&self) -> String {
@@ -106,6 +154,13 @@ impl MailService {
     String::new()
   }
 
+  pub fn headers(&self) -> Vec<(String, String)> {
+    if let Some(parser) = self.parser.borrow().as_ref() {
+      return parser.headers();
+    }
+    vec![]
+  }
+
   pub fn body_text(&self) -> Option<String> {
     if let Some(parser) = self.parser.borrow().as_ref() {
       return parser.body_text();
@@ -120,6 +175,14 @@ impl MailService {
     None
   }
 
+  /// The message converted to an .eml file, empty when none is open.
+  pub fn to_eml(&self) -> Vec<u8> {
+    if let Some(parser) = self.parser.borrow().as_ref() {
+      return parser.to_eml();
+    }
+    vec![]
+  }
+
   pub fn attachments(&self) -> Vec<Attachment> {
     if let Some(parser) = self.parser.borrow().as_ref() {
       return parser.attachments().clone();
@@ -127,6 +190,20 @@ impl MailService {
     vec![]
   }
 
+  pub fn events(&self) -> Vec<CalendarEvent> {
+    if let Some(parser) = self.parser.borrow().as_ref() {
+      return parser.events();
+    }
+    vec![]
+  }
+
+  pub fn delivery_report(&self) -> Option<DeliveryReport> {
+    if let Some(parser) = self.parser.borrow().as_ref() {
+      return parser.delivery_report();
+    }
+    None
+  }
+
   pub fn set_show_file_name(&self, show_file_name: bool) {
     log::debug!("set_show_file_name({})", show_file_name);
     self.show_file_name.replace(show_file_name);
@@ -320,6 +397,25 @@ mod tests {
     });
   }
 
+  #[test]
+  fn preloaded_message() {
+    let service = MailService::new();
+    let file = gio::File::for_path("sample.eml");
+
+    utils::spawn_and_wait_new_ctx(async move {
+      service.preload(&file, None).await.unwrap();
+      // Already parsed, the message doesn't have to be read again.
+      let cancellable = gio::Cancellable::new();
+      cancellable.cancel();
+      service
+        .open_message(&file, Some(&cancellable))
+        .await
+        .unwrap();
+      assert_eq!(service.subject(), "Lorem ipsum");
+      assert!(service.preloaded.borrow().is_empty());
+    });
+  }
+
   #[test]
   fn cancelled_loading() {
     let service = MailService::new();
@@ -18,11 +18,26 @@
  * SPDX-License-Identifier: GPL-3.0-or-later
  */
 mod application;
+mod attachmentcontent;
 mod config;
+mod eventcard;
 mod gmimeinit;
 mod html;
+mod htmltext;
+mod indicators;
+mod lightbox;
+mod mailfolder;
 mod mailservice;
 mod message;
+mod messagelist;
+mod mhtml;
+mod previewdialog;
+mod reportcard;
+mod richtext;
+mod searchdialog;
+mod searchindex;
+mod textsearch;
+mod trustedsenders;
 mod utils;
 mod window;
 
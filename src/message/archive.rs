@@ -0,0 +1,772 @@
+/* archive.rs
+ *
+ * Copyright 2024 Alexandre Del Bigio
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+use std::error::Error;
+
+use encoding_rs::WINDOWS_1252;
+
+use super::attachment::Attachment;
+use crate::gio;
+use crate::gio::prelude::*;
+
+/// An archive listing more entries than this is refused.
+const MAX_ENTRIES: usize = 10_000;
+
+/// The largest entry extracted. A zip bomb makes gigabytes out of a few
+/// kilobytes, everything decompressed is checked against this.
+const MAX_ENTRY_SIZE: u64 = 256 * 1024 * 1024;
+
+/// The largest a compressed tarball may grow to, it has to be decompressed as
+/// a whole to be listed. Mail servers refuse messages of a few tens of
+/// megabytes, and tarballs rarely compress more than a few times.
+const MAX_STREAM_SIZE: u64 = 64 * 1024 * 1024;
+
+const ZIP_LOCAL_HEADER: u32 = 0x04034b50;
+const ZIP_CENTRAL_HEADER: u32 = 0x02014b50;
+const ZIP_END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY: u32 = 0x06064b50;
+const ZIP64_LOCATOR: u32 = 0x07064b50;
+const ZIP64_EXTRA_FIELD: u16 = 0x0001;
+const ZIP_FLAG_ENCRYPTED: u16 = 0x0001;
+const ZIP_FLAG_UTF8: u16 = 0x0800;
+const ZIP_STORED: u16 = 0;
+const ZIP_DEFLATED: u16 = 8;
+
+const TAR_BLOCK: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ArchiveKind {
+  Zip,
+  Tar,
+  /// A gzip compressed tarball, or a single compressed file.
+  Gzip,
+}
+
+impl ArchiveKind {
+  /// The kind of archive a body is, by its magic number : the name of an
+  /// attachment is whatever the sender chose.
+  fn detect(body: &[u8]) -> Option<Self> {
+    if body.starts_with(b"PK\x03\x04") || body.starts_with(b"PK\x05\x06") {
+      return Some(Self::Zip);
+    }
+    if body.starts_with(b"\x1f\x8b") {
+      return Some(Self::Gzip);
+    }
+    if is_tar(body) {
+      return Some(Self::Tar);
+    }
+    None
+  }
+
+  /// Whether an attachment says it is an archive of this kind, by the
+  /// extension of its name or by its type. Office documents, jars and apks
+  /// are zips too, but not archives to browse.
+  fn is_declared(self, filename: &str, mime_type: Option<&str>) -> bool {
+    let extension = filename
+      .rsplit_once('.')
+      .map(|(_, extension)| extension.to_lowercase())
+      .unwrap_or_default();
+    let mime_type = mime_type.unwrap_or_default().to_lowercase();
+    let (extensions, mime_types): (&[&str], &[&str]) = match self {
+      Self::Zip => (&["zip"], &[
+        "application/zip",
+        "application/x-zip",
+        "application/x-zip-compressed",
+      ]),
+      Self::Tar => (&["tar"], &["application/x-tar"]),
+      Self::Gzip => (&["gz", "tgz"], &[
+        "application/gzip",
+        "application/x-gzip",
+        "application/x-gtar",
+        "application/x-compressed-tar",
+      ]),
+    };
+    extensions.contains(&extension.as_str()) || mime_types.contains(&mime_type.as_str())
+  }
+}
+
+/// Where the data of an entry is.
+#[derive(Debug, Clone)]
+enum Source {
+  Zip {
+    offset: u64,
+    compressed_size: u64,
+    method: u16,
+    crc32: u32,
+    encrypted: bool,
+  },
+  /// A range of the (decompressed) archive.
+  Range(usize, usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct ArchiveEntry {
+  /// The path of the entry in the archive, without control characters. It is
+  /// only ever shown : written anywhere, the entry goes by its
+  /// `Attachment::safe_filename()`.
+  pub name: String,
+  pub size: u64,
+  source: Source,
+}
+
+impl ArchiveEntry {
+  /// Whether the path of the entry tries to get out of the folder it would be
+  /// extracted to, which no honest archive does.
+  pub fn escapes(&self) -> bool {
+    self.name.starts_with(['/', '\\'])
+      || self.name.get(1..2) == Some(":")
+      || self
+        .name
+        .split(['/', '\\'])
+        .any(|component| component == "..")
+  }
+}
+
+/// The files of a zip, tar or gzip attachment. Entries are listed from the
+/// archive headers and only decompressed when extracted.
+pub struct Archive {
+  entries: Vec<ArchiveEntry>,
+  data: Vec<u8>,
+}
+
+impl Archive {
+  /// Whether an attachment is an archive to browse : it has to say so, and
+  /// its content has to agree.
+  pub fn is_archive(filename: &str, mime_type: Option<&str>, body: &[u8]) -> bool {
+    ArchiveKind::detect(body).is_some_and(|kind| kind.is_declared(filename, mime_type))
+  }
+
+  pub fn open(filename: &str, body: &[u8]) -> Result<Self, Box<dyn Error>> {
+    match ArchiveKind::detect(body).ok_or("Not a supported archive")? {
+      ArchiveKind::Zip => Ok(Self {
+        entries: zip_entries(body)?,
+        data: body.to_vec(),
+      }),
+      ArchiveKind::Tar => Ok(Self {
+        entries: tar_entries(body)?,
+        data: body.to_vec(),
+      }),
+      ArchiveKind::Gzip => {
+        let data = inflate(body, gio::ZlibCompressorFormat::Gzip, MAX_STREAM_SIZE)?;
+        if is_tar(&data) {
+          return Ok(Self {
+            entries: tar_entries(&data)?,
+            data,
+          });
+        }
+        let name = gzip_name(body).unwrap_or_else(|| {
+          let name = filename.rsplit(['/', '\\']).next().unwrap_or_default();
+          name
+            .strip_suffix(".gz")
+            .or(name.strip_suffix(".GZ"))
+            .unwrap_or(name)
+            .to_string()
+        });
+        Ok(Self {
+          entries: vec![ArchiveEntry {
+            name: clean_name(&name),
+            size: data.len() as u64,
+            source: Source::Range(0, data.len()),
+          }],
+          data,
+        })
+      }
+    }
+  }
+
+  pub fn entries(&self) -> &[ArchiveEntry] {
+    &self.entries
+  }
+
+  /// Decompresses an entry into an attachment, so that it can be opened or
+  /// saved like any other.
+  pub fn extract(&self, index: usize) -> Result<Attachment, Box<dyn Error>> {
+    let entry = self.entries.get(index).ok_or("No such entry")?;
+    let body = match &entry.source {
+      Source::Range(start, end) => self.data[*start..*end].to_vec(),
+      Source::Zip {
+        offset,
+        compressed_size,
+        method,
+        crc32,
+        encrypted,
+      } => {
+        if *encrypted {
+          return Err(format!("{} is encrypted", entry.name).into());
+        }
+        if entry.size > MAX_ENTRY_SIZE {
+          return Err(format!("{} is too large to be extracted", entry.name).into());
+        }
+        let data = zip_data(&self.data, *offset, *compressed_size)?;
+        let body = match *method {
+          ZIP_STORED => data.to_vec(),
+          // The size the archive declares is as far as decompressing goes.
+          ZIP_DEFLATED => inflate(data, gio::ZlibCompressorFormat::Raw, entry.size)?,
+          method => {
+            return Err(format!("{}: unsupported compression method {}", entry.name, method).into())
+          }
+        };
+        if body.len() as u64 != entry.size || crc32_of(&body) != *crc32 {
+          return Err(format!("{} is corrupted", entry.name).into());
+        }
+        body
+      }
+    };
+
+    let (content_type, _) = gio::content_type_guess(Some(entry.name.as_str()), Some(&body));
+    Ok(Attachment {
+      filename: entry.name.clone(),
+      content_id: String::new(),
+      mime_type: gio::content_type_get_mime_type(&content_type).map(|mime| mime.to_string()),
+      body,
+    })
+  }
+
+  /// A zip of the attachments, each one under its
+  /// `Attachment::unique_filenames()` name. An attachment is deflated unless
+  /// that does not make it smaller.
+  pub fn zip(attachments: &[Attachment]) -> Result<Vec<u8>, Box<dyn Error>> {
+    const TOO_LARGE: &str = "Too large for a zip archive";
+    let (time, date) = dos_date_time();
+    let names = Attachment::unique_filenames(attachments, |_| false);
+    let mut zip: Vec<u8> = Vec::new();
+    let mut directory: Vec<u8> = Vec::new();
+
+    for (attachment, name) in attachments.iter().zip(&names) {
+      let deflated = deflate(&attachment.body)?;
+      let (method, data) = if deflated.len() < attachment.body.len() {
+        (ZIP_DEFLATED, deflated.as_slice())
+      } else {
+        (ZIP_STORED, attachment.body.as_slice())
+      };
+      let offset = u32::try_from(zip.len()).map_err(|_| TOO_LARGE)?;
+      let size = u32::try_from(attachment.body.len()).map_err(|_| TOO_LARGE)?;
+      let compressed_size = u32::try_from(data.len()).map_err(|_| TOO_LARGE)?;
+      let name_length = u16::try_from(name.len()).map_err(|_| TOO_LARGE)?;
+
+      // What the local header and the central directory entry have in common,
+      // from the version needed to extract to the extra field length.
+      let mut common: Vec<u8> = Vec::new();
+      common.extend_from_slice(&20u16.to_le_bytes());
+      common.extend_from_slice(&ZIP_FLAG_UTF8.to_le_bytes());
+      common.extend_from_slice(&method.to_le_bytes());
+      common.extend_from_slice(&time.to_le_bytes());
+      common.extend_from_slice(&date.to_le_bytes());
+      common.extend_from_slice(&crc32_of(&attachment.body).to_le_bytes());
+      common.extend_from_slice(&compressed_size.to_le_bytes());
+      common.extend_from_slice(&size.to_le_bytes());
+      common.extend_from_slice(&name_length.to_le_bytes());
+      common.extend_from_slice(&0u16.to_le_bytes());
+
+      zip.extend_from_slice(&ZIP_LOCAL_HEADER.to_le_bytes());
+      zip.extend_from_slice(&common);
+      zip.extend_from_slice(name.as_bytes());
+      zip.extend_from_slice(data);
+
+      directory.extend_from_slice(&ZIP_CENTRAL_HEADER.to_le_bytes());
+      // Version made by.
+      directory.extend_from_slice(&20u16.to_le_bytes());
+      directory.extend_from_slice(&common);
+      // Comment length, disk number, internal and external attributes.
+      directory.extend_from_slice(&[0; 10]);
+      directory.extend_from_slice(&offset.to_le_bytes());
+      directory.extend_from_slice(name.as_bytes());
+    }
+
+    let count = u16::try_from(attachments.len()).map_err(|_| TOO_LARGE)?;
+    let directory_offset = u32::try_from(zip.len()).map_err(|_| TOO_LARGE)?;
+    let directory_size = u32::try_from(directory.len()).map_err(|_| TOO_LARGE)?;
+    zip.extend_from_slice(&directory);
+    zip.extend_from_slice(&ZIP_END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+    // Disk numbers.
+    zip.extend_from_slice(&[0; 4]);
+    zip.extend_from_slice(&count.to_le_bytes());
+    zip.extend_from_slice(&count.to_le_bytes());
+    zip.extend_from_slice(&directory_size.to_le_bytes());
+    zip.extend_from_slice(&directory_offset.to_le_bytes());
+    // Comment length.
+    zip.extend_from_slice(&0u16.to_le_bytes());
+    Ok(zip)
+  }
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+  Some(u16::from_le_bytes(
+    data.get(offset..offset.checked_add(2)?)?.try_into().ok()?,
+  ))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+  Some(u32::from_le_bytes(
+    data.get(offset..offset.checked_add(4)?)?.try_into().ok()?,
+  ))
+}
+
+fn u64_at(data: &[u8], offset: usize) -> Option<u64> {
+  Some(u64::from_le_bytes(
+    data.get(offset..offset.checked_add(8)?)?.try_into().ok()?,
+  ))
+}
+
+/// A path as stored in an archive, with the control characters a name could
+/// hide behind removed.
+fn clean_name(name: &str) -> String {
+  name.chars().filter(|c| !c.is_control()).collect()
+}
+
+/// Reads the central directory of a zip, which is what its entries really are:
+/// local headers can be forged to say anything.
+fn zip_entries(body: &[u8]) -> Result<Vec<ArchiveEntry>, Box<dyn Error>> {
+  const CORRUPTED: &str = "Corrupted zip archive";
+
+  // The end of central directory record is followed by a comment of at most
+  // 65535 bytes.
+  let search_start = body.len().saturating_sub(22 + 65535);
+  let end = (search_start..=body.len().saturating_sub(22))
+    .rev()
+    .find(|offset| u32_at(body, *offset) == Some(ZIP_END_OF_CENTRAL_DIRECTORY))
+    .ok_or(CORRUPTED)?;
+
+  let mut count = u16_at(body, end + 10).ok_or(CORRUPTED)? as u64;
+  let mut offset = u32_at(body, end + 16).ok_or(CORRUPTED)? as u64;
+  if count == 0xffff || offset == 0xffffffff {
+    let locator = end.checked_sub(20).ok_or(CORRUPTED)?;
+    if u32_at(body, locator) == Some(ZIP64_LOCATOR) {
+      let end64 = u64_at(body, locator + 8).ok_or(CORRUPTED)? as usize;
+      if u32_at(body, end64) != Some(ZIP64_END_OF_CENTRAL_DIRECTORY) {
+        return Err(CORRUPTED.into());
+      }
+      count = u64_at(body, end64 + 32).ok_or(CORRUPTED)?;
+      offset = u64_at(body, end64 + 48).ok_or(CORRUPTED)?;
+    }
+  }
+  if count as usize > MAX_ENTRIES {
+    return Err(format!("The archive has more than {} entries", MAX_ENTRIES).into());
+  }
+
+  let mut entries = Vec::new();
+  let mut position = usize::try_from(offset).map_err(|_| CORRUPTED)?;
+  for _ in 0..count {
+    if u32_at(body, position) != Some(ZIP_CENTRAL_HEADER) {
+      return Err(CORRUPTED.into());
+    }
+    let flags = u16_at(body, position + 8).ok_or(CORRUPTED)?;
+    let method = u16_at(body, position + 10).ok_or(CORRUPTED)?;
+    let crc32 = u32_at(body, position + 16).ok_or(CORRUPTED)?;
+    let mut compressed_size = u32_at(body, position + 20).ok_or(CORRUPTED)? as u64;
+    let mut size = u32_at(body, position + 24).ok_or(CORRUPTED)? as u64;
+    let name_length = u16_at(body, position + 28).ok_or(CORRUPTED)? as usize;
+    let extra_length = u16_at(body, position + 30).ok_or(CORRUPTED)? as usize;
+    let comment_length = u16_at(body, position + 32).ok_or(CORRUPTED)? as usize;
+    let mut local_offset = u32_at(body, position + 42).ok_or(CORRUPTED)? as u64;
+
+    let name_start = position + 46;
+    let name = body
+      .get(name_start..name_start + name_length)
+      .ok_or(CORRUPTED)?;
+    let name = if flags & ZIP_FLAG_UTF8 != 0 {
+      String::from_utf8_lossy(name).to_string()
+    } else {
+      // Should be CP437, which encoding_rs doesn't know. Names are mostly
+      // ASCII anyway.
+      match std::str::from_utf8(name) {
+        Ok(name) => name.to_string(),
+        Err(_) => WINDOWS_1252.decode(name).0.to_string(),
+      }
+    };
+
+    // Sizes and offsets which don't fit 32 bits are in the zip64 extra field,
+    // in this order, each only when its 32 bits value is saturated.
+    let extra_start = name_start + name_length;
+    let extra = body
+      .get(extra_start..extra_start + extra_length)
+      .ok_or(CORRUPTED)?;
+    let mut field = 0;
+    while let (Some(id), Some(length)) = (u16_at(extra, field), u16_at(extra, field + 2)) {
+      let data = extra
+        .get(field + 4..field + 4 + length as usize)
+        .ok_or(CORRUPTED)?;
+      if id == ZIP64_EXTRA_FIELD {
+        let mut values = data
+          .chunks_exact(8)
+          .map(|value| u64::from_le_bytes(value.try_into().unwrap()));
+        if size == 0xffffffff {
+          size = values.next().ok_or(CORRUPTED)?;
+        }
+        if compressed_size == 0xffffffff {
+          compressed_size = values.next().ok_or(CORRUPTED)?;
+        }
+        if local_offset == 0xffffffff {
+          local_offset = values.next().ok_or(CORRUPTED)?;
+        }
+      }
+      field += 4 + length as usize;
+    }
+
+    position = extra_start + extra_length + comment_length;
+    if name.ends_with('/') {
+      continue;
+    }
+    entries.push(ArchiveEntry {
+      name: clean_name(&name),
+      size,
+      source: Source::Zip {
+        offset: local_offset,
+        compressed_size,
+        method,
+        crc32,
+        encrypted: flags & ZIP_FLAG_ENCRYPTED != 0,
+      },
+    });
+  }
+  Ok(entries)
+}
+
+/// The data of a zip entry, which follows its local header.
+fn zip_data(body: &[u8], offset: u64, compressed_size: u64) -> Result<&[u8], Box<dyn Error>> {
+  const CORRUPTED: &str = "Corrupted zip archive";
+
+  let offset = usize::try_from(offset).map_err(|_| CORRUPTED)?;
+  if u32_at(body, offset) != Some(ZIP_LOCAL_HEADER) {
+    return Err(CORRUPTED.into());
+  }
+  let name_length = u16_at(body, offset + 26).ok_or(CORRUPTED)? as usize;
+  let extra_length = u16_at(body, offset + 28).ok_or(CORRUPTED)? as usize;
+  let start = offset + 30 + name_length + extra_length;
+  let end = usize::try_from(compressed_size)
+    .ok()
+    .and_then(|size| start.checked_add(size))
+    .ok_or(CORRUPTED)?;
+  Ok(body.get(start..end).ok_or(CORRUPTED)?)
+}
+
+fn is_tar(body: &[u8]) -> bool {
+  body.get(257..262) == Some(b"ustar")
+}
+
+/// A numeric field of a tar header : octal text, or base-256 when its first
+/// byte has the high bit set (GNU tar, for sizes past 8 GiB).
+fn tar_number(field: &[u8]) -> Option<u64> {
+  if field.first().is_some_and(|byte| byte & 0x80 != 0) {
+    return field[1..].iter().try_fold(0u64, |value, byte| {
+      value.checked_mul(256)?.checked_add(*byte as u64)
+    });
+  }
+  let text = std::str::from_utf8(field).ok()?;
+  let text = text.trim_matches(|c: char| c == '\0' || c == ' ');
+  if text.is_empty() {
+    return Some(0);
+  }
+  u64::from_str_radix(text, 8).ok()
+}
+
+fn tar_string(field: &[u8]) -> String {
+  let end = field
+    .iter()
+    .position(|byte| *byte == 0)
+    .unwrap_or(field.len());
+  String::from_utf8_lossy(&field[..end]).to_string()
+}
+
+/// The path a pax extended header sets, if it does. Records are
+/// "<length> <key>=<value>\n".
+fn pax_path(data: &[u8]) -> Option<String> {
+  let mut rest = data;
+  while !rest.is_empty() {
+    let space = rest.iter().position(|byte| *byte == b' ')?;
+    let length: usize = std::str::from_utf8(&rest[..space]).ok()?.parse().ok()?;
+    let record = rest.get(space + 1..length)?;
+    let record = record.strip_suffix(b"\n").unwrap_or(record);
+    if let Some(path) = record.strip_prefix(b"path=") {
+      return Some(String::from_utf8_lossy(path).to_string());
+    }
+    rest = &rest[length..];
+  }
+  None
+}
+
+fn tar_entries(body: &[u8]) -> Result<Vec<ArchiveEntry>, Box<dyn Error>> {
+  const CORRUPTED: &str = "Corrupted tar archive";
+
+  let mut entries = Vec::new();
+  let mut long_name: Option<String> = None;
+  let mut position = 0;
+  while let Some(header) = body.get(position..position + TAR_BLOCK) {
+    if header.iter().all(|byte| *byte == 0) {
+      break;
+    }
+    let size = tar_number(&header[124..136]).ok_or(CORRUPTED)?;
+    let start = position + TAR_BLOCK;
+    let end = usize::try_from(size)
+      .ok()
+      .and_then(|size| start.checked_add(size))
+      .filter(|end| *end <= body.len())
+      .ok_or(CORRUPTED)?;
+
+    let name = match long_name.take() {
+      Some(name) => name,
+      None => {
+        let name = tar_string(&header[0..100]);
+        let prefix = tar_string(&header[345..500]);
+        if is_tar(header) && !prefix.is_empty() {
+          format!("{prefix}/{name}")
+        } else {
+          name
+        }
+      }
+    };
+
+    match header[156] {
+      // GNU long name, and pax extended header : they apply to the next entry.
+      b'L' => long_name = Some(tar_string(&body[start..end])),
+      b'x' => long_name = pax_path(&body[start..end]),
+      // Regular files. Links, devices and directories have no content.
+      b'0' | b'\0' | b'7' => {
+        if entries.len() == MAX_ENTRIES {
+          return Err(format!("The archive has more than {} entries", MAX_ENTRIES).into());
+        }
+        entries.push(ArchiveEntry {
+          name: clean_name(&name),
+          size,
+          source: Source::Range(start, end),
+        });
+      }
+      _ => (),
+    }
+
+    position = start + end.saturating_sub(start).div_ceil(TAR_BLOCK) * TAR_BLOCK;
+  }
+  Ok(entries)
+}
+
+/// The original file name a gzip header may carry (FNAME flag).
+fn gzip_name(body: &[u8]) -> Option<String> {
+  const FEXTRA: u8 = 0x04;
+  const FNAME: u8 = 0x08;
+
+  let flags = *body.get(3)?;
+  if flags & FNAME == 0 {
+    return None;
+  }
+  let mut start = 10;
+  if flags & FEXTRA != 0 {
+    start += 2 + u16_at(body, 10)? as usize;
+  }
+  let name = body.get(start..)?;
+  let end = name.iter().position(|byte| *byte == 0)?;
+  let name = WINDOWS_1252.decode(&name[..end]).0.to_string();
+  let name = name
+    .rsplit(['/', '\\'])
+    .next()
+    .unwrap_or_default()
+    .to_string();
+  (!name.is_empty()).then_some(name)
+}
+
+/// Decompresses `data`, failing as soon as the output grows past `limit`.
+fn inflate(
+  data: &[u8],
+  format: gio::ZlibCompressorFormat,
+  limit: u64,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+  let decompressor = gio::ZlibDecompressor::new(format);
+  let mut output: Vec<u8> = Vec::new();
+  let mut buffer = vec![0u8; 64 * 1024];
+  let mut input = data;
+
+  loop {
+    let (result, read, written) =
+      decompressor.convert(input, &mut buffer[..], gio::ConverterFlags::INPUT_AT_END)?;
+    input = &input[read..];
+    if output.len() as u64 + written as u64 > limit {
+      return Err("The archive decompresses to more than it declares or than is allowed".into());
+    }
+    output.extend_from_slice(&buffer[..written]);
+
+    match result {
+      gio::ConverterResult::Finished => return Ok(output),
+      gio::ConverterResult::Error => return Err("Corrupted compressed data".into()),
+      _ if read == 0 && written == 0 => return Err("Truncated compressed data".into()),
+      _ => (),
+    }
+  }
+}
+
+fn deflate(data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+  let compressor = gio::ZlibCompressor::new(gio::ZlibCompressorFormat::Raw, -1);
+  let mut output: Vec<u8> = Vec::new();
+  let mut buffer = vec![0u8; 64 * 1024];
+  let mut input = data;
+
+  loop {
+    let (result, read, written) =
+      compressor.convert(input, &mut buffer[..], gio::ConverterFlags::INPUT_AT_END)?;
+    input = &input[read..];
+    output.extend_from_slice(&buffer[..written]);
+
+    match result {
+      gio::ConverterResult::Finished => return Ok(output),
+      gio::ConverterResult::Error => return Err("Compression failed".into()),
+      _ if read == 0 && written == 0 => return Err("Compression stalled".into()),
+      _ => (),
+    }
+  }
+}
+
+/// The local time, as the (time, date) of a zip entry. Zip dates start in 1980.
+fn dos_date_time() -> (u16, u16) {
+  let Ok(now) = gio::glib::DateTime::now_local() else {
+    return (0, 1 << 5 | 1);
+  };
+  let time = now.hour() << 11 | now.minute() << 5 | now.second() / 2;
+  let date = (now.year() - 1980).max(0) << 9 | now.month() << 5 | now.day_of_month();
+  (time as u16, date as u16)
+}
+
+fn crc32_of(data: &[u8]) -> u32 {
+  let mut crc = 0xffffffffu32;
+  for byte in data {
+    crc ^= *byte as u32;
+    for _ in 0..8 {
+      crc = if crc & 1 != 0 {
+        (crc >> 1) ^ 0xedb88320
+      } else {
+        crc >> 1
+      };
+    }
+  }
+  !crc
+}
+
+#[cfg(test)]
+mod tests {
+  use std::fs;
+
+  use crate::message::archive::*;
+
+  fn names(archive: &Archive) -> Vec<(&str, u64)> {
+    archive
+      .entries()
+      .iter()
+      .map(|entry| (entry.name.as_str(), entry.size))
+      .collect()
+  }
+
+  #[test]
+  fn zip_entries() {
+    let archive = Archive::open("archive.zip", &fs::read("tests/archive.zip").unwrap()).unwrap();
+    assert_eq!(names(&archive), vec![
+      ("docs/readme.txt", 23),
+      ("notes.txt", 1200),
+      ("../../evil.sh", 10)
+    ]);
+
+    let readme = archive.extract(0).unwrap();
+    assert_eq!(readme.body, b"Hello from the archive\n");
+    assert_eq!(readme.safe_filename(), "readme.txt");
+    assert_eq!(archive.extract(1).unwrap().body, b"notes ".repeat(200));
+
+    assert!(!archive.entries()[1].escapes());
+    assert!(archive.entries()[2].escapes());
+    assert_eq!(archive.extract(2).unwrap().safe_filename(), "evil.sh");
+  }
+
+  #[test]
+  fn tar_entries() {
+    let long_name = format!("a/{}.txt", "long".repeat(40));
+    for filename in ["archive.tar", "archive.tar.gz"] {
+      let body = fs::read(format!("tests/{filename}")).unwrap();
+      assert!(Archive::is_archive(filename, None, &body));
+      let archive = Archive::open(filename, &body).unwrap();
+      assert_eq!(names(&archive), vec![
+        ("readme.txt", 23),
+        (long_name.as_str(), 10)
+      ]);
+      assert_eq!(
+        archive.extract(0).unwrap().body,
+        b"Hello from the tarball\n"
+      );
+      assert_eq!(archive.extract(1).unwrap().body, b"long name\n");
+    }
+  }
+
+  #[test]
+  fn zip_bomb() {
+    // 10 MiB of zeros declaring 1 KiB.
+    let archive = Archive::open("bomb.zip", &fs::read("tests/bomb.zip").unwrap()).unwrap();
+    assert_eq!(names(&archive), vec![("zeros.bin", 1024)]);
+    assert!(archive.extract(0).is_err());
+  }
+
+  #[test]
+  fn zip_attachments() {
+    let attachment = |filename: &str, body: &[u8]| Attachment {
+      filename: filename.to_string(),
+      content_id: String::new(),
+      mime_type: None,
+      body: body.to_vec(),
+    };
+    let notes = b"notes ".repeat(200);
+    let attachments = [
+      attachment("Réunion.txt", b"ok"),
+      attachment("notes.txt", &notes),
+      attachment("dir/notes.txt", b""),
+    ];
+
+    let zip = Archive::zip(&attachments).unwrap();
+    let archive = Archive::open("attachments.zip", &zip).unwrap();
+    assert_eq!(names(&archive), vec![
+      ("Réunion.txt", 2),
+      ("notes.txt", 1200),
+      ("notes (2).txt", 0)
+    ]);
+    assert!(zip.len() < notes.len());
+    assert_eq!(archive.extract(0).unwrap().body, b"ok");
+    assert_eq!(archive.extract(1).unwrap().body, notes);
+    assert_eq!(archive.extract(2).unwrap().body, b"");
+
+    let empty = Archive::zip(&[]).unwrap();
+    assert!(Archive::open("empty.zip", &empty)
+      .unwrap()
+      .entries()
+      .is_empty());
+  }
+
+  #[test]
+  fn not_archives() {
+    assert!(!Archive::is_archive("setup.zip", None, b"MZ\x90\x00"));
+    let zip = fs::read("tests/archive.zip").unwrap();
+    assert!(Archive::is_archive(
+      "archive",
+      Some("application/zip"),
+      &zip
+    ));
+    assert!(!Archive::is_archive("report.docx", None, &zip));
+    assert!(!Archive::is_archive(
+      "app.apk",
+      Some("application/octet-stream"),
+      &zip
+    ));
+    assert!(Archive::open("fake.zip", b"PK\x03\x04garbage").is_err());
+    assert!(Archive::open("fake.tar", b"hello").is_err());
+    // Offsets read from an archive can be anything.
+    assert_eq!(u32_at(b"PK\x05\x06", usize::MAX - 2), None);
+    assert_eq!(u64_at(b"PK\x05\x06", usize::MAX - 4), None);
+  }
+}
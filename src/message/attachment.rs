@@ -17,15 +17,29 @@
  *
  * SPDX-License-Identifier: GPL-3.0-or-later
  */
+use std::collections::HashSet;
 use std::error::Error;
 use std::fmt;
 
+use uuid::Uuid;
+
 use super::message::TEMP_FOLDER;
+use super::preview::{decode_text, PreviewKind};
+use super::risk::Risk;
+use super::vcard::Contact;
 use crate::gio::prelude::*;
 use crate::{gio, glib};
 
 const DEFAULT_FILENAME: &str = "attachment";
 
+/// The digests of an attachment, as lowercase hex strings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hashes {
+  pub sha256: String,
+  pub sha1: String,
+  pub md5: String,
+}
+
 #[derive(Debug, Clone)]
 pub struct Attachment {
   pub filename: String,
@@ -49,13 +63,77 @@ impl Attachment {
     name.to_string()
   }
 
+  /// The `safe_filename()` of each attachment, numbered when it is taken
+  /// already, by an attachment before it or by a file `taken` tells about:
+  /// "report.pdf", then "report (2).pdf". Names are compared without case,
+  /// not every file system tells them apart.
+  pub fn unique_filenames<F: Fn(&str) -> bool>(
+    attachments: &[Attachment],
+    taken: F,
+  ) -> Vec<String> {
+    let mut used: HashSet<String> = HashSet::new();
+    attachments
+      .iter()
+      .map(|attachment| {
+        let name = attachment.safe_filename();
+        let mut unique = name.clone();
+        let mut number = 1;
+        while used.contains(&unique.to_lowercase()) || taken(&unique) {
+          number += 1;
+          unique = numbered_filename(&name, number);
+        }
+        used.insert(unique.to_lowercase());
+        unique
+      })
+      .collect()
+  }
+
+  /// How dangerous opening the attachment could be, judging by its name, its
+  /// declared type and its content.
+  pub fn risk(&self) -> Risk {
+    Risk::classify(&self.filename, self.mime_type.as_deref(), &self.body)
+  }
+
+  /// How the attachment can be shown inside the viewer, if it can.
+  pub fn preview_kind(&self) -> Option<PreviewKind> {
+    PreviewKind::for_attachment(&self.filename, self.mime_type.as_deref(), &self.body)
+  }
+
+  /// The contacts of a vCard attachment, none for any other.
+  pub fn contacts(&self) -> Vec<Contact> {
+    if !Contact::is_vcard(&self.filename, self.mime_type.as_deref()) {
+      return vec![];
+    }
+    Contact::parse(&decode_text(&self.body))
+  }
+
+  pub fn hashes(&self) -> Hashes {
+    let digest = |checksum_type| {
+      glib::compute_checksum_for_data(checksum_type, &self.body)
+        .map(|digest| digest.to_string())
+        .unwrap_or_default()
+    };
+    Hashes {
+      sha256: digest(glib::ChecksumType::Sha256),
+      sha1: digest(glib::ChecksumType::Sha1),
+      md5: digest(glib::ChecksumType::Md5),
+    }
+  }
+
+  /// Writes the attachment under its `safe_filename()`, in a folder of its
+  /// own in TEMP_FOLDER : attachments of the same name don't replace each
+  /// other, even while an application still has one open.
   pub async fn write_to_tmp(&self) -> Result<gio::File, Box<dyn Error>> {
     let tmp = gio::File::for_path(TEMP_FOLDER.to_str().unwrap());
     if file_exists(&tmp).await.is_ok_and(|v| !v) {
       log::debug!("create_dir({:?})", tmp);
       tmp.make_directory_future(glib::Priority::default()).await?;
     }
-    let tmp = tmp.child(self.safe_filename());
+    let folder = tmp.child(Uuid::new_v4().simple().to_string());
+    folder
+      .make_directory_future(glib::Priority::default())
+      .await?;
+    let tmp = folder.child(self.safe_filename());
     log::debug!("write_to_tmp({:?})", tmp);
     self.write_to_file(&tmp).await?;
     Ok(tmp)
@@ -113,6 +191,15 @@ impl fmt::Display for Attachment {
   }
 }
 
+/// `name` with its number before the extension. A leading dot starts a
+/// hidden name, not an extension.
+fn numbered_filename(name: &str, number: usize) -> String {
+  match name.rfind('.').filter(|index| *index > 0) {
+    Some(index) => format!("{} ({}){}", &name[..index], number, &name[index..]),
+    None => format!("{name} ({number})"),
+  }
+}
+
 async fn file_exists(file: &gio::File) -> Result<bool, Box<dyn Error>> {
   match file
     .query_info_future(
@@ -167,6 +254,18 @@ mod tests {
     std::fs::remove_file(&path).unwrap();
   }
 
+  #[test]
+  fn hashes_of_the_body() {
+    let mut attachment = attachment("abc.txt");
+    attachment.body = b"abc".to_vec();
+
+    assert_eq!(attachment.hashes(), Hashes {
+      sha256: "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_string(),
+      sha1: "a9993e364706816aba3e25717850c26c9cd0d89d".to_string(),
+      md5: "900150983cd24fb0d6963f7d28e17f72".to_string(),
+    });
+  }
+
   #[test]
   fn safe_filename_keeps_regular_names() {
     assert_eq!(
@@ -199,4 +298,27 @@ mod tests {
   fn safe_filename_strips_control_characters() {
     assert_eq!(attachment("a\nb\tc.png").safe_filename(), "abc.png");
   }
+
+  #[test]
+  fn unique_filenames_are_numbered() {
+    let attachments = [
+      attachment("report.pdf"),
+      attachment("a/Report.pdf"),
+      attachment("report.pdf"),
+      attachment(".profile"),
+      attachment(".profile"),
+      attachment("notes"),
+    ];
+    assert_eq!(
+      Attachment::unique_filenames(&attachments, |name| name == "notes"),
+      [
+        "report.pdf",
+        "Report (2).pdf",
+        "report (3).pdf",
+        ".profile",
+        ".profile (2)",
+        "notes (2)",
+      ]
+    );
+  }
 }
@@ -0,0 +1,1092 @@
+/* calendar.rs
+ *
+ * Copyright 2024 Alexandre Del Bigio
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use gettextrs::{gettext, ngettext};
+
+use super::contentline::{unfold, Property};
+use crate::glib;
+
+const WEEKDAYS: [&str; 7] = ["MO", "TU", "WE", "TH", "FR", "SA", "SU"];
+
+/// Lines of an iCalendar file are folded past this many octets (RFC 5545).
+const MAX_LINE_LENGTH: usize = 75;
+
+/// The Windows names of time zones Outlook writes in a TZID without
+/// describing them, and the IANA zone of each, from the CLDR windowsZones
+/// table.
+const WINDOWS_ZONES: [(&str, &str); 52] = [
+  ("Dateline Standard Time", "Etc/GMT+12"),
+  ("Hawaiian Standard Time", "Pacific/Honolulu"),
+  ("Alaskan Standard Time", "America/Anchorage"),
+  ("Pacific Standard Time", "America/Los_Angeles"),
+  ("US Mountain Standard Time", "America/Phoenix"),
+  ("Mountain Standard Time", "America/Denver"),
+  ("Central America Standard Time", "America/Guatemala"),
+  ("Central Standard Time", "America/Chicago"),
+  ("Central Standard Time (Mexico)", "America/Mexico_City"),
+  ("Canada Central Standard Time", "America/Regina"),
+  ("SA Pacific Standard Time", "America/Bogota"),
+  ("Eastern Standard Time", "America/New_York"),
+  ("US Eastern Standard Time", "America/Indianapolis"),
+  ("Venezuela Standard Time", "America/Caracas"),
+  ("Atlantic Standard Time", "America/Halifax"),
+  ("Pacific SA Standard Time", "America/Santiago"),
+  ("Newfoundland Standard Time", "America/St_Johns"),
+  ("E. South America Standard Time", "America/Sao_Paulo"),
+  ("Argentina Standard Time", "America/Buenos_Aires"),
+  ("UTC", "Etc/UTC"),
+  ("GMT Standard Time", "Europe/London"),
+  ("Greenwich Standard Time", "Atlantic/Reykjavik"),
+  ("Morocco Standard Time", "Africa/Casablanca"),
+  ("W. Europe Standard Time", "Europe/Berlin"),
+  ("Central Europe Standard Time", "Europe/Budapest"),
+  ("Romance Standard Time", "Europe/Paris"),
+  ("Central European Standard Time", "Europe/Warsaw"),
+  ("W. Central Africa Standard Time", "Africa/Lagos"),
+  ("GTB Standard Time", "Europe/Bucharest"),
+  ("E. Europe Standard Time", "Europe/Chisinau"),
+  ("FLE Standard Time", "Europe/Kiev"),
+  ("Egypt Standard Time", "Africa/Cairo"),
+  ("South Africa Standard Time", "Africa/Johannesburg"),
+  ("Israel Standard Time", "Asia/Jerusalem"),
+  ("Turkey Standard Time", "Europe/Istanbul"),
+  ("Russian Standard Time", "Europe/Moscow"),
+  ("Arab Standard Time", "Asia/Riyadh"),
+  ("Iran Standard Time", "Asia/Tehran"),
+  ("Arabian Standard Time", "Asia/Dubai"),
+  ("Pakistan Standard Time", "Asia/Karachi"),
+  ("India Standard Time", "Asia/Calcutta"),
+  ("Bangladesh Standard Time", "Asia/Dhaka"),
+  ("SE Asia Standard Time", "Asia/Bangkok"),
+  ("China Standard Time", "Asia/Shanghai"),
+  ("Singapore Standard Time", "Asia/Singapore"),
+  ("Taipei Standard Time", "Asia/Taipei"),
+  ("Tokyo Standard Time", "Asia/Tokyo"),
+  ("Korea Standard Time", "Asia/Seoul"),
+  ("W. Australia Standard Time", "Australia/Perth"),
+  ("Cen. Australia Standard Time", "Australia/Adelaide"),
+  ("AUS Eastern Standard Time", "Australia/Sydney"),
+  ("New Zealand Standard Time", "Pacific/Auckland"),
+];
+
+/// The IANA name of a zone, for the Windows names Outlook uses.
+pub fn iana_zone(zone: &str) -> &str {
+  WINDOWS_ZONES
+    .iter()
+    .find(|(windows, _)| *windows == zone)
+    .map_or(zone, |(_, iana)| iana)
+}
+
+/// A date and a wall clock time, without a zone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Civil {
+  pub year: i32,
+  pub month: i32,
+  pub day: i32,
+  pub hour: i32,
+  pub minute: i32,
+  pub second: i32,
+}
+
+impl Civil {
+  /// "20240315" or "20240315T093000", optionally followed by a Z.
+  fn parse(value: &str) -> Option<Self> {
+    let value = value.trim().trim_end_matches('Z');
+    let (date, time) = match value.split_once('T') {
+      Some((date, time)) => (date, time),
+      None => (value, "000000"),
+    };
+    if date.len() != 8 || time.len() != 6 {
+      return None;
+    }
+    let number = |text: &str| -> Option<i32> {
+      text
+        .bytes()
+        .all(|byte| byte.is_ascii_digit())
+        .then(|| text.parse().ok())
+        .flatten()
+    };
+    let civil = Self {
+      year: number(&date[0..4])?,
+      month: number(&date[4..6])?,
+      day: number(&date[6..8])?,
+      hour: number(&time[0..2])?,
+      minute: number(&time[2..4])?,
+      second: number(&time[4..6])?,
+    };
+    let valid = (1..=12).contains(&civil.month)
+      && (1..=days_in_month(civil.year, civil.month)).contains(&civil.day)
+      && civil.hour < 24
+      && civil.minute < 60
+      && civil.second <= 60;
+    valid.then_some(civil)
+  }
+
+  fn from_unix(time: i64) -> Self {
+    let (year, month, day) = civil_from_days(time.div_euclid(86400));
+    let seconds = time.rem_euclid(86400) as i32;
+    Self {
+      year,
+      month,
+      day,
+      hour: seconds / 3600,
+      minute: seconds % 3600 / 60,
+      second: seconds % 60,
+    }
+  }
+
+  /// Seconds since the Unix epoch, reading the time as UTC.
+  fn unix(&self) -> i64 {
+    days_from_civil(self.year, self.month, self.day) * 86400
+      + (self.hour * 3600 + self.minute * 60 + self.second) as i64
+  }
+
+  pub fn date_string(&self) -> String {
+    format!("{:04}-{:02}-{:02}", self.year, self.month, self.day)
+  }
+
+  fn ics_date(&self) -> String {
+    format!("{:04}{:02}{:02}", self.year, self.month, self.day)
+  }
+
+  fn ics_date_time(&self) -> String {
+    format!(
+      "{}T{:02}{:02}{:02}",
+      self.ics_date(),
+      self.hour,
+      self.minute,
+      self.second
+    )
+  }
+}
+
+/// Days since 1970-01-01, for a date of the proleptic Gregorian calendar.
+fn days_from_civil(year: i32, month: i32, day: i32) -> i64 {
+  let year = if month <= 2 { year - 1 } else { year } as i64;
+  let era = year.div_euclid(400);
+  let year_of_era = year - era * 400;
+  let month = month as i64;
+  let day_of_year =
+    (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+  let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+  era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i32, i32, i32) {
+  let days = days + 719468;
+  let era = days.div_euclid(146097);
+  let day_of_era = days - era * 146097;
+  let year_of_era =
+    (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+  let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  let month = (5 * day_of_year + 2) / 153;
+  let day = day_of_year - (153 * month + 2) / 5 + 1;
+  let month = if month < 10 { month + 3 } else { month - 9 };
+  let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+  (year as i32, month as i32, day as i32)
+}
+
+fn days_in_month(year: i32, month: i32) -> i32 {
+  match month {
+    2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+    2 => 28,
+    4 | 6 | 9 | 11 => 30,
+    _ => 31,
+  }
+}
+
+/// 0 for Monday, as in WEEKDAYS.
+fn weekday(year: i32, month: i32, day: i32) -> i32 {
+  // 1970-01-01 was a Thursday.
+  (days_from_civil(year, month, day) + 3).rem_euclid(7) as i32
+}
+
+/// The day of the month of its `nth` `weekday`, counting from its end when
+/// `nth` is negative.
+fn nth_weekday(year: i32, month: i32, nth: i32, weekday_index: i32) -> Option<i32> {
+  let last = days_in_month(year, month);
+  let day = if nth > 0 {
+    1 + (weekday_index - weekday(year, month, 1)).rem_euclid(7) + (nth - 1) * 7
+  } else {
+    last - (weekday(year, month, last) - weekday_index).rem_euclid(7) + (nth + 1) * 7
+  };
+  (1..=last).contains(&day).then_some(day)
+}
+
+/// "SU", "2SU" or "-1SU" from a BYDAY rule part.
+fn parse_weekday(value: &str) -> Option<(Option<i32>, i32)> {
+  let split = value.len().checked_sub(2)?;
+  let code = value.get(split..)?;
+  let day = WEEKDAYS.iter().position(|weekday| *weekday == code)? as i32;
+  let nth = &value[..split];
+  if nth.is_empty() {
+    return Some((None, day));
+  }
+  Some((Some(nth.trim_start_matches('+').parse().ok()?), day))
+}
+
+/// "+0100", "-0500" or "+053000", in seconds.
+fn parse_offset(value: &str) -> Option<i32> {
+  let value = value.trim();
+  let sign = match value.get(..1)? {
+    "+" => 1,
+    "-" => -1,
+    _ => return None,
+  };
+  let digits = &value[1..];
+  let part = |range: std::ops::Range<usize>| digits.get(range)?.parse::<i32>().ok();
+  let seconds = match digits.len() {
+    4 => part(0..2)? * 3600 + part(2..4)? * 60,
+    6 => part(0..2)? * 3600 + part(2..4)? * 60 + part(4..6)?,
+    _ => return None,
+  };
+  Some(sign * seconds)
+}
+
+/// "+0100" or "-0500", as in TZOFFSETFROM and TZOFFSETTO.
+fn ics_offset(offset: i32) -> String {
+  let sign = if offset < 0 { '-' } else { '+' };
+  let offset = offset.abs();
+  let (hours, minutes, seconds) = (offset / 3600, offset % 3600 / 60, offset % 60);
+  if seconds == 0 {
+    format!("{sign}{hours:02}{minutes:02}")
+  } else {
+    format!("{sign}{hours:02}{minutes:02}{seconds:02}")
+  }
+}
+
+/// A VTIMEZONE for a zone the invitation doesn't describe but glib knows,
+/// repeating every year the changes of offset it goes through in `year`.
+fn describe_zone(id: &str, year: i32) -> Option<Vec<String>> {
+  let zone = glib::TimeZone::from_identifier(Some(iana_zone(id)))?;
+  let offset_at = |time: i64| zone.offset(zone.find_interval(glib::TimeType::Universal, time));
+  let new_year = |year| {
+    Civil {
+      year,
+      month: 1,
+      day: 1,
+      hour: 0,
+      minute: 0,
+      second: 0,
+    }
+    .unix()
+  };
+
+  let mut lines = vec!["BEGIN:VTIMEZONE".to_string(), format!("TZID:{id}")];
+  let mut observances = 0;
+  let mut day = new_year(year);
+  while day < new_year(year + 1) {
+    let (from, to) = (offset_at(day), offset_at(day + 86400));
+    if from != to {
+      // The first second of the new offset.
+      let (mut before, mut after) = (day, day + 86400);
+      while after - before > 1 {
+        let middle = before + (after - before) / 2;
+        if offset_at(middle) == from {
+          before = middle;
+        } else {
+          after = middle;
+        }
+      }
+      let onset = Civil::from_unix(after + from as i64);
+      let nth = if onset.day + 7 > days_in_month(onset.year, onset.month) {
+        -1
+      } else {
+        (onset.day - 1) / 7 + 1
+      };
+      let weekday = WEEKDAYS[weekday(onset.year, onset.month, onset.day) as usize];
+      let kind = if to > from { "DAYLIGHT" } else { "STANDARD" };
+      lines.extend([
+        format!("BEGIN:{kind}"),
+        format!("DTSTART:{}", onset.ics_date_time()),
+        format!("TZOFFSETFROM:{}", ics_offset(from)),
+        format!("TZOFFSETTO:{}", ics_offset(to)),
+        format!(
+          "RRULE:FREQ=YEARLY;BYMONTH={};BYDAY={nth}{weekday}",
+          onset.month
+        ),
+        format!("END:{kind}"),
+      ]);
+      observances += 1;
+    }
+    day += 86400;
+  }
+  if observances == 0 {
+    let offset = ics_offset(offset_at(new_year(year)));
+    lines.extend([
+      "BEGIN:STANDARD".to_string(),
+      "DTSTART:19700101T000000".to_string(),
+      format!("TZOFFSETFROM:{offset}"),
+      format!("TZOFFSETTO:{offset}"),
+      "END:STANDARD".to_string(),
+    ]);
+  }
+  lines.push("END:VTIMEZONE".to_string());
+  Some(lines)
+}
+
+/// When an event takes place.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventTime {
+  /// A whole day.
+  Date(Civil),
+  /// An instant, as seconds since the Unix epoch.
+  Utc(i64),
+  /// A wall clock time in a zone the invitation describes, and the instant it
+  /// is.
+  Described(Civil, String, i64),
+  /// A wall clock time in a zone the invitation doesn't describe, usually an
+  /// IANA one such as Europe/Paris.
+  Zoned(Civil, String),
+  /// The same wall clock time wherever the reader is.
+  Floating(Civil),
+}
+
+impl EventTime {
+  /// The "2024-03-15T09:30:00Z" or "2024-03-15T09:30:00.000Z" form Outlook
+  /// items have their times in.
+  pub fn from_iso8601(value: &str) -> Option<Self> {
+    let value = value.trim();
+    if !value.ends_with('Z') {
+      return None;
+    }
+    let value = value.trim_end_matches('Z');
+    let value = value.split('.').next().unwrap_or_default();
+    let value: String = value.chars().filter(|c| *c != '-' && *c != ':').collect();
+    Civil::parse(&value).map(|civil| Self::Utc(civil.unix()))
+  }
+
+  fn ics_property(&self, name: &str) -> String {
+    match self {
+      Self::Date(civil) => format!("{name};VALUE=DATE:{}", civil.ics_date()),
+      Self::Utc(time) => format!("{name}:{}Z", Civil::from_unix(*time).ics_date_time()),
+      Self::Described(civil, zone, _) | Self::Zoned(civil, zone) => {
+        format!("{name};TZID={zone}:{}", civil.ics_date_time())
+      }
+      Self::Floating(civil) => format!("{name}:{}", civil.ics_date_time()),
+    }
+  }
+}
+
+fn rule_parts(rule: &str) -> Vec<(String, String)> {
+  rule
+    .split(';')
+    .filter_map(|part| part.split_once('='))
+    .map(|(key, value)| (key.trim().to_uppercase(), value.trim().to_uppercase()))
+    .collect()
+}
+
+fn rule_part<'a>(parts: &'a [(String, String)], key: &str) -> Option<&'a str> {
+  parts
+    .iter()
+    .find(|(name, _)| name == key)
+    .map(|(_, value)| value.as_str())
+}
+
+/// A STANDARD or DAYLIGHT part of a VTIMEZONE.
+#[derive(Debug)]
+struct Observance {
+  start: Civil,
+  offset_from: i32,
+  offset_to: i32,
+  month: Option<i32>,
+  weekday: Option<(i32, i32)>,
+  until: Option<Civil>,
+}
+
+impl Observance {
+  fn from_properties(properties: &[Property]) -> Option<Self> {
+    let property = |name: &str| properties.iter().find(|property| property.name == name);
+    let rule = property("RRULE")
+      .map(|rule| rule_parts(&rule.value))
+      .unwrap_or_default();
+    let weekday = rule_part(&rule, "BYDAY")
+      .and_then(parse_weekday)
+      .map(|(nth, day)| (nth.unwrap_or(1), day));
+    Some(Self {
+      start: Civil::parse(&property("DTSTART")?.value)?,
+      offset_from: parse_offset(&property("TZOFFSETFROM")?.value)?,
+      offset_to: parse_offset(&property("TZOFFSETTO")?.value)?,
+      month: rule_part(&rule, "BYMONTH").and_then(|month| month.parse().ok()),
+      weekday,
+      until: rule_part(&rule, "UNTIL").and_then(Civil::parse),
+    })
+  }
+
+  /// When the observance begins in `year`, if it does.
+  fn onset(&self, year: i32) -> Option<Civil> {
+    if year < self.start.year {
+      return None;
+    }
+    let onset = match (self.month, self.weekday) {
+      (Some(month), Some((nth, weekday))) => Civil {
+        year,
+        month,
+        day: nth_weekday(year, month, nth, weekday)?,
+        ..self.start
+      },
+      (Some(month), None) => Civil {
+        year,
+        month,
+        ..self.start
+      },
+      _ if year == self.start.year => self.start,
+      _ => return None,
+    };
+    let until = self.until.unwrap_or(onset);
+    (onset >= self.start && onset <= until).then_some(onset)
+  }
+}
+
+#[derive(Debug)]
+struct TimeZone {
+  id: String,
+  observances: Vec<Observance>,
+  /// The content lines of the VTIMEZONE, to export it as it is.
+  lines: Vec<String>,
+}
+
+impl TimeZone {
+  /// Reads a wall clock time of the zone as UTC, using the observance which
+  /// started last before it.
+  fn to_utc(&self, civil: &Civil) -> i64 {
+    let latest = self
+      .observances
+      .iter()
+      .flat_map(|observance| {
+        [civil.year, civil.year - 1]
+          .into_iter()
+          .filter_map(|year| observance.onset(year))
+          .chain(
+            (observance.month.is_none() && observance.start.year < civil.year - 1)
+              .then_some(observance.start),
+          )
+          .filter(|onset| onset <= civil)
+          .map(move |onset| (onset, observance.offset_to))
+      })
+      .max_by_key(|(onset, _)| *onset);
+    let offset = match latest {
+      Some((_, offset)) => offset,
+      // Before the zone is described: what it was changing from.
+      None => self
+        .observances
+        .iter()
+        .min_by_key(|observance| observance.start)
+        .map_or(0, |observance| observance.offset_from),
+    };
+    civil.unix() - offset as i64
+  }
+}
+
+/// Someone taking part in an event, as an ORGANIZER or an ATTENDEE.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Participant {
+  pub name: String,
+  pub email: String,
+  /// The PARTSTAT of an attendee: ACCEPTED, DECLINED, TENTATIVE...
+  pub status: Option<String>,
+}
+
+impl Participant {
+  fn from_property(property: &Property) -> Self {
+    let value = property.value.trim();
+    let email = match value.get(..7) {
+      Some(scheme) if scheme.eq_ignore_ascii_case("mailto:") => &value[7..],
+      _ => value,
+    };
+    Self {
+      name: property.param("CN").unwrap_or_default().to_string(),
+      email: email.to_string(),
+      status: property
+        .param("PARTSTAT")
+        .map(|status| status.to_uppercase()),
+    }
+  }
+
+  pub fn label(&self) -> String {
+    if self.name.is_empty() || self.name == self.email {
+      return self.email.clone();
+    }
+    if self.email.is_empty() {
+      return self.name.clone();
+    }
+    format!("{} <{}>", self.name, self.email)
+  }
+
+  pub fn status_label(&self) -> Option<String> {
+    match self.status.as_deref()? {
+      "ACCEPTED" => Some(gettext("Accepted")),
+      "DECLINED" => Some(gettext("Declined")),
+      "TENTATIVE" => Some(gettext("Tentative")),
+      "DELEGATED" => Some(gettext("Delegated")),
+      _ => None,
+    }
+  }
+
+  fn ics_property(&self, name: &str) -> String {
+    let mut line = name.to_string();
+    if !self.name.is_empty() {
+      line.push_str(&format!(";CN=\"{}\"", self.name.replace('"', "")));
+    }
+    if let Some(status) = &self.status {
+      line.push_str(&format!(";PARTSTAT={status}"));
+    }
+    format!("{line}:mailto:{}", self.email)
+  }
+}
+
+/// A VEVENT of an invitation, or the meeting an Outlook item describes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CalendarEvent {
+  pub uid: String,
+  /// The METHOD of the calendar: REQUEST, REPLY, CANCEL...
+  pub method: Option<String>,
+  pub summary: String,
+  pub description: String,
+  pub location: String,
+  pub organizer: Option<Participant>,
+  pub attendees: Vec<Participant>,
+  pub start: Option<EventTime>,
+  pub end: Option<EventTime>,
+  pub rrule: Option<String>,
+  /// The content lines of the VTIMEZONE components its times refer to.
+  pub time_zones: Vec<String>,
+  /// Whether the event repeats when its RRULE is not known, as with Outlook
+  /// items.
+  pub recurring: bool,
+  pub cancelled: bool,
+}
+
+impl CalendarEvent {
+  /// The events of an iCalendar file.
+  pub fn parse(ics: &str) -> Vec<Self> {
+    let lines = unfold(ics);
+    let mut method = None;
+    let mut events: Vec<Vec<Property>> = vec![];
+    let mut zones: Vec<TimeZone> = vec![];
+    let mut observance: Vec<Property> = vec![];
+    let mut components: Vec<String> = vec![];
+    for (line, property) in lines
+      .iter()
+      .filter_map(|line| Some((line, Property::parse(line)?)))
+    {
+      let component = property.value.trim().to_uppercase();
+      match property.name.as_str() {
+        "BEGIN" => {
+          match component.as_str() {
+            "VEVENT" => events.push(vec![]),
+            "VTIMEZONE" => zones.push(TimeZone {
+              id: String::new(),
+              observances: vec![],
+              lines: vec![],
+            }),
+            "STANDARD" | "DAYLIGHT" => observance.clear(),
+            _ => (),
+          }
+          components.push(component);
+          keep_zone_line(&mut zones, &components, line);
+          continue;
+        }
+        "END" => {
+          if matches!(component.as_str(), "STANDARD" | "DAYLIGHT") {
+            if let (Some(zone), Some(observance)) =
+              (zones.last_mut(), Observance::from_properties(&observance))
+            {
+              zone.observances.push(observance);
+            }
+          }
+          keep_zone_line(&mut zones, &components, line);
+          components.pop();
+          continue;
+        }
+        _ => keep_zone_line(&mut zones, &components, line),
+      }
+
+      match components.last().map(String::as_str) {
+        Some("VCALENDAR") if property.name == "METHOD" => {
+          method = Some(property.value.trim().to_uppercase())
+        }
+        Some("VEVENT") => events.last_mut().unwrap().push(property),
+        Some("VTIMEZONE") if property.name == "TZID" => {
+          zones.last_mut().unwrap().id = property.value.trim().to_string()
+        }
+        Some("STANDARD" | "DAYLIGHT") => observance.push(property),
+        _ => (),
+      }
+    }
+
+    events
+      .iter()
+      .map(|properties| Self::from_properties(properties, method.clone(), &zones))
+      .collect()
+  }
+
+  fn from_properties(properties: &[Property], method: Option<String>, zones: &[TimeZone]) -> Self {
+    let time = |property: &Property| -> Option<EventTime> {
+      let civil = Civil::parse(&property.value)?;
+      let value = property.value.trim();
+      if property.param("VALUE") == Some("DATE") || !value.contains('T') {
+        return Some(EventTime::Date(civil));
+      }
+      if value.ends_with('Z') {
+        return Some(EventTime::Utc(civil.unix()));
+      }
+      Some(match property.param("TZID") {
+        Some(tzid) => match zones.iter().find(|zone| zone.id == tzid) {
+          Some(zone) => EventTime::Described(civil, zone.id.clone(), zone.to_utc(&civil)),
+          None => EventTime::Zoned(civil, tzid.to_string()),
+        },
+        None => EventTime::Floating(civil),
+      })
+    };
+
+    let mut event = Self {
+      method,
+      ..Default::default()
+    };
+    for property in properties {
+      match property.name.as_str() {
+        "UID" => event.uid = property.value.trim().to_string(),
+        "SUMMARY" => event.summary = property.text(),
+        "DESCRIPTION" => event.description = property.text(),
+        "LOCATION" => event.location = property.text(),
+        "ORGANIZER" => event.organizer = Some(Participant::from_property(property)),
+        "ATTENDEE" => event.attendees.push(Participant::from_property(property)),
+        "DTSTART" => event.start = time(property),
+        "DTEND" => event.end = time(property),
+        "RRULE" => event.rrule = Some(property.value.trim().to_string()),
+        "STATUS" => event.cancelled = property.value.trim().eq_ignore_ascii_case("CANCELLED"),
+        _ => (),
+      }
+    }
+    event.recurring = event.rrule.is_some();
+    for zone in zones {
+      let used = [&event.start, &event.end]
+        .into_iter()
+        .any(|time| matches!(time, Some(EventTime::Described(_, id, _)) if *id == zone.id));
+      if used {
+        event.time_zones.extend(zone.lines.iter().cloned());
+      }
+    }
+    event
+  }
+
+  /// Whether an invitation has been called off.
+  pub fn is_cancelled(&self) -> bool {
+    self.cancelled || self.method.as_deref() == Some("CANCEL")
+  }
+
+  /// How the event repeats, in words.
+  pub fn recurrence(&self) -> Option<String> {
+    let Some(rrule) = &self.rrule else {
+      return self.recurring.then(|| gettext("Repeats"));
+    };
+    let parts = rule_parts(rrule);
+    let interval: u32 = rule_part(&parts, "INTERVAL")
+      .and_then(|interval| interval.parse().ok())
+      .unwrap_or(1)
+      .max(1);
+    let mut label = match rule_part(&parts, "FREQ") {
+      Some("DAILY") => ngettext("Every day", "Every {interval} days", interval),
+      Some("WEEKLY") => ngettext("Every week", "Every {interval} weeks", interval),
+      Some("MONTHLY") => ngettext("Every month", "Every {interval} months", interval),
+      Some("YEARLY") => ngettext("Every year", "Every {interval} years", interval),
+      _ => return Some(rrule.clone()),
+    }
+    .replace("{interval}", &interval.to_string());
+
+    let days: Vec<String> = rule_part(&parts, "BYDAY")
+      .unwrap_or_default()
+      .split(',')
+      .filter_map(parse_weekday)
+      .map(|(nth, day)| weekday_label(nth, day))
+      .collect();
+    if !days.is_empty() {
+      label = gettext("{recurrence} on {days}")
+        .replace("{recurrence}", &label)
+        .replace("{days}", &days.join(", "));
+    }
+
+    if let Some(count) = rule_part(&parts, "COUNT").and_then(|count| count.parse::<u32>().ok()) {
+      label = ngettext("{recurrence}, once", "{recurrence}, {count} times", count)
+        .replace("{recurrence}", &label)
+        .replace("{count}", &count.to_string());
+    } else if let Some(until) = rule_part(&parts, "UNTIL").and_then(Civil::parse) {
+      label = gettext("{recurrence}, until {date}")
+        .replace("{recurrence}", &label)
+        .replace("{date}", &until.date_string());
+    }
+    Some(label)
+  }
+
+  /// The event alone, as an iCalendar file calendars can import. Times keep
+  /// their wall clock and zone, so that a repeating event stays at the same
+  /// hour across daylight saving time changes.
+  pub fn to_ics(&self) -> String {
+    let now = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map_or(0, |duration| duration.as_secs() as i64);
+    let stamp = EventTime::Utc(now);
+    let uid = if self.uid.is_empty() {
+      format!("{now}@mailviewer")
+    } else {
+      self.uid.clone()
+    };
+
+    let mut lines = vec![
+      "BEGIN:VCALENDAR".to_string(),
+      "VERSION:2.0".to_string(),
+      "PRODID:-//MailViewer//EN".to_string(),
+      "METHOD:PUBLISH".to_string(),
+    ];
+    lines.extend(self.time_zones.iter().cloned());
+    let mut described: Vec<&str> = vec![];
+    for time in self.start.iter().chain(self.end.iter()) {
+      if let EventTime::Zoned(civil, zone) = time {
+        if !described.contains(&zone.as_str()) {
+          described.push(zone);
+          lines.extend(describe_zone(zone, civil.year).unwrap_or_default());
+        }
+      }
+    }
+    lines.extend([
+      "BEGIN:VEVENT".to_string(),
+      format!("UID:{uid}"),
+      stamp.ics_property("DTSTAMP"),
+    ]);
+    lines.extend(self.start.iter().map(|start| start.ics_property("DTSTART")));
+    lines.extend(self.end.iter().map(|end| end.ics_property("DTEND")));
+    for (name, text) in [
+      ("SUMMARY", &self.summary),
+      ("LOCATION", &self.location),
+      ("DESCRIPTION", &self.description),
+    ] {
+      if !text.is_empty() {
+        lines.push(format!("{name}:{}", escape_text(text)));
+      }
+    }
+    lines.extend(
+      self
+        .organizer
+        .iter()
+        .map(|organizer| organizer.ics_property("ORGANIZER")),
+    );
+    lines.extend(
+      self
+        .attendees
+        .iter()
+        .map(|attendee| attendee.ics_property("ATTENDEE")),
+    );
+    lines.extend(self.rrule.iter().map(|rrule| format!("RRULE:{rrule}")));
+    if self.is_cancelled() {
+      lines.push("STATUS:CANCELLED".to_string());
+    }
+    lines.push("END:VEVENT".to_string());
+    lines.push("END:VCALENDAR".to_string());
+
+    lines.iter().map(|line| fold(line) + "\r\n").collect()
+  }
+}
+
+/// Adds the events not already in `events`: the same invitation often comes as
+/// an inline part and as an .ics attachment.
+pub fn merge(events: &mut Vec<CalendarEvent>, others: Vec<CalendarEvent>) {
+  for other in others {
+    let known = events
+      .iter()
+      .any(|event| !event.uid.is_empty() && event.uid == other.uid && event.start == other.start);
+    if !known {
+      events.push(other);
+    }
+  }
+}
+
+/// Keeps the lines of the VTIMEZONE being read.
+fn keep_zone_line(zones: &mut [TimeZone], components: &[String], line: &str) {
+  if components.iter().any(|component| component == "VTIMEZONE") {
+    if let Some(zone) = zones.last_mut() {
+      zone.lines.push(line.to_string());
+    }
+  }
+}
+
+fn weekday_label(nth: Option<i32>, day: i32) -> String {
+  let name = match day {
+    0 => gettext("Monday"),
+    1 => gettext("Tuesday"),
+    2 => gettext("Wednesday"),
+    3 => gettext("Thursday"),
+    4 => gettext("Friday"),
+    5 => gettext("Saturday"),
+    _ => gettext("Sunday"),
+  };
+  let nth = match nth {
+    Some(1) => gettext("the first {day}"),
+    Some(2) => gettext("the second {day}"),
+    Some(3) => gettext("the third {day}"),
+    Some(4) => gettext("the fourth {day}"),
+    Some(-1) => gettext("the last {day}"),
+    _ => return name,
+  };
+  nth.replace("{day}", &name)
+}
+
+fn escape_text(text: &str) -> String {
+  text
+    .replace('\\', "\\\\")
+    .replace(';', "\\;")
+    .replace(',', "\\,")
+    .replace("\r\n", "\\n")
+    .replace('\n', "\\n")
+}
+
+fn fold(line: &str) -> String {
+  let mut folded = String::with_capacity(line.len());
+  let mut length = 0;
+  for c in line.chars() {
+    if length + c.len_utf8() > MAX_LINE_LENGTH {
+      folded.push_str("\r\n ");
+      length = 1;
+    }
+    folded.push(c);
+    length += c.len_utf8();
+  }
+  folded
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::message::calendar::*;
+
+  const INVITATION: &str = "BEGIN:VCALENDAR\r
+PRODID:-//Google Inc//Google Calendar 70.9054//EN\r
+VERSION:2.0\r
+METHOD:REQUEST\r
+BEGIN:VTIMEZONE\r
+TZID:Romance Standard Time\r
+BEGIN:STANDARD\r
+DTSTART:16010101T030000\r
+TZOFFSETFROM:+0200\r
+TZOFFSETTO:+0100\r
+RRULE:FREQ=YEARLY;INTERVAL=1;BYDAY=-1SU;BYMONTH=10\r
+END:STANDARD\r
+BEGIN:DAYLIGHT\r
+DTSTART:16010101T020000\r
+TZOFFSETFROM:+0100\r
+TZOFFSETTO:+0200\r
+RRULE:FREQ=YEARLY;INTERVAL=1;BYDAY=-1SU;BYMONTH=3\r
+END:DAYLIGHT\r
+END:VTIMEZONE\r
+BEGIN:VEVENT\r
+DTSTART;TZID=Romance Standard Time:20240705T093000\r
+DTEND;TZID=Romance Standard Time:20240705T103000\r
+RRULE:FREQ=WEEKLY;BYDAY=MO,WE;UNTIL=20241231T000000Z\r
+ORGANIZER;CN=John Doe:mailto:john@moon.space\r
+UID:event-1@google.com\r
+ATTENDEE;CUTYPE=INDIVIDUAL;ROLE=REQ-PARTICIPANT;PARTSTAT=ACCEPTED;CN=\"Lucas\r
+ ; the second\":mailto:lucas@mercure.space\r
+ATTENDEE;PARTSTAT=NEEDS-ACTION:MAILTO:mars@mars.space\r
+SUMMARY:Weekly sync\\, planets\r
+LOCATION:Room 42\\; Moon base\r
+DESCRIPTION:First line\\nSecond line\r
+BEGIN:VALARM\r
+ACTION:DISPLAY\r
+DESCRIPTION:This is an event reminder\r
+END:VALARM\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+  #[test]
+  fn parse_invitation() {
+    let events = CalendarEvent::parse(INVITATION);
+    assert_eq!(events.len(), 1);
+    let event = &events[0];
+    assert_eq!(event.method.as_deref(), Some("REQUEST"));
+    assert_eq!(event.uid, "event-1@google.com");
+    assert_eq!(event.summary, "Weekly sync, planets");
+    assert_eq!(event.location, "Room 42; Moon base");
+    assert_eq!(event.description, "First line\nSecond line");
+    assert_eq!(
+      event.organizer.as_ref().unwrap().label(),
+      "John Doe <john@moon.space>"
+    );
+    assert_eq!(event.attendees.len(), 2);
+    assert_eq!(
+      event.attendees[0].label(),
+      "Lucas; the second <lucas@mercure.space>"
+    );
+    assert_eq!(event.attendees[0].status_label().unwrap(), "Accepted");
+    assert_eq!(event.attendees[1].label(), "mars@mars.space");
+    assert_eq!(event.attendees[1].status_label(), None);
+    assert!(!event.is_cancelled());
+
+    // 09:30 summer time in Paris is 07:30 UTC.
+    let zone = "Romance Standard Time".to_string();
+    let civil = Civil::parse("20240705T093000").unwrap();
+    assert_eq!(
+      event.start,
+      Some(EventTime::Described(civil, zone.clone(), 1720164600))
+    );
+    let civil = Civil {
+      hour: 10,
+      ..civil
+    };
+    assert_eq!(
+      event.end,
+      Some(EventTime::Described(civil, zone, 1720168200))
+    );
+    assert_eq!(
+      event.recurrence().unwrap(),
+      "Every week on Monday, Wednesday, until 2024-12-31"
+    );
+  }
+
+  #[test]
+  fn time_zones() {
+    let zoned = INVITATION.replace("20240705T093000", "20240105T093000");
+    // 09:30 winter time is 08:30 UTC.
+    assert!(matches!(
+      CalendarEvent::parse(&zoned)[0].start,
+      Some(EventTime::Described(_, _, 1704443400))
+    ));
+
+    let ics = "BEGIN:VCALENDAR\nBEGIN:VEVENT\nDTSTART;TZID=Europe/Paris:20240705T093000\n\
+               DTEND;VALUE=DATE:20240706\nEND:VEVENT\nEND:VCALENDAR\n";
+    let event = &CalendarEvent::parse(ics)[0];
+    let civil = Civil {
+      year: 2024,
+      month: 7,
+      day: 5,
+      hour: 9,
+      minute: 30,
+      second: 0,
+    };
+    assert_eq!(
+      event.start,
+      Some(EventTime::Zoned(civil, "Europe/Paris".to_string()))
+    );
+    assert_eq!(
+      event.end,
+      Some(EventTime::Date(Civil {
+        day: 6,
+        hour: 0,
+        minute: 0,
+        ..civil
+      }))
+    );
+
+    assert_eq!(
+      EventTime::from_iso8601("2024-07-05T07:30:00.000Z"),
+      Some(EventTime::Utc(1720164600))
+    );
+    assert_eq!(EventTime::from_iso8601("2024-07-05T07:30:00"), None);
+    assert_eq!(Civil::parse("20240230"), None);
+    assert_eq!(nth_weekday(2024, 3, -1, 6), Some(31));
+    assert_eq!(nth_weekday(2024, 10, -1, 6), Some(27));
+    assert_eq!(nth_weekday(2024, 2, 5, 0), None);
+  }
+
+  #[test]
+  fn recurrences() {
+    let event = |rrule: &str| CalendarEvent {
+      rrule: Some(rrule.to_string()),
+      ..Default::default()
+    };
+    assert_eq!(event("FREQ=DAILY").recurrence().unwrap(), "Every day");
+    assert_eq!(
+      event("FREQ=WEEKLY;INTERVAL=2;COUNT=10")
+        .recurrence()
+        .unwrap(),
+      "Every 2 weeks, 10 times"
+    );
+    assert_eq!(
+      event("FREQ=MONTHLY;BYDAY=-1FR").recurrence().unwrap(),
+      "Every month on the last Friday"
+    );
+    assert_eq!(
+      event("FREQ=SECONDLY").recurrence().unwrap(),
+      "FREQ=SECONDLY"
+    );
+    let outlook = CalendarEvent {
+      recurring: true,
+      ..Default::default()
+    };
+    assert_eq!(outlook.recurrence().unwrap(), "Repeats");
+    assert_eq!(CalendarEvent::default().recurrence(), None);
+  }
+
+  #[test]
+  fn export_and_merge() {
+    let mut events = CalendarEvent::parse(INVITATION);
+    let ics = events[0].to_ics();
+    assert!(ics.contains("METHOD:PUBLISH\r\n"));
+    assert!(ics.contains("DTSTART;TZID=Romance Standard Time:20240705T093000\r\n"));
+    assert!(ics.lines().all(|line| line.len() <= MAX_LINE_LENGTH));
+
+    let exported = CalendarEvent::parse(&ics);
+    assert_eq!(exported[0].summary, events[0].summary);
+    assert_eq!(exported[0].location, events[0].location);
+    assert_eq!(exported[0].attendees, events[0].attendees);
+    assert_eq!(exported[0].start, events[0].start);
+    assert_eq!(exported[0].rrule, events[0].rrule);
+
+    merge(&mut events, exported);
+    assert_eq!(events.len(), 1);
+    merge(&mut events, vec![CalendarEvent::default()]);
+    assert_eq!(events.len(), 2);
+  }
+
+  #[test]
+  fn export_repeating_event_across_dst() {
+    // A weekly meeting at 09:30 in Paris stays at 09:30 after the clocks
+    // change, at another time in UTC.
+    let events = CalendarEvent::parse(INVITATION);
+    let ics = events[0].to_ics();
+    assert!(ics.contains("BEGIN:VTIMEZONE\r\nTZID:Romance Standard Time\r\n"));
+    assert!(ics.contains("RRULE:FREQ=YEARLY;INTERVAL=1;BYDAY=-1SU;BYMONTH=10\r\n"));
+    assert!(ics.contains("RRULE:FREQ=WEEKLY;BYDAY=MO,WE;UNTIL=20241231T000000Z\r\n"));
+    let exported = &CalendarEvent::parse(&ics)[0];
+    assert_eq!(exported.start, events[0].start);
+    assert_eq!(exported.time_zones, events[0].time_zones);
+
+    let ics = "BEGIN:VCALENDAR\nBEGIN:VEVENT\nDTSTART;TZID=Europe/Paris:20241104T093000\n\
+               RRULE:FREQ=WEEKLY;BYDAY=MO\nEND:VEVENT\nEND:VCALENDAR\n";
+    // Paris isn't described: its rules come from glib.
+    let ics = CalendarEvent::parse(ics)[0].to_ics();
+    let civil = Civil::parse("20241104T093000").unwrap();
+    assert_eq!(
+      CalendarEvent::parse(&ics)[0].start,
+      Some(EventTime::Described(
+        civil,
+        "Europe/Paris".to_string(),
+        1730709000
+      ))
+    );
+    let summer = ics.replace("20241104T093000", "20240701T093000");
+    assert!(matches!(
+      CalendarEvent::parse(&summer)[0].start,
+      Some(EventTime::Described(_, _, 1719819000))
+    ));
+    assert_eq!(iana_zone("W. Europe Standard Time"), "Europe/Berlin");
+    assert_eq!(iana_zone("Europe/Paris"), "Europe/Paris");
+  }
+}
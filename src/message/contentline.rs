@@ -0,0 +1,246 @@
+/* contentline.rs
+ *
+ * Copyright 2024 Alexandre Del Bigio
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! The content lines iCalendar (RFC 5545) and vCard (RFC 6350) files are made
+//! of.
+
+use encoding_rs::{Encoding, UTF_8};
+
+/// Joins folded lines: a line starting with a space or a tab continues the
+/// previous one. vCard 2.1 quoted-printable values are also continued by a
+/// trailing "=".
+pub(crate) fn unfold(text: &str) -> Vec<String> {
+  let mut lines: Vec<String> = vec![];
+  let mut soft_break = false;
+  for line in text.split('\n') {
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    if let Some(last) = lines.last_mut() {
+      if soft_break {
+        last.pop();
+        last.push_str(line);
+        soft_break = is_soft_break(last);
+        continue;
+      }
+      if let Some(continuation) = line.strip_prefix([' ', '\t']) {
+        last.push_str(continuation);
+        continue;
+      }
+    }
+    if !line.is_empty() {
+      lines.push(line.to_string());
+      soft_break = is_soft_break(line);
+    }
+  }
+  lines
+}
+
+fn is_soft_break(line: &str) -> bool {
+  line.ends_with('=')
+    && line
+      .split_once(':')
+      .is_some_and(|(head, _)| head.to_uppercase().contains("QUOTED-PRINTABLE"))
+}
+
+/// Splits on `separator` outside of double quotes, which parameter values may
+/// contain it in.
+fn split_unquoted(text: &str, separator: char) -> Vec<&str> {
+  let mut parts = vec![];
+  let mut quoted = false;
+  let mut start = 0;
+  for (index, c) in text.char_indices() {
+    if c == '"' {
+      quoted = !quoted;
+    } else if c == separator && !quoted {
+      parts.push(&text[start..index]);
+      start = index + c.len_utf8();
+    }
+  }
+  parts.push(&text[start..]);
+  parts
+}
+
+fn unescape(value: &str) -> String {
+  let mut text = String::with_capacity(value.len());
+  let mut chars = value.chars();
+  while let Some(c) = chars.next() {
+    if c != '\\' {
+      text.push(c);
+      continue;
+    }
+    match chars.next() {
+      Some('n' | 'N') => text.push('\n'),
+      Some(c) => text.push(c),
+      None => (),
+    }
+  }
+  text
+}
+
+fn decode_quoted_printable(value: &str) -> Vec<u8> {
+  let bytes = value.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut index = 0;
+  while index < bytes.len() {
+    let byte = bytes[index];
+    let hex = bytes
+      .get(index + 1..index + 3)
+      .and_then(|hex| std::str::from_utf8(hex).ok())
+      .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+    match (byte, hex) {
+      (b'=', Some(hex)) => {
+        decoded.push(hex);
+        index += 3;
+      }
+      _ => {
+        decoded.push(byte);
+        index += 1;
+      }
+    }
+  }
+  decoded
+}
+
+/// A content line: "NAME;PARAM=VALUE:value".
+#[derive(Debug)]
+pub(crate) struct Property {
+  /// Upper case, without the group a vCard property may be prefixed with.
+  pub name: String,
+  pub params: Vec<(String, String)>,
+  pub value: String,
+}
+
+impl Property {
+  pub fn parse(line: &str) -> Option<Self> {
+    let mut quoted = false;
+    let colon = line.char_indices().find_map(|(index, c)| {
+      if c == '"' {
+        quoted = !quoted;
+      }
+      (c == ':' && !quoted).then_some(index)
+    })?;
+    let mut head = split_unquoted(&line[..colon], ';').into_iter();
+    let name = head.next()?.trim();
+    let name = name.rsplit('.').next().unwrap_or(name).to_uppercase();
+    // vCard 2.1 has bare parameters, which are types: "TEL;CELL:...".
+    let params = head
+      .map(|param| param.split_once('=').unwrap_or(("TYPE", param)))
+      .map(|(key, value)| {
+        (
+          key.trim().to_uppercase(),
+          value.trim_matches('"').to_string(),
+        )
+      })
+      .collect();
+    Some(Self {
+      name,
+      params,
+      value: line[colon + 1..].to_string(),
+    })
+  }
+
+  pub fn param(&self, name: &str) -> Option<&str> {
+    self
+      .params
+      .iter()
+      .find(|(key, _)| key == name)
+      .map(|(_, value)| value.as_str())
+  }
+
+  /// The values of a parameter, which may be repeated or comma separated:
+  /// "TYPE=work,voice" or "TYPE=work;TYPE=voice".
+  pub fn param_values(&self, name: &str) -> Vec<String> {
+    self
+      .params
+      .iter()
+      .filter(|(key, _)| key == name)
+      .flat_map(|(_, value)| value.split(','))
+      .map(|value| value.trim().to_lowercase())
+      .filter(|value| !value.is_empty())
+      .collect()
+  }
+
+  /// The value, quoted-printable decoded if it is.
+  fn raw_text(&self) -> String {
+    let encoding = self.param("ENCODING").unwrap_or_default();
+    if !encoding.eq_ignore_ascii_case("QUOTED-PRINTABLE") {
+      return self.value.clone();
+    }
+    let charset = self
+      .param("CHARSET")
+      .and_then(|charset| Encoding::for_label(charset.as_bytes()))
+      .unwrap_or(UTF_8);
+    let bytes = decode_quoted_printable(&self.value);
+    charset.decode(&bytes).0.into_owned()
+  }
+
+  /// The value of a TEXT property, unescaped.
+  pub fn text(&self) -> String {
+    unescape(&self.raw_text())
+  }
+
+  /// The ";" separated components of a structured value, such as N or ORG,
+  /// each unescaped.
+  pub fn components(&self) -> Vec<String> {
+    let raw = self.raw_text();
+    let mut components = vec![];
+    let mut start = 0;
+    let mut escaped = false;
+    for (index, c) in raw.char_indices() {
+      match c {
+        _ if escaped => escaped = false,
+        '\\' => escaped = true,
+        ';' => {
+          components.push(unescape(&raw[start..index]));
+          start = index + 1;
+        }
+        _ => (),
+      }
+    }
+    components.push(unescape(&raw[start..]));
+    components
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::message::contentline::*;
+
+  #[test]
+  fn content_lines() {
+    let lines = unfold(
+      "SUMMARY:Long\r\n  line\r\nitem1.EMAIL;type=INTERNET;TYPE=work,pref:a@b.c\r\n\
+       N;ENCODING=QUOTED-PRINTABLE;CHARSET=UTF-8:=C3=89ric;Du=\r\nmont\r\n",
+    );
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], "SUMMARY:Long line");
+
+    let email = Property::parse(&lines[1]).unwrap();
+    assert_eq!(email.name, "EMAIL");
+    assert_eq!(email.param_values("TYPE"), ["internet", "work", "pref"]);
+
+    let name = Property::parse(&lines[2]).unwrap();
+    assert_eq!(name.components(), ["Éric", "Dumont"]);
+
+    let tel = Property::parse("TEL;CELL:+33 6").unwrap();
+    assert_eq!(tel.param("TYPE"), Some("CELL"));
+    let org = Property::parse("ORG:Moon\\; Mars;Sales\\, EMEA").unwrap();
+    assert_eq!(org.components(), ["Moon; Mars", "Sales, EMEA"]);
+    assert_eq!(org.text(), "Moon; Mars;Sales, EMEA");
+  }
+}
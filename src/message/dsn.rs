@@ -0,0 +1,244 @@
+/* dsn.rs
+ *
+ * Copyright 2024 Alexandre Del Bigio
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Delivery status notifications (RFC 3464), the reports mail servers send
+//! back when a message could not be delivered, or only late.
+
+use gettextrs::gettext;
+
+use super::attachment::Attachment;
+use super::message::MessageParser;
+
+/// What the reporting server did with the message for a recipient.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+  Failed,
+  Delayed,
+  Delivered,
+  Relayed,
+  Expanded,
+}
+
+impl Action {
+  fn parse(value: &str) -> Option<Self> {
+    match value.trim().to_lowercase().as_str() {
+      "failed" => Some(Self::Failed),
+      "delayed" => Some(Self::Delayed),
+      "delivered" => Some(Self::Delivered),
+      "relayed" => Some(Self::Relayed),
+      "expanded" => Some(Self::Expanded),
+      _ => None,
+    }
+  }
+
+  pub fn label(&self) -> String {
+    match self {
+      Self::Failed => gettext("Failed"),
+      Self::Delayed => gettext("Delayed"),
+      Self::Delivered => gettext("Delivered"),
+      Self::Relayed => gettext("Relayed"),
+      Self::Expanded => gettext("Expanded"),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RecipientStatus {
+  pub recipient: String,
+  pub action: Option<Action>,
+  /// The enhanced status code (RFC 3463), "5.1.1".
+  pub status: String,
+  /// What the remote server answered, "550 5.1.1 User unknown".
+  pub diagnostic: String,
+  pub remote_mta: String,
+}
+
+impl RecipientStatus {
+  /// What the status code means, "Permanent failure: bad destination
+  /// mailbox address".
+  pub fn status_label(&self) -> Option<String> {
+    let (class, detail) = self.status.split_once('.')?;
+    let class = match class {
+      "2" => gettext("Success"),
+      "4" => gettext("Temporary failure"),
+      "5" => gettext("Permanent failure"),
+      _ => return None,
+    };
+    let detail = match detail {
+      "1.1" => gettext("bad destination mailbox address"),
+      "1.2" => gettext("bad destination system address"),
+      "1.3" => gettext("bad destination mailbox address syntax"),
+      "1.10" => gettext("the recipient domain accepts no mail"),
+      "2.1" => gettext("mailbox disabled"),
+      "2.2" => gettext("mailbox full"),
+      "2.3" => gettext("message too large for the mailbox"),
+      "3.4" => gettext("message too large for the system"),
+      "4.1" => gettext("no answer from the host"),
+      "4.2" => gettext("bad connection"),
+      "4.4" => gettext("unable to route"),
+      "4.7" => gettext("delivery time expired"),
+      "5.3" => gettext("too many recipients"),
+      "7.1" => gettext("delivery not authorized, message refused"),
+      "7.23" => gettext("SPF validation failed"),
+      "7.25" => gettext("reverse DNS validation failed"),
+      "7.26" => gettext("multiple authentication checks failed"),
+      _ => return Some(class),
+    };
+    Some(format!("{}: {}", class, detail))
+  }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DeliveryReport {
+  pub reporting_mta: String,
+  pub recipients: Vec<RecipientStatus>,
+  /// The message the report is about, as it was returned: whole, or its
+  /// headers only.
+  pub original: Option<Attachment>,
+}
+
+impl DeliveryReport {
+  /// Reads a message/delivery-status part: a block of fields about the
+  /// message, then one block per recipient, separated by empty lines.
+  pub fn parse(text: &str) -> Self {
+    let mut report = Self::default();
+    let text = text.replace("\r\n", "\n");
+    for block in text.split("\n\n") {
+      let fields = MessageParser::split_headers(block.trim_start_matches('\n'));
+      let field = |name: &str| {
+        fields
+          .iter()
+          .find(|(key, _)| key.eq_ignore_ascii_case(name))
+          .map(|(_, value)| value.as_str())
+      };
+      if let Some(mta) = field("Reporting-MTA") {
+        report.reporting_mta = strip_type(mta).to_string();
+      }
+      let Some(recipient) = field("Final-Recipient").or(field("Original-Recipient")) else {
+        continue;
+      };
+      report.recipients.push(RecipientStatus {
+        recipient: strip_type(recipient).to_string(),
+        action: field("Action").and_then(Action::parse),
+        status: field("Status")
+          .and_then(|status| status.split_whitespace().next())
+          .unwrap_or_default()
+          .to_string(),
+        diagnostic: field("Diagnostic-Code")
+          .map(strip_type)
+          .unwrap_or_default()
+          .to_string(),
+        remote_mta: field("Remote-MTA")
+          .map(strip_type)
+          .unwrap_or_default()
+          .to_string(),
+      });
+    }
+    report
+  }
+
+  /// Whether the message could not be delivered to one of the recipients.
+  pub fn has_failed(&self) -> bool {
+    self
+      .recipients
+      .iter()
+      .any(|recipient| recipient.action == Some(Action::Failed))
+  }
+
+  /// Whether the delivery to one of the recipients is still being retried.
+  pub fn is_delayed(&self) -> bool {
+    self
+      .recipients
+      .iter()
+      .any(|recipient| recipient.action == Some(Action::Delayed))
+  }
+}
+
+/// Removes the type values are prefixed with: "rfc822; john@moon.space",
+/// "smtp; 550 User unknown", "dns; mx.moon.space".
+fn strip_type(value: &str) -> &str {
+  match value.split_once(';') {
+    Some((kind, rest)) if !kind.contains([' ', '@']) => rest.trim(),
+    _ => value.trim(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::message::dsn::*;
+
+  const DELIVERY_STATUS: &str = "Reporting-MTA: dns; mx.moon.space\r
+Arrival-Date: Tue, 1 Oct 2024 10:00:00 +0000\r
+\r
+Original-Recipient: rfc822;lucas@mercure.space\r
+Final-Recipient: rfc822; lucas@mercure.space\r
+Action: failed\r
+Status: 5.1.1\r
+Remote-MTA: dns; mx.mercure.space\r
+Diagnostic-Code: smtp; 550 5.1.1 <lucas@mercure.space>:\r
+ Recipient address rejected: User unknown\r
+\r
+Final-Recipient: rfc822; venus@venus.space\r
+Action: delayed\r
+Status: 4.4.7 (delivery time expired)\r
+\r
+Final-Recipient: rfc822; mars@mars.space\r
+Action: Delivered\r
+Status: 2.0.0\r
+";
+
+  #[test]
+  fn parse_report() {
+    let report = DeliveryReport::parse(DELIVERY_STATUS);
+    assert_eq!(report.reporting_mta, "mx.moon.space");
+    assert_eq!(report.recipients.len(), 3);
+    assert!(report.has_failed());
+    assert!(report.is_delayed());
+
+    assert_eq!(report.recipients[0], RecipientStatus {
+      recipient: "lucas@mercure.space".to_string(),
+      action: Some(Action::Failed),
+      status: "5.1.1".to_string(),
+      diagnostic: "550 5.1.1 <lucas@mercure.space>: Recipient address rejected: User unknown"
+        .to_string(),
+      remote_mta: "mx.mercure.space".to_string(),
+    });
+    assert_eq!(report.recipients[1].action, Some(Action::Delayed));
+    assert_eq!(report.recipients[1].status, "4.4.7");
+    assert_eq!(report.recipients[2].action, Some(Action::Delivered));
+  }
+
+  #[test]
+  fn status_labels() {
+    let status = |status: &str| RecipientStatus {
+      status: status.to_string(),
+      ..Default::default()
+    };
+    assert_eq!(
+      status("5.1.1").status_label().as_deref(),
+      Some("Permanent failure: bad destination mailbox address")
+    );
+    assert_eq!(
+      status("4.9.9").status_label().as_deref(),
+      Some("Temporary failure")
+    );
+    assert_eq!(status("").status_label(), None);
+    assert_eq!(strip_type("john@moon.space"), "john@moon.space");
+  }
+}
@@ -23,14 +23,17 @@ use encoding_rs::Encoding;
 use gio::prelude::*;
 use gmime::prelude::Cast;
 use gmime::traits::{
-  ContentTypeExt, DataWrapperExt, MessageExt, ObjectExt, ParserExt, PartExt, StreamExt, StreamMemExt
+  ContentTypeExt, DataWrapperExt, HeaderExt, HeaderListExt, MessageExt, MessagePartExt, ObjectExt, ParserExt, PartExt, StreamExt, StreamMemExt
 };
 use gmime::{
-  glib, InternetAddressExt, InternetAddressList, InternetAddressListExt, Message, Parser, Part, StreamMem
+  glib, InternetAddressExt, InternetAddressList, InternetAddressListExt, Message, MessagePart, Object, Parser, Part, StreamMem
 };
 
 use crate::gio;
 use crate::message::attachment::Attachment;
+use crate::message::calendar::{self, CalendarEvent};
+use crate::message::dsn::DeliveryReport;
+use crate::message::flowed;
 use crate::message::message::MessageParser;
 
 #[allow(unused_variables, dead_code)]
@@ -51,9 +54,12 @@ pub struct ElectronicMail {
   pub to: String,
   pub date: Option<gmime::DateTime>,
   pub subject: String,
+  pub headers: Vec<(String, String)>,
   pub body_html: Option<String>,
   pub body_text: Option<String>,
   pub attachments: Vec<Attachment>,
+  pub events: Vec<CalendarEvent>,
+  pub delivery_report: Option<DeliveryReport>,
 }
 
 impl ElectronicMail {
@@ -63,10 +69,13 @@ impl ElectronicMail {
       from: String::new(),
       to: String::new(),
       subject: String::new(),
+      headers: vec![],
       body_html: None,
       body_text: None,
       date: None,
       attachments: vec![],
+      events: vec![],
+      delivery_report: None,
     }
   }
 
@@ -93,6 +102,19 @@ impl ElectronicMail {
     list
   }
 
+  fn parse_headers(&mut self, message: &Message) {
+    if let Some(list) = message.header_list() {
+      for i in 0..list.count() {
+        if let Some(header) = list.header_at(i) {
+          self.headers.push((
+            header.name().unwrap_or_default().to_string(),
+            header.value().unwrap_or_default().to_string(),
+          ));
+        }
+      }
+    }
+  }
+
   fn internet_list(&self, list: &InternetAddressList) -> String {
     let mut addresses: Vec<String> = Vec::new(); // Crée un vecteur vide de String
 
@@ -108,9 +130,43 @@ impl ElectronicMail {
 
   fn parse_body(&mut self, message: &Message) {
     let mut html: Option<String> = None;
+    // A delivery report returns the message it is about, which is not part of
+    // this one.
+    let is_report = message
+      .mime_part()
+      .and_then(|part| part.content_type())
+      .is_some_and(|content_type| content_type.is_type("multipart", "report"));
+    let mut delivery_status: Option<String> = None;
+    let mut original: Option<Attachment> = None;
     message.foreach(|_, current| {
       log::debug!("part() => {:?}", current.content_id());
+      if let Some(content_type) = current.content_type() {
+        if content_type.is_type("message", "delivery-status")
+          || content_type.is_type("message", "global-delivery-status")
+        {
+          let content = Self::object_content(current);
+          delivery_status = Some(String::from_utf8_lossy(&content).into_owned());
+          return;
+        }
+        if is_report
+          && (content_type.is_type("message", "rfc822")
+            || content_type.is_type("message", "global")
+            || content_type.is_type("text", "rfc822-headers"))
+        {
+          original = Some(Self::returned_message(current));
+          return;
+        }
+      }
       if let Some(part) = current.dynamic_cast_ref::<Part>() {
+        // Invitations are read whether they come inline or as an .ics file,
+        // and still listed as attachments.
+        if part
+          .content_type()
+          .is_some_and(|content_type| content_type.is_type("text", "calendar"))
+        {
+          let events = CalendarEvent::parse(&self.get_content(part));
+          calendar::merge(&mut self.events, events);
+        }
         if part.is_attachment() {
           self.add_attachment(part);
         } else {
@@ -119,7 +175,17 @@ impl ElectronicMail {
             if content_type.is_type("text", "html") {
               html = Some(self.get_content(part));
             } else if content_type.is_type("text", "plain") {
-              self.body_text = Some(self.get_content(part));
+              let text = self.get_content(part);
+              let parameter_is = |name: &str, expected: &str| {
+                content_type
+                  .parameter(name)
+                  .is_some_and(|value| value.eq_ignore_ascii_case(expected))
+              };
+              self.body_text = Some(if parameter_is("format", "flowed") {
+                flowed::decode(&text, parameter_is("delsp", "yes"))
+              } else {
+                text
+              });
             } else {
               self.add_attachment(part);
             }
@@ -127,6 +193,13 @@ impl ElectronicMail {
         }
       }
     });
+    if let Some(text) = delivery_status {
+      let mut report = DeliveryReport::parse(&text);
+      report.original = original.clone();
+      self.delivery_report = Some(report);
+    }
+    // Listed too, to be saved like any attachment.
+    self.attachments.extend(original);
     if let Some(html) = html {
       self.body_html = Some(html);
       // for debugging parsed html
@@ -174,6 +247,44 @@ impl ElectronicMail {
     None
   }
 
+  /// The decoded content of a part, or the whole of an embedded message.
+  fn object_content(object: &Object) -> Vec<u8> {
+    let stream = StreamMem::new();
+    if let Some(part) = object.dynamic_cast_ref::<Part>() {
+      if let Some(content) = part.content() {
+        content.write_to_stream(&stream);
+      }
+    } else if let Some(message) = object
+      .dynamic_cast_ref::<MessagePart>()
+      .and_then(|part| part.message())
+    {
+      message.write_to_stream(None, &stream);
+    }
+    let content = stream
+      .byte_array()
+      .map(|array| array.to_vec())
+      .unwrap_or_default();
+    stream.close();
+    content
+  }
+
+  /// The message a delivery report was sent back with, named after its
+  /// subject.
+  fn returned_message(object: &Object) -> Attachment {
+    let subject = object
+      .dynamic_cast_ref::<MessagePart>()
+      .and_then(|part| part.message())
+      .and_then(|message| message.subject())
+      .map(|subject| subject.trim().to_string())
+      .filter(|subject| !subject.is_empty());
+    Attachment {
+      content_id: "none".to_string(),
+      filename: format!("{}.eml", subject.as_deref().unwrap_or("original-message")),
+      mime_type: Some("message/rfc822".to_string()),
+      body: Self::object_content(object),
+    }
+  }
+
   // It seems that gmime-rs has a memory free bug with g_mime_message_get_date()
   fn my_mime_message_get_date(e: &Message) -> Option<glib::DateTime> {
     unsafe {
@@ -266,6 +377,7 @@ impl super::message::Message for ElectronicMail {
         self.subject = subject.to_string();
       }
       self.date = ElectronicMail::my_mime_message_get_date(eml);
+      self.parse_headers(eml);
       self.parse_body(eml);
     }
     stream.close();
@@ -293,10 +405,22 @@ impl super::message::Message for ElectronicMail {
     MessageParser::to_local_date(&self.date)
   }
 
+  fn headers(&self) -> Vec<(String, String)> {
+    self.headers.clone()
+  }
+
   fn attachments(&self) -> Vec<Attachment> {
     self.attachments.clone()
   }
 
+  fn events(&self) -> Vec<CalendarEvent> {
+    self.events.clone()
+  }
+
+  fn delivery_report(&self) -> Option<DeliveryReport> {
+    self.delivery_report.clone()
+  }
+
   fn body_html(&self) -> Option<String> {
     self.body_html.clone()
   }
@@ -304,6 +428,11 @@ impl super::message::Message for ElectronicMail {
   fn body_text(&self) -> Option<String> {
     self.body_text.clone()
   }
+
+  /// The message as it was read, it is one already.
+  fn to_eml(&self) -> Vec<u8> {
+    self.data.clone()
+  }
 }
 
 #[cfg(test)]
@@ -372,6 +501,15 @@ mod tests {
     assert_eq!(attachment.content_id, "none");
     assert_eq!(attachment.mime_type.as_ref().unwrap(), "text/html");
 
+    let received: Vec<&str> = parser
+      .headers
+      .iter()
+      .filter(|(name, _)| name.eq_ignore_ascii_case("Received"))
+      .map(|(_, value)| value.as_str())
+      .collect();
+    assert_eq!(received.len(), 1);
+    assert!(received[0].starts_with("by 10.220.191.194 with HTTP;"));
+
     Ok(())
   }
 
@@ -389,6 +527,63 @@ mod tests {
 
     Ok(())
   }
+
+  #[test]
+  fn test_invitation() -> Result<(), Box<dyn Error>> {
+    let mut parser = ElectronicMail::new(fs::read("tests/invitation.eml").unwrap());
+    parser.parse(None)?;
+    // Inline and attached, the same event is only shown once.
+    assert_eq!(parser.events.len(), 1);
+    let event = &parser.events[0];
+    assert_eq!(event.summary, "Launch review");
+    assert_eq!(event.location, "Moon base, room 42");
+    assert_eq!(event.method.as_deref(), Some("REQUEST"));
+    assert_eq!(event.attendees[0].label(), "Lucas <lucas@mercure.space>");
+    assert_eq!(parser.attachments.len(), 1);
+    assert_eq!(parser.attachments[0].filename, "invite.ics");
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_delivery_report() -> Result<(), Box<dyn Error>> {
+    let mut parser = ElectronicMail::new(fs::read("tests/bounce.eml").unwrap());
+    parser.parse(None)?;
+    assert!(parser.body_text.unwrap().contains("could not"));
+    let report = parser.delivery_report.expect("a delivery report");
+    assert_eq!(report.reporting_mta, "mx.moon.space");
+    assert_eq!(report.recipients.len(), 1);
+    let recipient = &report.recipients[0];
+    assert_eq!(recipient.recipient, "lucas@mercure.space");
+    assert_eq!(recipient.status, "5.1.1");
+    assert!(recipient
+      .diagnostic
+      .ends_with("User unknown in virtual mailbox table"));
+    assert!(report.has_failed());
+
+    let original = report.original.expect("the returned message");
+    assert_eq!(original.filename, "Lorem ipsum.eml");
+    assert!(String::from_utf8_lossy(&original.body).contains("Lorem ipsum dolor sit amet."));
+    assert_eq!(parser.attachments.len(), 1);
+    assert_eq!(parser.attachments[0].filename, "Lorem ipsum.eml");
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_flowed() -> Result<(), Box<dyn Error>> {
+    let mut parser = ElectronicMail::new(fs::read("tests/flowed.eml").unwrap());
+    parser.parse(None)?;
+    let text = parser.body_text.unwrap();
+    assert!(text.contains(
+      "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor \
+       incididunt ut labore et dolore magna aliqua."
+    ));
+    assert!(text.contains("> > Ut enim ad minim veniam, quis nostrud exercitation."));
+
+    Ok(())
+  }
+
   #[test]
   fn test_sample_html() -> Result<(), Box<dyn Error>> {
     let mut parser = ElectronicMail::new(fs::read("tests/html.eml").unwrap());
@@ -0,0 +1,103 @@
+/* flowed.rs
+ *
+ * Copyright 2024 Alexandre Del Bigio
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+
+/// Joins the lines of a `format=flowed` text back into the paragraphs they
+/// were cut from (RFC 3676). A line ending with a space goes on with the next
+/// one, unless the quote level changes; with `delsp=yes` that space was only
+/// added to cut the line and is dropped. Quotes are written `> > `.
+pub fn decode(text: &str, delsp: bool) -> String {
+  let mut lines: Vec<String> = vec![];
+  // The paragraph being joined, and its quote level.
+  let mut paragraph: Option<(usize, String)> = None;
+
+  for line in text.lines() {
+    let quote = line.chars().take_while(|c| *c == '>').count();
+    let content = &line[quote..];
+    // Space stuffing protects a line starting with a space, a `>` or "From ".
+    let content = content.strip_prefix(' ').unwrap_or(content);
+    let flowed = content.ends_with(' ') && content != "-- ";
+
+    let (level, mut joined) = match paragraph.take() {
+      Some((level, joined)) if level == quote => (level, joined),
+      Some((level, joined)) => {
+        lines.push(quoted(level, &joined));
+        (quote, String::new())
+      }
+      None => (quote, String::new()),
+    };
+    if flowed {
+      joined.push_str(if delsp {
+        &content[..content.len() - 1]
+      } else {
+        content
+      });
+      paragraph = Some((level, joined));
+    } else {
+      joined.push_str(content);
+      lines.push(quoted(level, &joined));
+    }
+  }
+  if let Some((level, joined)) = paragraph {
+    lines.push(quoted(level, &joined));
+  }
+  lines.join("\n")
+}
+
+fn quoted(level: usize, text: &str) -> String {
+  if level == 0 {
+    return text.to_string();
+  }
+  let prefix = "> ".repeat(level);
+  if text.is_empty() {
+    prefix.trim_end().to_string()
+  } else {
+    prefix + text
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::message::flowed::*;
+
+  #[test]
+  fn join_flowed_lines() {
+    let text = "Hello Lucas,\r\n\r\nThis paragraph was \r\ncut in \r\nthree lines.\r\n\
+                >> Quoted \r\n>> twice\r\n>\r\n> Once \r\n>> but not this\r\n \
+                >From the start\r\n-- \r\nJohn Doe";
+    assert_eq!(
+      decode(text, false),
+      "Hello Lucas,\n\nThis paragraph was cut in three lines.\n\
+       > > Quoted twice\n>\n> Once \n> > but not this\n>From the start\n-- \nJohn Doe"
+    );
+  }
+
+  #[test]
+  fn delete_added_spaces() {
+    let text = "A long word cut in the midd \r\nle, and an ending \r\nspace.";
+    assert_eq!(
+      decode(text, true),
+      "A long word cut in the middle, and an endingspace."
+    );
+    assert_eq!(
+      decode(text, false),
+      "A long word cut in the midd le, and an ending space."
+    );
+  }
+}
@@ -19,17 +19,20 @@
  */
 use std::error::Error;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
 use lazy_static::lazy_static;
 use uuid::Uuid;
 
 use super::attachment::Attachment;
+use super::calendar::CalendarEvent;
+use super::dsn::DeliveryReport;
 use crate::config::APP_NAME;
 use crate::gio::prelude::*;
 use crate::message::electronicmail::ElectronicMail;
 use crate::message::outlook::OutlookMessage;
-use crate::{gio, glib};
+use crate::{gio, glib, htmltext};
 
 const EML_MIME_TYPES: [&str; 1] = ["message/rfc822"];
 
@@ -58,9 +61,18 @@ pub trait Message {
   fn to(&self) -> String;
   fn subject(&self) -> String;
   fn date(&self) -> String;
+  /// The top level headers, in the order they appear, with folded values
+  /// unfolded.
+  fn headers(&self) -> Vec<(String, String)>;
   fn attachments(&self) -> Vec<Attachment>;
+  /// The meetings the message is an invitation to, or tells about.
+  fn events(&self) -> Vec<CalendarEvent>;
+  /// The delivery status notification the message is, if it is a bounce.
+  fn delivery_report(&self) -> Option<DeliveryReport>;
   fn body_html(&self) -> Option<String>;
   fn body_text(&self) -> Option<String>;
+  /// The message as a MIME message (RFC 5322), the content of an .eml file.
+  fn to_eml(&self) -> Vec<u8>;
 }
 
 #[derive(PartialEq, Debug)]
@@ -74,6 +86,8 @@ pub struct MessageParser {
   parser: Box<dyn Message + Send>,
   #[allow(dead_code)]
   message_type: MessageType,
+  /// The text part, rendered once from the html part when there is none.
+  body_text: OnceLock<Option<String>>,
 }
 
 impl MessageParser {
@@ -82,30 +96,37 @@ impl MessageParser {
     cancellable: Option<&gio::Cancellable>,
   ) -> Result<Self, Box<dyn Error>> {
     let content = Self::message_content(file, cancellable).await?;
-    let (content_type, _) = gio::content_type_guess(file.path(), Some(content.as_slice()));
+    Ok(Self::from_content(file.path().as_deref(), content))
+  }
+
+  /// A parser for a message read already, `path` being the file it was read
+  /// from if any. Nothing is read, so it may be called from any thread.
+  pub fn from_content(path: Option<&Path>, content: Vec<u8>) -> Self {
+    let (content_type, _) = gio::content_type_guess(path, Some(content.as_slice()));
 
     log::debug!(
-      "MessageParser::new() message_content {:?}: {:?}",
-      file.path(),
+      "MessageParser::from_content() message_content {:?}: {:?}",
+      path,
       content_type
     );
 
     // gio::content_type_guess() detects EML as text/plain if file == /dev/stdin,
     // so we assume != MSG => EML when file
-    let message_type = if MSG_MIME_TYPES.contains(&content_type.as_str()) {
+    let message_type = if Self::is_outlook(&content_type) {
       MessageType::Msg
     } else {
       MessageType::Eml
     };
 
-    Ok(Self {
+    Self {
       parser: if message_type == MessageType::Msg {
         Box::new(OutlookMessage::new(content))
       } else {
         Box::new(ElectronicMail::new(content))
       },
       message_type,
-    })
+      body_text: OnceLock::new(),
+    }
   }
 
   pub fn supported_mime_types() -> Vec<&'static str> {
@@ -115,6 +136,11 @@ impl MessageParser {
     v
   }
 
+  /// Whether a guessed content type is the one of an Outlook .msg file.
+  pub fn is_outlook(content_type: &str) -> bool {
+    MSG_MIME_TYPES.contains(&content_type)
+  }
+
   #[allow(dead_code)]
   async fn message_type(file: &gio::File) -> Result<MessageType, Box<dyn Error>> {
     let file_info = file
@@ -190,6 +216,27 @@ impl MessageParser {
     }
   }
 
+  /// Splits a raw header block into names and values. A line starting with
+  /// whitespace continues the previous header (RFC 5322 folding), parsing stops
+  /// at the first empty line.
+  pub fn split_headers(raw: &str) -> Vec<(String, String)> {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in raw.lines() {
+      if line.trim().is_empty() {
+        break;
+      }
+      if line.starts_with([' ', '\t']) {
+        if let Some((_, value)) = headers.last_mut() {
+          value.push(' ');
+          value.push_str(line.trim());
+        }
+      } else if let Some((name, value)) = line.split_once(':') {
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+      }
+    }
+    headers
+  }
+
   pub fn to_local_date(date: &Option<gmime::DateTime>) -> String {
     if let Some(date) = date {
       match date.to_local() {
@@ -207,6 +254,7 @@ impl MessageParser {
 
 impl Message for MessageParser {
   fn parse(&mut self, cancellable: Option<&gio::Cancellable>) -> Result<(), Box<dyn Error>> {
+    self.body_text = OnceLock::new();
     self.parser.parse(cancellable)
   }
 
@@ -226,16 +274,42 @@ impl Message for MessageParser {
     self.parser.date()
   }
 
+  fn headers(&self) -> Vec<(String, String)> {
+    self.parser.headers()
+  }
+
   fn attachments(&self) -> Vec<Attachment> {
     self.parser.attachments()
   }
 
+  fn events(&self) -> Vec<CalendarEvent> {
+    self.parser.events()
+  }
+
+  fn delivery_report(&self) -> Option<DeliveryReport> {
+    self.parser.delivery_report()
+  }
+
   fn body_html(&self) -> Option<String> {
     self.parser.body_html()
   }
 
+  /// A message with only an html part still has a text one, rendered from
+  /// the html.
   fn body_text(&self) -> Option<String> {
-    self.parser.body_text()
+    self
+      .body_text
+      .get_or_init(|| {
+        self
+          .parser
+          .body_text()
+          .or_else(|| self.parser.body_html().map(|html| htmltext::to_text(&html)))
+      })
+      .clone()
+  }
+
+  fn to_eml(&self) -> Vec<u8> {
+    self.parser.to_eml()
   }
 }
 
@@ -257,6 +331,28 @@ mod tests {
     );
   }
 
+  #[test]
+  fn split_headers() {
+    let raw = "Received: from mx.moon.space (mx.moon.space [192.0.2.1])\r\n\
+               \tby mx.mercure.space; Tue, 1 Oct 2024 10:00:00 +0000\r\n\
+               From: John Doe <john@moon.space>\r\n\
+               not a header\r\n\
+               Subject:Lorem ipsum\r\n\
+               \r\n\
+               Body: not a header";
+
+    assert_eq!(MessageParser::split_headers(raw), vec![
+      (
+        "Received".to_string(),
+        "from mx.moon.space (mx.moon.space [192.0.2.1]) by mx.mercure.space; Tue, 1 Oct 2024 \
+           10:00:00 +0000"
+          .to_string()
+      ),
+      ("From".to_string(), "John Doe <john@moon.space>".to_string()),
+      ("Subject".to_string(), "Lorem ipsum".to_string()),
+    ]);
+  }
+
   #[test]
   fn test_content_type_eml() {
     utils::spawn_and_wait_new_ctx(async move {
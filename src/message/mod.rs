@@ -1,5 +1,14 @@
+pub(crate) mod archive;
 pub(crate) mod attachment;
+pub(crate) mod calendar;
+mod contentline;
+pub(crate) mod dsn;
 mod electronicmail;
+mod flowed;
 #[allow(clippy::module_inception)]
 pub(crate) mod message;
 mod outlook;
+pub(crate) mod preview;
+pub(crate) mod risk;
+pub(crate) mod summary;
+pub(crate) mod vcard;
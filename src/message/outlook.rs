@@ -20,11 +20,20 @@
 use std::error::Error;
 
 use gio::prelude::*;
-use gmime::glib;
+use gmime::prelude::Cast;
+use gmime::traits::{
+  MessageExt, MultipartExt, ObjectExt, PartExt, StreamExt, StreamMemExt, TextPartExt
+};
+use gmime::{
+  glib, ContentEncoding, DataWrapper, EncodingConstraint, Multipart, Object, Part, StreamMem, TextPart
+};
 use msg_parser::Outlook;
 
 use super::attachment::Attachment;
+use super::calendar::{self, CalendarEvent, EventTime, Participant};
+use super::dsn::DeliveryReport;
 use super::message::Message;
+use super::preview::decode_text;
 use crate::gio;
 use crate::message::message::MessageParser;
 
@@ -35,9 +44,11 @@ pub struct OutlookMessage {
   pub to: String,
   pub date: Option<gmime::DateTime>,
   pub subject: String,
+  pub headers: Vec<(String, String)>,
   pub body: Option<String>,
   pub html: Option<String>,
   pub attachments: Vec<Attachment>,
+  pub events: Vec<CalendarEvent>,
 }
 
 impl OutlookMessage {
@@ -48,9 +59,11 @@ impl OutlookMessage {
       to: String::new(),
       date: None,
       subject: String::new(),
+      headers: vec![],
       body: None,
       html: None,
       attachments: vec![],
+      events: vec![],
     }
   }
 
@@ -75,6 +88,92 @@ impl OutlookMessage {
       .join(", ")
   }
 
+  fn person_to_participant(person: &msg_parser::Person) -> Participant {
+    Participant {
+      name: Self::clean_string(person.name.clone()),
+      email: Self::clean_string(person.email.clone()),
+      status: None,
+    }
+  }
+
+  /// The meeting a meeting request, a cancellation, a response or an
+  /// appointment item is about. Its recurrence pattern is a binary blob which
+  /// is not decoded, only whether there is one.
+  fn meeting(outlook: &Outlook) -> Option<CalendarEvent> {
+    let class = outlook.message_class.to_lowercase();
+    let method = if class.starts_with("ipm.schedule.meeting.request") {
+      "REQUEST"
+    } else if class.starts_with("ipm.schedule.meeting.canceled") {
+      "CANCEL"
+    } else if class.starts_with("ipm.schedule.meeting.resp") {
+      "REPLY"
+    } else if class.starts_with("ipm.appointment") {
+      "PUBLISH"
+    } else {
+      return None;
+    };
+
+    let property = |name: &str| {
+      outlook
+        .named_properties
+        .get(name)
+        .map(|value| Self::clean_string(value.clone()))
+        .filter(|value| !value.is_empty())
+    };
+    let time = |name: &str| property(name).and_then(|value| EventTime::from_iso8601(&value));
+    Some(CalendarEvent {
+      method: Some(method.to_string()),
+      summary: Self::clean_string(outlook.subject.clone()),
+      location: property("Location").unwrap_or_default(),
+      organizer: Some(Self::person_to_participant(&outlook.sender)),
+      attendees: outlook
+        .to
+        .iter()
+        .chain(&outlook.cc)
+        .map(Self::person_to_participant)
+        .collect(),
+      start: time("AppointmentStartWhole"),
+      end: time("AppointmentEndWhole"),
+      recurring: property("Recurring").as_deref() == Some("1")
+        || property("AppointmentRecur").is_some(),
+      cancelled: method == "CANCEL",
+      ..Default::default()
+    })
+  }
+
+  fn text_part(subtype: &str, text: &str) -> Object {
+    let part = TextPart::with_subtype(subtype);
+    part.set_text(text);
+    part.upcast()
+  }
+
+  fn attachment_part(attachment: &Attachment, disposition: &str) -> Object {
+    let (kind, subtype) = attachment
+      .mime_type
+      .as_deref()
+      .and_then(|mime_type| mime_type.split_once('/'))
+      .filter(|(kind, subtype)| !kind.is_empty() && !subtype.is_empty())
+      .unwrap_or(("application", "octet-stream"));
+    let part = Part::with_type(kind, subtype);
+    let stream = StreamMem::with_buffer(&attachment.body);
+    part.set_content(&DataWrapper::with_stream(&stream, ContentEncoding::Default));
+    part.set_content_encoding(ContentEncoding::Base64);
+    part.set_disposition(disposition);
+    part.set_filename(&attachment.filename);
+    if !attachment.content_id.is_empty() {
+      part.set_content_id(&attachment.content_id);
+    }
+    part.upcast()
+  }
+
+  fn multipart(subtype: &str, parts: &[Object]) -> Object {
+    let multipart = Multipart::with_subtype(subtype);
+    for part in parts {
+      multipart.add(part);
+    }
+    multipart.upcast()
+  }
+
   /* some msg fields contains null bytes and gtk4 components can't handle them */
   fn clean_string(mut value: String) -> String {
     value.retain(|c| c != '\0');
@@ -90,10 +189,12 @@ impl Message for OutlookMessage {
       cancellable.set_error_if_cancelled()?;
     }
 
+    self.events.extend(Self::meeting(&outlook));
     self.from = Self::clean_string(OutlookMessage::person_to_string(&outlook.sender));
     self.to = Self::clean_string(OutlookMessage::person_list_to_string(&outlook.to));
     self.subject = Self::clean_string(outlook.subject);
     self.date = self.get_date(&outlook.headers.date);
+    self.headers = MessageParser::split_headers(&Self::clean_string(outlook.headers.raw.clone()));
     self.body = if outlook.body.is_empty() {
       None
     } else {
@@ -121,14 +222,33 @@ impl Message for OutlookMessage {
         cancellable.set_error_if_cancelled()?;
       }
 
+      // Not every attachment has a content id, the html of those that have
+      // one refers to them by it.
+      let content_id = if att.content_id.is_empty() {
+        &att.file_name
+      } else {
+        &att.content_id
+      };
       self.attachments.push(Attachment {
         filename: Self::clean_string(att.file_name.clone()),
-        content_id: Self::clean_string(att.file_name.clone()), // Uuid::new_v4().simple().to_string(),
+        content_id: Self::clean_string(content_id.clone()),
         body: hex::decode(&att.payload)?,
         mime_type: Some(att.mime_tag.clone()),
       });
     }
 
+    for attachment in &self.attachments {
+      let is_calendar = attachment
+        .mime_type
+        .as_deref()
+        .is_some_and(|mime_type| mime_type.eq_ignore_ascii_case("text/calendar"))
+        || attachment.filename.to_lowercase().ends_with(".ics");
+      if is_calendar {
+        let events = CalendarEvent::parse(&decode_text(&attachment.body));
+        calendar::merge(&mut self.events, events);
+      }
+    }
+
     Ok(())
   }
 
@@ -148,10 +268,24 @@ impl Message for OutlookMessage {
     MessageParser::to_local_date(&self.date)
   }
 
+  fn headers(&self) -> Vec<(String, String)> {
+    self.headers.clone()
+  }
+
   fn attachments(&self) -> Vec<Attachment> {
     self.attachments.clone()
   }
 
+  fn events(&self) -> Vec<CalendarEvent> {
+    self.events.clone()
+  }
+
+  // Outlook keeps the delivery status of a non-delivery report in its
+  // recipient table, which msg_parser doesn't read.
+  fn delivery_report(&self) -> Option<DeliveryReport> {
+    None
+  }
+
   fn body_html(&self) -> Option<String> {
     self.html.clone()
   }
@@ -159,6 +293,90 @@ impl Message for OutlookMessage {
   fn body_text(&self) -> Option<String> {
     self.body.clone()
   }
+
+  /// Rebuilds the message with gmime. The transport headers of a message that
+  /// was sent are kept, but for those about its structure; the text and the
+  /// html are alternatives, and the attachments the html shows by their
+  /// content id are related to it.
+  fn to_eml(&self) -> Vec<u8> {
+    let message = gmime::Message::new(true);
+    for (name, value) in &self.headers {
+      let name_lower = name.to_ascii_lowercase();
+      if !name_lower.starts_with("content-") && name_lower != "mime-version" {
+        message.append_header(name, value, "utf-8");
+      }
+    }
+    let has_header = |name: &str| {
+      self
+        .headers
+        .iter()
+        .any(|(header, _)| header.eq_ignore_ascii_case(name))
+    };
+    if !has_header("From") && !self.from.is_empty() {
+      message.append_header("From", &self.from, "utf-8");
+    }
+    if !has_header("To") && !self.to.is_empty() {
+      message.append_header("To", &self.to, "utf-8");
+    }
+    if !has_header("Subject") {
+      message.set_subject(&self.subject, "utf-8");
+    }
+    if let Some(date) = self.date.as_ref().filter(|_| !has_header("Date")) {
+      message.set_date(date);
+    }
+
+    let html_lower = self.html.as_deref().unwrap_or_default().to_lowercase();
+    let (inline, attached): (Vec<&Attachment>, Vec<&Attachment>) =
+      self.attachments.iter().partition(|attachment| {
+        !attachment.content_id.is_empty()
+          && html_lower.contains(&format!("cid:{}", attachment.content_id.to_lowercase()))
+      });
+
+    let text = self
+      .body
+      .as_deref()
+      .map(|text| Self::text_part("plain", text));
+    let html = self.html.as_deref().map(|html| {
+      let html = Self::text_part("html", html);
+      if inline.is_empty() {
+        return html;
+      }
+      let mut parts = vec![html];
+      parts.extend(
+        inline
+          .iter()
+          .map(|attachment| Self::attachment_part(attachment, "inline")),
+      );
+      Self::multipart("related", &parts)
+    });
+    let body = match (text, html) {
+      (Some(text), Some(html)) => Self::multipart("alternative", &[text, html]),
+      (Some(part), None) | (None, Some(part)) => part,
+      (None, None) => Self::text_part("plain", ""),
+    };
+    let root = if attached.is_empty() {
+      body
+    } else {
+      let mut parts = vec![body];
+      parts.extend(
+        attached
+          .iter()
+          .map(|attachment| Self::attachment_part(attachment, "attachment")),
+      );
+      Self::multipart("mixed", &parts)
+    };
+    root.encode(EncodingConstraint::_7bit);
+    message.set_mime_part(&root);
+
+    let stream = StreamMem::new();
+    message.write_to_stream(None, &stream);
+    let eml = stream
+      .byte_array()
+      .map(|array| array.to_vec())
+      .unwrap_or_default();
+    stream.close();
+    eml
+  }
 }
 
 #[cfg(test)]
@@ -166,6 +384,8 @@ mod tests {
   use std::error::Error;
   use std::fs;
 
+  use crate::message::attachment::Attachment;
+  use crate::message::electronicmail::ElectronicMail;
   use crate::message::message::Message;
   use crate::message::outlook::OutlookMessage;
 
@@ -180,6 +400,7 @@ mod tests {
     assert_eq!(parser.attachments.len(), 3);
     assert_eq!(parser.attachments[0].filename, "image001.png");
     assert!(parser.body.clone().unwrap().contains("Hello Lucas"));
+    assert!(parser.events.is_empty());
     assert_eq!(
       parser.attachments[0].mime_type.clone().unwrap(),
       "image/png"
@@ -187,6 +408,46 @@ mod tests {
     Ok(())
   }
 
+  #[test]
+  fn outlook_to_eml() -> Result<(), Box<dyn Error>> {
+    let mut outlook = OutlookMessage::new(fs::read("sample.msg").unwrap());
+    outlook.parse(None)?;
+    let mut eml = ElectronicMail::new(outlook.to_eml());
+    eml.parse(None)?;
+
+    assert_eq!(eml.from, outlook.from);
+    assert_eq!(eml.to, outlook.to);
+    assert_eq!(eml.subject, outlook.subject);
+    let lines = |text: Option<String>| text.map(|text| text.lines().collect::<Vec<_>>().join("\n"));
+    assert_eq!(lines(eml.body_text.clone()), lines(outlook.body.clone()));
+    assert_eq!(lines(eml.body_html.clone()), lines(outlook.html.clone()));
+
+    let attachments = |attachments: &[Attachment]| {
+      let mut attachments: Vec<_> = attachments
+        .iter()
+        .map(|attachment| {
+          (
+            attachment.filename.clone(),
+            attachment.content_id.clone(),
+            attachment
+              .mime_type
+              .clone()
+              .filter(|mime_type| !mime_type.is_empty())
+              .unwrap_or("application/octet-stream".to_string()),
+            attachment.body.clone(),
+          )
+        })
+        .collect();
+      attachments.sort();
+      attachments
+    };
+    assert_eq!(
+      attachments(&eml.attachments),
+      attachments(&outlook.attachments)
+    );
+    Ok(())
+  }
+
   #[test]
   fn clean_string_bytes() {
     assert_eq!(OutlookMessage::clean_string("a\0b\0c".to_string()), "abc");
@@ -0,0 +1,226 @@
+/* preview.rs
+ *
+ * Copyright 2024 Alexandre Del Bigio
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+
+/// Text past this size is not worth reading in a preview, and a text view
+/// becomes painfully slow with it.
+const MAX_TEXT_SIZE: usize = 4 * 1024 * 1024;
+
+/// How much of a body is looked at to tell text from binary content.
+const SNIFF_SIZE: usize = 8192;
+
+const TEXT_MIME_TYPES: [&str; 10] = [
+  "application/json",
+  "application/xml",
+  "application/javascript",
+  "application/x-javascript",
+  "application/x-sh",
+  "application/x-shellscript",
+  "application/x-python",
+  "application/sql",
+  "application/x-yaml",
+  "application/toml",
+];
+
+const TEXT_EXTENSIONS: [&str; 42] = [
+  "txt",
+  "text",
+  "log",
+  "md",
+  "rst",
+  "csv",
+  "tsv",
+  "json",
+  "xml",
+  "yml",
+  "yaml",
+  "toml",
+  "ini",
+  "conf",
+  "cfg",
+  "properties",
+  "rs",
+  "c",
+  "h",
+  "cc",
+  "cpp",
+  "hpp",
+  "cs",
+  "java",
+  "kt",
+  "go",
+  "py",
+  "rb",
+  "php",
+  "pl",
+  "js",
+  "ts",
+  "css",
+  "sh",
+  "bash",
+  "ps1",
+  "bat",
+  "cmd",
+  "vbs",
+  "sql",
+  "diff",
+  "patch",
+];
+
+const IMAGE_EXTENSIONS: [&str; 9] = [
+  "png",
+  "jpg",
+  "jpeg",
+  "gif",
+  "bmp",
+  "webp",
+  "tif",
+  "tiff",
+  "ico",
+];
+
+const HTML_EXTENSIONS: [&str; 2] = ["html", "htm"];
+
+/// How an attachment can be shown without handing it to another application.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PreviewKind {
+  Image,
+  Text,
+  Pdf,
+  Html,
+}
+
+impl PreviewKind {
+  /// The preview to use for an attachment, judging by its declared type, its
+  /// name and its content. None when it has to be opened elsewhere.
+  pub fn for_attachment(filename: &str, mime_type: Option<&str>, body: &[u8]) -> Option<Self> {
+    let mime_type = mime_type.unwrap_or_default().to_lowercase();
+    let extension = filename
+      .rsplit_once('.')
+      .map(|(_, extension)| extension.to_lowercase())
+      .unwrap_or_default();
+    let extension = extension.as_str();
+
+    if mime_type == "application/pdf" || extension == "pdf" {
+      // The content has to agree, anything else claiming to be a PDF is not
+      // handed to the PDF renderer.
+      return body.starts_with(b"%PDF-").then_some(Self::Pdf);
+    }
+    if mime_type == "text/html" || HTML_EXTENSIONS.contains(&extension) {
+      return is_text(body).then_some(Self::Html);
+    }
+    if (mime_type.starts_with("image/") && mime_type != "image/svg+xml")
+      || IMAGE_EXTENSIONS.contains(&extension)
+    {
+      return Some(Self::Image);
+    }
+    if mime_type.starts_with("text/")
+      || TEXT_MIME_TYPES.contains(&mime_type.as_str())
+      || TEXT_EXTENSIONS.contains(&extension)
+    {
+      return is_text(body).then_some(Self::Text);
+    }
+    None
+  }
+}
+
+/// Whether a body looks like text small enough to be shown : no NUL byte at its
+/// start, which binary formats nearly always have.
+fn is_text(body: &[u8]) -> bool {
+  body.len() <= MAX_TEXT_SIZE && !body[..body.len().min(SNIFF_SIZE)].contains(&0)
+}
+
+/// Decodes a text attachment. The charset of the part is not kept, so a byte
+/// order mark decides, then UTF-8, then Windows-1252 which accepts anything.
+pub fn decode_text(body: &[u8]) -> String {
+  if let Some((encoding, _)) = Encoding::for_bom(body) {
+    let (text, _, _) = encoding.decode(body);
+    return text.into_owned();
+  }
+  match UTF_8.decode_without_bom_handling_and_without_replacement(body) {
+    Some(text) => text.into_owned(),
+    None => WINDOWS_1252.decode(body).0.into_owned(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::message::preview::*;
+
+  #[test]
+  fn previews_by_type_and_name() {
+    assert_eq!(
+      PreviewKind::for_attachment("photo.JPG", None, b"\xff\xd8\xff"),
+      Some(PreviewKind::Image)
+    );
+    assert_eq!(
+      PreviewKind::for_attachment("scan", Some("image/png"), b"\x89PNG"),
+      Some(PreviewKind::Image)
+    );
+    assert_eq!(
+      PreviewKind::for_attachment("notes.txt", Some("text/plain"), b"hello"),
+      Some(PreviewKind::Text)
+    );
+    assert_eq!(
+      PreviewKind::for_attachment("main.rs", Some("application/octet-stream"), b"fn main() {}"),
+      Some(PreviewKind::Text)
+    );
+    assert_eq!(
+      PreviewKind::for_attachment("page.htm", None, b"<p>hello</p>"),
+      Some(PreviewKind::Html)
+    );
+    assert_eq!(
+      PreviewKind::for_attachment("report.pdf", Some("application/pdf"), b"%PDF-1.7"),
+      Some(PreviewKind::Pdf)
+    );
+    assert_eq!(
+      PreviewKind::for_attachment("setup.exe", Some("application/x-msdownload"), b"MZ"),
+      None
+    );
+    assert_eq!(
+      PreviewKind::for_attachment("logo.svg", Some("image/svg+xml"), b"<svg/>"),
+      None
+    );
+  }
+
+  #[test]
+  fn content_has_to_agree() {
+    assert_eq!(
+      PreviewKind::for_attachment("report.pdf", Some("application/pdf"), b"MZ\x90\x00"),
+      None
+    );
+    assert_eq!(
+      PreviewKind::for_attachment("notes.txt", Some("text/plain"), b"MZ\x90\x00\x03"),
+      None
+    );
+    assert_eq!(
+      PreviewKind::for_attachment("huge.log", None, &vec![b'a'; MAX_TEXT_SIZE + 1]),
+      None
+    );
+  }
+
+  #[test]
+  fn decode_texts() {
+    assert_eq!(decode_text("état".as_bytes()), "état");
+    assert_eq!(decode_text(b"\xe9tat"), "état");
+    assert_eq!(decode_text(b"\xef\xbb\xbfhello"), "hello");
+    assert_eq!(decode_text(b"\xff\xfeh\x00i\x00"), "hi");
+  }
+}
@@ -0,0 +1,435 @@
+/* risk.rs
+ *
+ * Copyright 2024 Alexandre Del Bigio
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+use gettextrs::gettext;
+
+/// Runs as soon as it is opened, on one system or another.
+const EXECUTABLE_EXTENSIONS: [&str; 14] = [
+  "exe",
+  "com",
+  "scr",
+  "pif",
+  "msi",
+  "msp",
+  "dll",
+  "cpl",
+  "jar",
+  "appimage",
+  "apk",
+  "deb",
+  "rpm",
+  "run",
+];
+
+/// Handed to an interpreter, which is just as bad.
+const SCRIPT_EXTENSIONS: [&str; 20] = [
+  "bat",
+  "cmd",
+  "ps1",
+  "psm1",
+  "vbs",
+  "vbe",
+  "js",
+  "jse",
+  "wsf",
+  "wsh",
+  "hta",
+  "sh",
+  "bash",
+  "py",
+  "pl",
+  "lnk",
+  "reg",
+  "scf",
+  "inf",
+  "desktop",
+];
+
+/// What the extension of a program is hidden behind in `invoice.pdf.exe`.
+const DOCUMENT_EXTENSIONS: [&str; 20] = [
+  "pdf",
+  "doc",
+  "docx",
+  "xls",
+  "xlsx",
+  "ppt",
+  "pptx",
+  "odt",
+  "ods",
+  "rtf",
+  "txt",
+  "csv",
+  "htm",
+  "html",
+  "jpg",
+  "jpeg",
+  "png",
+  "gif",
+  "zip",
+  "mp3",
+];
+
+/// Office documents allowed to carry macros.
+const MACRO_EXTENSIONS: [&str; 11] = [
+  "docm",
+  "dotm",
+  "xlsm",
+  "xltm",
+  "xlam",
+  "xlsb",
+  "pptm",
+  "potm",
+  "ppsm",
+  "ppam",
+  "sldm",
+];
+
+/// Containers that are mounted rather than opened, a common way around the
+/// checks of a mail client.
+const DISK_IMAGE_EXTENSIONS: [&str; 4] = ["iso", "img", "vhd", "vhdx"];
+
+/// Declared types that say nothing about the content.
+const GENERIC_MIME_TYPES: [&str; 3] = [
+  "application/octet-stream",
+  "application/unknown",
+  "binary/octet-stream",
+];
+
+/// Magic numbers of the types a mismatch is worth reporting for, along with the
+/// mime type they reveal.
+const MAGIC_NUMBERS: [(&[u8], &str); 13] = [
+  (b"MZ", MIME_EXECUTABLE),
+  (b"\x7fELF", MIME_EXECUTABLE),
+  (b"#!", MIME_SCRIPT),
+  (b"%PDF-", "application/pdf"),
+  (b"\x89PNG\r\n\x1a\n", "image/png"),
+  (b"\xff\xd8\xff", "image/jpeg"),
+  (b"GIF87a", "image/gif"),
+  (b"GIF89a", "image/gif"),
+  (b"PK\x03\x04", MIME_ZIP),
+  (b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1", MIME_OLE),
+  (b"Rar!\x1a\x07", "application/vnd.rar"),
+  (b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+  (b"{\\rtf", "application/rtf"),
+];
+
+const MIME_EXECUTABLE: &str = "application/x-executable";
+const MIME_SCRIPT: &str = "text/x-script";
+const MIME_ZIP: &str = "application/zip";
+const MIME_OLE: &str = "application/x-ole-storage";
+
+/// Characters that change the direction of the text, so that `exe.pdf` can be
+/// displayed for a name that really ends with `fdp.exe`.
+const BIDI_CONTROLS: [char; 9] = [
+  '\u{202a}',
+  '\u{202b}',
+  '\u{202c}',
+  '\u{202d}',
+  '\u{202e}',
+  '\u{2066}',
+  '\u{2067}',
+  '\u{2068}',
+  '\u{2069}',
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RiskLevel {
+  Safe,
+  Suspicious,
+  Dangerous,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RiskReason {
+  Executable(String),
+  Script(String),
+  DoubleExtension(String),
+  MacroEnabled(String),
+  DiskImage(String),
+  HiddenExecutable,
+  TypeMismatch { declared: String, detected: String },
+  BidiOverride,
+}
+
+impl RiskReason {
+  pub fn level(&self) -> RiskLevel {
+    match self {
+      RiskReason::Executable(_)
+      | RiskReason::Script(_)
+      | RiskReason::DoubleExtension(_)
+      | RiskReason::HiddenExecutable
+      | RiskReason::BidiOverride => RiskLevel::Dangerous,
+      RiskReason::MacroEnabled(_)
+      | RiskReason::DiskImage(_)
+      | RiskReason::TypeMismatch {
+        ..
+      } => RiskLevel::Suspicious,
+    }
+  }
+
+  pub fn description(&self) -> String {
+    match self {
+      RiskReason::Executable(extension) => {
+        gettext("Executable program (.{extension})").replace("{extension}", extension)
+      }
+      RiskReason::Script(extension) => {
+        gettext("Script or shortcut (.{extension})").replace("{extension}", extension)
+      }
+      RiskReason::DoubleExtension(extension) => {
+        gettext("Double extension, disguised as a .{extension} file")
+          .replace("{extension}", extension)
+      }
+      RiskReason::MacroEnabled(extension) => {
+        gettext("Office document with macros (.{extension})").replace("{extension}", extension)
+      }
+      RiskReason::DiskImage(extension) => {
+        gettext("Disk image (.{extension})").replace("{extension}", extension)
+      }
+      RiskReason::HiddenExecutable => gettext("The content is an executable program"),
+      RiskReason::TypeMismatch {
+        declared,
+        detected,
+      } => gettext("Declared as {declared} but the content is {detected}")
+        .replace("{declared}", declared)
+        .replace("{detected}", detected),
+      RiskReason::BidiOverride => {
+        gettext("The name contains characters that reverse the text, hiding its real extension")
+      }
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Risk {
+  pub level: RiskLevel,
+  pub reasons: Vec<RiskReason>,
+}
+
+impl Risk {
+  /// Looks at what an attachment claims to be and at what it is. Every check
+  /// is a hint on its own, the level is the one of the worst reason found.
+  pub fn classify(filename: &str, mime_type: Option<&str>, body: &[u8]) -> Self {
+    let mut reasons = vec![];
+
+    if filename.contains(BIDI_CONTROLS) {
+      reasons.push(RiskReason::BidiOverride);
+    }
+    // The name as it is displayed once the direction controls are ignored.
+    let name: String = filename
+      .chars()
+      .filter(|c| !BIDI_CONTROLS.contains(c))
+      .collect::<String>()
+      .to_lowercase();
+    let mut extensions = name.trim_end_matches(['.', ' ']).rsplit('.');
+    let extension = extensions.next().map(str::trim).unwrap_or_default();
+    let previous = extensions.next().map(str::trim);
+    let has_extension = name.contains('.');
+
+    let dangerous = if !has_extension {
+      None
+    } else if EXECUTABLE_EXTENSIONS.contains(&extension) {
+      Some(RiskReason::Executable(extension.to_string()))
+    } else if SCRIPT_EXTENSIONS.contains(&extension) {
+      Some(RiskReason::Script(extension.to_string()))
+    } else {
+      None
+    };
+    if let Some(reason) = dangerous {
+      // invoice.pdf.exe : a name made to look like a document.
+      if let Some(previous) = previous.filter(|previous| DOCUMENT_EXTENSIONS.contains(previous)) {
+        reasons.push(RiskReason::DoubleExtension(previous.to_string()));
+      }
+      reasons.push(reason);
+    } else if has_extension && MACRO_EXTENSIONS.contains(&extension) {
+      reasons.push(RiskReason::MacroEnabled(extension.to_string()));
+    } else if has_extension && DISK_IMAGE_EXTENSIONS.contains(&extension) {
+      reasons.push(RiskReason::DiskImage(extension.to_string()));
+    }
+
+    let declared = mime_type
+      .map(|mime_type| {
+        let mime_type = mime_type.split(';').next().unwrap_or_default();
+        mime_type.trim().to_lowercase()
+      })
+      .unwrap_or_default();
+    if let Some(detected) = sniff(body) {
+      let executable = detected == MIME_EXECUTABLE || detected == MIME_SCRIPT;
+      let expected = EXECUTABLE_EXTENSIONS.contains(&extension)
+        || SCRIPT_EXTENSIONS.contains(&extension)
+        || declared.contains("executable")
+        || declared.contains("msdownload")
+        || declared.contains("script");
+      if executable && !expected {
+        reasons.push(RiskReason::HiddenExecutable);
+      } else if !executable && !is_compatible(&declared, detected) {
+        reasons.push(RiskReason::TypeMismatch {
+          declared,
+          detected: detected.to_string(),
+        });
+      }
+    }
+
+    let level = reasons
+      .iter()
+      .map(RiskReason::level)
+      .max()
+      .unwrap_or(RiskLevel::Safe);
+    Self {
+      level,
+      reasons,
+    }
+  }
+
+  pub fn description(&self) -> String {
+    self
+      .reasons
+      .iter()
+      .map(RiskReason::description)
+      .collect::<Vec<_>>()
+      .join("\n")
+  }
+}
+
+/// The type the first bytes of `body` reveal, for the few types worth it.
+fn sniff(body: &[u8]) -> Option<&'static str> {
+  MAGIC_NUMBERS
+    .iter()
+    .find(|(magic, _)| body.starts_with(magic))
+    .map(|(_, mime_type)| *mime_type)
+}
+
+/// Whether content of the `detected` type can honestly be declared as
+/// `declared`. A lot of formats are zip or ole containers under their own name.
+fn is_compatible(declared: &str, detected: &str) -> bool {
+  if declared.is_empty() || declared == detected || GENERIC_MIME_TYPES.contains(&declared) {
+    return true;
+  }
+  match detected {
+    MIME_ZIP => {
+      declared.contains("zip")
+        || declared.contains("openxmlformats")
+        || declared.contains("opendocument")
+        || declared.contains("java-archive")
+        || declared.contains("epub")
+        || declared.contains("macroenabled")
+    }
+    MIME_OLE => {
+      declared.starts_with("application/vnd.ms-")
+        || declared == "application/msword"
+        || declared == "application/msoutlook"
+        || declared == "application/x-msi"
+    }
+    "image/jpeg" => declared == "image/jpg" || declared == "image/pjpeg",
+    "application/rtf" => declared == "text/rtf" || declared == "application/msword",
+    "application/pdf" => declared == "application/x-pdf",
+    _ => false,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::message::risk::{Risk, RiskLevel, RiskReason};
+
+  const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+  const PDF: &[u8] = b"%PDF-1.7\n";
+  const EXE: &[u8] = b"MZ\x90\0\x03\0";
+
+  #[test]
+  fn regular_files_are_safe() {
+    let risk = Risk::classify("Deus_Gnome.png", Some("image/png"), PNG);
+    assert_eq!(risk.level, RiskLevel::Safe);
+    assert!(risk.reasons.is_empty());
+
+    let risk = Risk::classify("report.pdf", Some("application/octet-stream"), PDF);
+    assert_eq!(risk.level, RiskLevel::Safe);
+
+    let risk = Risk::classify("notes.txt", Some("text/plain"), b"hello");
+    assert_eq!(risk.level, RiskLevel::Safe);
+  }
+
+  #[test]
+  fn programs_and_scripts_are_dangerous() {
+    let risk = Risk::classify("setup.EXE", Some("application/x-msdownload"), EXE);
+    assert_eq!(risk.level, RiskLevel::Dangerous);
+    assert_eq!(risk.reasons, vec![RiskReason::Executable("exe".into())]);
+
+    let risk = Risk::classify("run.ps1", Some("text/plain"), b"Write-Host hi");
+    assert_eq!(risk.level, RiskLevel::Dangerous);
+    assert_eq!(risk.reasons, vec![RiskReason::Script("ps1".into())]);
+  }
+
+  #[test]
+  fn a_double_extension_is_reported() {
+    let risk = Risk::classify("invoice.pdf.exe", Some("application/pdf"), EXE);
+    assert_eq!(risk.level, RiskLevel::Dangerous);
+    assert_eq!(risk.reasons[0], RiskReason::DoubleExtension("pdf".into()));
+    assert_eq!(risk.reasons[1], RiskReason::Executable("exe".into()));
+
+    let risk = Risk::classify("invoice.pdf     .scr", None, b"");
+    assert_eq!(risk.reasons[0], RiskReason::DoubleExtension("pdf".into()));
+
+    // Only the version of a program, not a disguise.
+    let risk = Risk::classify("setup-1.2.exe", None, EXE);
+    assert_eq!(risk.reasons, vec![RiskReason::Executable("exe".into())]);
+  }
+
+  #[test]
+  fn macros_and_disk_images_are_suspicious() {
+    let risk = Risk::classify(
+      "budget.xlsm",
+      Some("application/vnd.ms-excel.sheet.macroEnabled.12"),
+      b"PK\x03\x04",
+    );
+    assert_eq!(risk.level, RiskLevel::Suspicious);
+    assert_eq!(risk.reasons, vec![RiskReason::MacroEnabled("xlsm".into())]);
+
+    let risk = Risk::classify("scan.iso", None, b"");
+    assert_eq!(risk.reasons, vec![RiskReason::DiskImage("iso".into())]);
+  }
+
+  #[test]
+  fn the_content_is_compared_to_the_declared_type() {
+    let risk = Risk::classify("photo.png", Some("image/png"), PDF);
+    assert_eq!(risk.level, RiskLevel::Suspicious);
+    assert_eq!(risk.reasons, vec![RiskReason::TypeMismatch {
+      declared: "image/png".into(),
+      detected: "application/pdf".into(),
+    }]);
+
+    let risk = Risk::classify("report.pdf", Some("application/pdf"), EXE);
+    assert_eq!(risk.level, RiskLevel::Dangerous);
+    assert_eq!(risk.reasons, vec![RiskReason::HiddenExecutable]);
+
+    let risk = Risk::classify(
+      "letter.docx",
+      Some("application/vnd.openxmlformats-officedocument.wordprocessingml.document"),
+      b"PK\x03\x04",
+    );
+    assert_eq!(risk.level, RiskLevel::Safe);
+  }
+
+  #[test]
+  fn a_reversed_name_is_dangerous() {
+    // Displayed as "invoice_exe.pdf"
+    let risk = Risk::classify("invoice_\u{202e}fdp.exe", Some("application/pdf"), EXE);
+    assert_eq!(risk.level, RiskLevel::Dangerous);
+    assert_eq!(risk.reasons[0], RiskReason::BidiOverride);
+    assert!(risk.reasons.contains(&RiskReason::Executable("exe".into())));
+  }
+}
@@ -0,0 +1,205 @@
+/* summary.rs
+ *
+ * Copyright 2024 Alexandre Del Bigio
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! What a message list shows of a message, read without parsing its body.
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use gmime::prelude::Cast;
+use gmime::traits::{ContentTypeExt, MessageExt, ObjectExt, ParserExt, PartExt, StreamExt};
+use gmime::{InternetAddressList, InternetAddressListExt, Parser, Part, StreamMem};
+
+use super::message::{Message, MessageParser};
+use super::outlook::OutlookMessage;
+use crate::gio;
+
+#[derive(Debug, Clone, Default)]
+pub struct MessageSummary {
+  pub path: PathBuf,
+  pub date: Option<gmime::DateTime>,
+  pub from: String,
+  pub to: String,
+  pub subject: String,
+  /// The size of the file, in bytes.
+  pub size: u64,
+  pub attachments: usize,
+}
+
+impl MessageSummary {
+  /// Reads the summary of a message file. It blocks, so is meant to be called
+  /// from `gio::spawn_blocking()`.
+  pub fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
+    let content = fs::read(path)?;
+    let (content_type, _) = gio::content_type_guess(Some(path), Some(content.as_slice()));
+    let size = content.len() as u64;
+    let summary = if MessageParser::is_outlook(&content_type) {
+      Self::from_msg(content)?
+    } else {
+      Self::from_eml(&content)
+    };
+    Ok(Self {
+      path: path.to_path_buf(),
+      size,
+      ..summary
+    })
+  }
+
+  /// Only the top level headers are decoded. The attachments are counted from
+  /// the MIME structure, the content of the parts is not decoded.
+  fn from_eml(content: &[u8]) -> Self {
+    let text = String::from_utf8_lossy(content);
+    let headers = MessageParser::split_headers(&text);
+    let header = |name: &str| {
+      headers
+        .iter()
+        .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+        .collect::<Vec<&str>>()
+        .join(", ")
+    };
+    let date = header("Date");
+    Self {
+      from: Self::addresses(&header("From")),
+      to: Self::addresses(
+        &[header("To"), header("Cc"), header("Bcc")]
+          .into_iter()
+          .filter(|value| !value.is_empty())
+          .collect::<Vec<String>>()
+          .join(", "),
+      ),
+      subject: gmime::functions::utils_header_decode_text(None, &header("Subject"))
+        .map(|subject| subject.to_string())
+        .unwrap_or_default(),
+      date: (!date.is_empty())
+        .then(|| gmime::functions::utils_header_decode_date(&date))
+        .flatten(),
+      attachments: Self::count_attachments(content),
+      ..Default::default()
+    }
+  }
+
+  /// A .msg file has no header block to stop at, it is parsed whole.
+  fn from_msg(content: Vec<u8>) -> Result<Self, Box<dyn Error>> {
+    let mut message = OutlookMessage::new(content);
+    message.parse(None)?;
+    Ok(Self {
+      attachments: message.attachments().len(),
+      from: message.from,
+      to: message.to,
+      subject: message.subject,
+      date: message.date,
+      ..Default::default()
+    })
+  }
+
+  fn addresses(value: &str) -> String {
+    match InternetAddressList::parse(None, value) {
+      Some(list) => InternetAddressListExt::to_string(&list, None, false)
+        .map(|addresses| addresses.to_string())
+        .unwrap_or_default(),
+      None => value.to_string(),
+    }
+  }
+
+  /// The parts the viewer lists as attachments: those said to be, and those
+  /// which are neither the text nor the html of the message.
+  fn count_attachments(content: &[u8]) -> usize {
+    let stream = StreamMem::with_buffer(content);
+    let parser = Parser::with_stream(&stream);
+    let mut count = 0;
+    if let Some(message) = parser.construct_message(None) {
+      message.foreach(|_, current| {
+        let Some(part) = current.dynamic_cast_ref::<Part>() else {
+          return;
+        };
+        let is_body = part.content_type().is_some_and(|content_type| {
+          content_type.is_type("text", "html") || content_type.is_type("text", "plain")
+        });
+        if part.is_attachment() || !is_body {
+          count += 1;
+        }
+      });
+    }
+    stream.close();
+    count
+  }
+
+  pub fn date_text(&self) -> String {
+    MessageParser::to_local_date(&self.date)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::message::summary::*;
+
+  #[test]
+  fn summary_eml() {
+    let summary = MessageSummary::read(Path::new("sample.eml")).unwrap();
+    assert_eq!(summary.from, "John Doe <john@moon.space>");
+    assert_eq!(summary.to, "Lucas <lucas@mercure.space>");
+    assert_eq!(summary.subject, "Lorem ipsum");
+    assert_eq!(summary.date.map(|date| date.to_unix()), Some(1729679241));
+    assert_eq!(summary.attachments, 1);
+    assert_eq!(summary.size, fs::metadata("sample.eml").unwrap().len());
+  }
+
+  #[test]
+  fn summary_msg() {
+    let summary = MessageSummary::read(Path::new("sample.msg")).unwrap();
+    assert_eq!(summary.from, "John Doe <john@moon.space>");
+    assert_eq!(summary.subject, "Lorem ipsum");
+    assert_eq!(summary.attachments, 3);
+  }
+
+  #[test]
+  fn attachments_from_the_mime_structure() {
+    // A folded header counts, a quoted one and the parts of an attached
+    // message don't.
+    let eml = "MIME-Version: 1.0\r
+Content-Type: multipart/mixed; boundary=\"outer\"\r
+\r
+--outer\r
+Content-Type: text/plain\r
+\r
+Content-Disposition: attachment\r
+--outer\r
+Content-Type: application/pdf\r
+Content-Disposition:\r
+ attachment; filename=\"report.pdf\"\r
+\r
+JVBERi0xLjQK\r
+--outer\r
+Content-Type: message/rfc822\r
+\r
+Content-Type: multipart/mixed; boundary=\"inner\"\r
+\r
+--inner\r
+Content-Type: image/png\r
+Content-Disposition: attachment; filename=\"logo.png\"\r
+\r
+iVBORw0KGgo=\r
+--inner--\r
+--outer--\r
+";
+    assert_eq!(MessageSummary::count_attachments(eml.as_bytes()), 1);
+  }
+}
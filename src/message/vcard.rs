@@ -0,0 +1,275 @@
+/* vcard.rs
+ *
+ * Copyright 2024 Alexandre Del Bigio
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig};
+use base64::engine::DecodePaddingMode;
+use base64::{alphabet, Engine};
+
+use super::contentline::{unfold, Property};
+
+const VCARD_MIME_TYPES: [&str; 3] = ["text/vcard", "text/x-vcard", "text/directory"];
+
+const VCARD_EXTENSIONS: [&str; 2] = ["vcf", "vcard"];
+
+/// Photos are mostly small thumbnails. Anything larger is not decoded.
+const MAX_PHOTO_SIZE: usize = 1024 * 1024;
+
+/// Photos of vCard 2.1 files often lack their padding.
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+  &alphabet::STANDARD,
+  GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// An email address or a phone number, with the types it has in the card
+/// (work, home, cell...).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContactValue {
+  pub value: String,
+  pub types: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Contact {
+  pub name: String,
+  pub organization: String,
+  pub title: String,
+  pub emails: Vec<ContactValue>,
+  pub phones: Vec<ContactValue>,
+  /// An embedded photo, never one to download.
+  pub photo: Option<Vec<u8>>,
+  /// The card as it was sent, for an export to lose nothing.
+  pub vcard: String,
+}
+
+impl Contact {
+  /// Whether an attachment is a vCard file, by its declared type or its name.
+  pub fn is_vcard(filename: &str, mime_type: Option<&str>) -> bool {
+    let mime_type = mime_type.unwrap_or_default().to_lowercase();
+    let extension = filename
+      .rsplit_once('.')
+      .map(|(_, extension)| extension.to_lowercase())
+      .unwrap_or_default();
+    VCARD_MIME_TYPES.contains(&mime_type.as_str()) || VCARD_EXTENSIONS.contains(&extension.as_str())
+  }
+
+  /// The contacts of a vCard file, of any version from 2.1 to 4.0.
+  pub fn parse(text: &str) -> Vec<Self> {
+    let mut contacts = vec![];
+    let mut properties: Option<Vec<Property>> = None;
+    let mut lines: Vec<String> = vec![];
+    for line in unfold(text) {
+      let Some(property) = Property::parse(&line) else {
+        continue;
+      };
+      let is_vcard = property.value.trim().eq_ignore_ascii_case("VCARD");
+      match property.name.as_str() {
+        "BEGIN" if is_vcard => {
+          properties = Some(vec![]);
+          lines.clear();
+          lines.push(line);
+        }
+        "END" if is_vcard => {
+          if let Some(properties) = properties.take() {
+            lines.push(line);
+            contacts.push(Self::from_properties(&properties, &lines));
+          }
+        }
+        _ => {
+          if let Some(properties) = properties.as_mut() {
+            properties.push(property);
+            lines.push(line);
+          }
+        }
+      }
+    }
+    contacts
+  }
+
+  fn from_properties(properties: &[Property], lines: &[String]) -> Self {
+    let mut contact = Self {
+      vcard: lines.join("\r\n") + "\r\n",
+      ..Default::default()
+    };
+    let mut structured_name = String::new();
+    for property in properties {
+      match property.name.as_str() {
+        "FN" => contact.name = property.text().trim().to_string(),
+        "N" => {
+          // Family; Given; Additional; Prefixes; Suffixes
+          let components = property.components();
+          let mut parts: Vec<&str> = vec![];
+          for index in [3, 1, 2, 0, 4] {
+            if let Some(part) = components.get(index).map(|part| part.trim()) {
+              if !part.is_empty() {
+                parts.push(part);
+              }
+            }
+          }
+          structured_name = parts.join(" ");
+        }
+        "ORG" => {
+          contact.organization = property
+            .components()
+            .iter()
+            .map(|part| part.trim())
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<&str>>()
+            .join(", ")
+        }
+        "TITLE" => contact.title = property.text().trim().to_string(),
+        "EMAIL" => contact
+          .emails
+          .push(Self::value(property, &["internet", "pref"])),
+        "TEL" => contact.phones.push(Self::value(property, &["pref"])),
+        "PHOTO" if contact.photo.is_none() => contact.photo = Self::photo(property),
+        _ => (),
+      }
+    }
+    if contact.name.is_empty() {
+      contact.name = structured_name;
+    }
+    contact.emails.retain(|email| !email.value.is_empty());
+    contact.phones.retain(|phone| !phone.value.is_empty());
+    contact
+  }
+
+  fn value(property: &Property, hidden_types: &[&str]) -> ContactValue {
+    let value = property.text().trim().to_string();
+    let value = match value.get(..4) {
+      Some(scheme) if scheme.eq_ignore_ascii_case("tel:") => value[4..].to_string(),
+      _ => value,
+    };
+    ContactValue {
+      value,
+      types: property
+        .param_values("TYPE")
+        .into_iter()
+        .filter(|kind| !hidden_types.contains(&kind.as_str()))
+        .collect(),
+    }
+  }
+
+  /// "data:image/jpeg;base64,..." (4.0) or an inline base64 value (2.1 and
+  /// 3.0). URIs are ignored: loading them would tell the sender the card was
+  /// opened.
+  fn photo(property: &Property) -> Option<Vec<u8>> {
+    let value = property.value.trim();
+    let encoded = if let Some(data) = value.strip_prefix("data:") {
+      let (header, data) = data.split_once(',')?;
+      if !header.ends_with(";base64") {
+        return None;
+      }
+      data
+    } else {
+      let encoding = property.param("ENCODING")?;
+      if !encoding.eq_ignore_ascii_case("b") && !encoding.eq_ignore_ascii_case("BASE64") {
+        return None;
+      }
+      value
+    };
+    if encoded.len() > MAX_PHOTO_SIZE * 4 / 3 + 4 {
+      return None;
+    }
+    let encoded: String = encoded.chars().filter(|c| !c.is_whitespace()).collect();
+    BASE64.decode(encoded).ok()
+  }
+}
+
+/// All the contacts in a single .vcf file, each as it was sent.
+pub fn to_vcf(contacts: &[Contact]) -> String {
+  contacts
+    .iter()
+    .map(|contact| contact.vcard.as_str())
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::message::vcard::*;
+
+  const VCARDS: &str = "BEGIN:VCARD\r
+VERSION:3.0\r
+N:Doe;John;;Dr.;\r
+FN:John Doe\r
+ORG:Moon Corp;Space\\, Exploration\r
+TITLE:Astronaut\r
+EMAIL;TYPE=INTERNET,WORK:john@moon.space\r
+EMAIL;TYPE=INTERNET,HOME:\r
+item1.TEL;TYPE=CELL:+33 6 12 34 56 78\r
+PHOTO;ENCODING=b;TYPE=PNG:iVBORw0KGgo\r
+END:VCARD\r
+BEGIN:VCARD\r
+VERSION:2.1\r
+N;CHARSET=UTF-8;ENCODING=QUOTED-PRINTABLE:Dupr=C3=A9;=\r
+Lucas\r
+TEL;WORK;VOICE:+33 1 23 45 67 89\r
+PHOTO;VALUE=URI:https://tracker.example/lucas.jpg\r
+END:VCARD\r
+BEGIN:VCARD\r
+VERSION:4.0\r
+FN:Mars\r
+TEL;VALUE=uri;TYPE=home:tel:+1-555-0100\r
+PHOTO:data:image/png;base64,iVBORw0KGgo=\r
+END:VCARD\r
+";
+
+  #[test]
+  fn parse_contacts() {
+    let contacts = Contact::parse(VCARDS);
+    assert_eq!(contacts.len(), 3);
+
+    let john = &contacts[0];
+    assert_eq!(john.name, "John Doe");
+    assert_eq!(john.organization, "Moon Corp, Space, Exploration");
+    assert_eq!(john.title, "Astronaut");
+    assert_eq!(john.emails, vec![ContactValue {
+      value: "john@moon.space".to_string(),
+      types: vec!["work".to_string()]
+    }]);
+    assert_eq!(john.phones[0].value, "+33 6 12 34 56 78");
+    assert_eq!(john.phones[0].types, ["cell"]);
+    assert_eq!(john.photo.as_deref(), Some(&b"\x89PNG\r\n\x1a\n"[..]));
+
+    let lucas = &contacts[1];
+    assert_eq!(lucas.name, "Lucas Dupré");
+    assert_eq!(lucas.phones[0].types, ["work", "voice"]);
+    assert_eq!(lucas.photo, None);
+
+    let mars = &contacts[2];
+    assert_eq!(mars.phones[0].value, "+1-555-0100");
+    assert_eq!(mars.phones[0].types, ["home"]);
+    assert_eq!(mars.photo.as_deref(), Some(&b"\x89PNG\r\n\x1a\n"[..]));
+  }
+
+  #[test]
+  fn export_contacts() {
+    let contacts = Contact::parse(VCARDS);
+    let vcf = to_vcf(&contacts);
+    assert_eq!(vcf.matches("BEGIN:VCARD\r\n").count(), 3);
+    assert!(vcf.contains("item1.TEL;TYPE=CELL:+33 6 12 34 56 78\r\n"));
+    assert_eq!(Contact::parse(&vcf), contacts);
+  }
+
+  #[test]
+  fn vcard_attachments() {
+    assert!(Contact::is_vcard("john.vcf", None));
+    assert!(Contact::is_vcard("contact", Some("text/x-vcard")));
+    assert!(!Contact::is_vcard("notes.txt", Some("text/plain")));
+  }
+}
@@ -0,0 +1,306 @@
+/* messagelist.rs
+ *
+ * Copyright 2024 Alexandre Del Bigio
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::error::Error;
+use std::path::PathBuf;
+
+use adw::glib::clone;
+use adw::prelude::*;
+use gettextrs::{gettext, ngettext};
+use gtk4::{gio, glib};
+
+use crate::mailfolder::MailFolder;
+use crate::message::summary::MessageSummary;
+
+/// How many messages a background thread reads at once, the list grows by as
+/// many.
+const SUMMARIES_PER_THREAD: usize = 32;
+
+/// The messages of a folder in a column view, to sort and filter them.
+#[derive(Debug)]
+pub struct MessageList {
+  widget: gtk4::Box,
+  store: gio::ListStore,
+  selection: gtk4::SingleSelection,
+  search_entry: gtk4::SearchEntry,
+  recursive: gtk4::ToggleButton,
+  close: gtk4::Button,
+  status: gtk4::Label,
+  folder: RefCell<Option<gio::File>>,
+  cancellable: RefCell<gio::Cancellable>,
+}
+
+impl MessageList {
+  pub fn new() -> Self {
+    let store = gio::ListStore::new::<glib::BoxedAnyObject>();
+    let search_entry = gtk4::SearchEntry::new();
+    search_entry.set_hexpand(true);
+    search_entry.set_placeholder_text(Some(&gettext("Filter Messages")));
+
+    let filter = gtk4::CustomFilter::new(clone!(
+      #[weak]
+      search_entry,
+      #[upgrade_or]
+      true,
+      move |item| {
+        let text = search_entry.text().to_lowercase();
+        text.is_empty() || Self::matches(&summary(item), &text)
+      }
+    ));
+    search_entry.connect_search_changed(clone!(
+      #[weak]
+      filter,
+      move |_| filter.changed(gtk4::FilterChange::Different)
+    ));
+    let filter_model = gtk4::FilterListModel::new(Some(store.clone()), Some(filter));
+
+    let column_view = gtk4::ColumnView::new(None::<gtk4::SelectionModel>);
+    column_view.set_show_column_separators(true);
+    column_view.add_css_class("data-table");
+    let date = Self::column(
+      &gettext("Date"),
+      |summary| summary.date_text(),
+      |a, b| {
+        let unix = |summary: &MessageSummary| summary.date.as_ref().map(|date| date.to_unix());
+        unix(a).cmp(&unix(b))
+      },
+    );
+    column_view.append_column(&date);
+    column_view.append_column(&Self::column(
+      &gettext("From"),
+      |summary| summary.from.clone(),
+      |a, b| a.from.to_lowercase().cmp(&b.from.to_lowercase()),
+    ));
+    column_view.append_column(&Self::column(
+      &gettext("To"),
+      |summary| summary.to.clone(),
+      |a, b| a.to.to_lowercase().cmp(&b.to.to_lowercase()),
+    ));
+    let subject = Self::column(
+      &gettext("Subject"),
+      |summary| summary.subject.clone(),
+      |a, b| a.subject.to_lowercase().cmp(&b.subject.to_lowercase()),
+    );
+    subject.set_expand(true);
+    column_view.append_column(&subject);
+    column_view.append_column(&Self::column(
+      &gettext("Size"),
+      |summary| glib::format_size(summary.size).to_string(),
+      |a, b| a.size.cmp(&b.size),
+    ));
+    column_view.append_column(&Self::column(
+      &gettext("Attachments"),
+      |summary| match summary.attachments {
+        0 => String::new(),
+        count => count.to_string(),
+      },
+      |a, b| a.attachments.cmp(&b.attachments),
+    ));
+
+    let sort_model = gtk4::SortListModel::new(Some(filter_model), column_view.sorter());
+    let selection = gtk4::SingleSelection::new(Some(sort_model));
+    selection.set_autoselect(false);
+    selection.set_can_unselect(true);
+    column_view.set_model(Some(&selection));
+    column_view.sort_by_column(Some(&date), gtk4::SortType::Descending);
+
+    let recursive = gtk4::ToggleButton::new();
+    recursive.set_icon_name("folder-open-symbolic");
+    recursive.set_tooltip_text(Some(&gettext("Include Subfolders")));
+    let close = gtk4::Button::from_icon_name("window-close-symbolic");
+    close.set_tooltip_text(Some(&gettext("Close Folder")));
+    close.add_css_class("flat");
+
+    let header = gtk4::Box::new(gtk4::Orientation::Horizontal, 6);
+    header.append(&search_entry);
+    header.append(&recursive);
+    header.append(&close);
+
+    let status = gtk4::Label::new(None);
+    status.set_xalign(0.0);
+    status.set_ellipsize(gtk4::pango::EllipsizeMode::Middle);
+    status.add_css_class("dim-label");
+    status.add_css_class("caption");
+
+    let scrolled_window = gtk4::ScrolledWindow::new();
+    scrolled_window.set_vexpand(true);
+    scrolled_window.set_child(Some(&column_view));
+
+    let widget = gtk4::Box::new(gtk4::Orientation::Vertical, 6);
+    widget.set_margin_top(5);
+    widget.set_margin_start(10);
+    widget.append(&header);
+    widget.append(&status);
+    widget.append(&scrolled_window);
+
+    Self {
+      widget,
+      store,
+      selection,
+      search_entry,
+      recursive,
+      close,
+      status,
+      folder: RefCell::new(None),
+      cancellable: RefCell::new(gio::Cancellable::new()),
+    }
+  }
+
+  fn column<T, C>(title: &str, text: T, compare: C) -> gtk4::ColumnViewColumn
+  where
+    T: Fn(&MessageSummary) -> String + 'static,
+    C: Fn(&MessageSummary, &MessageSummary) -> Ordering + 'static,
+  {
+    let factory = gtk4::SignalListItemFactory::new();
+    factory.connect_setup(|_, item| {
+      let label = gtk4::Label::new(None);
+      label.set_xalign(0.0);
+      label.set_ellipsize(gtk4::pango::EllipsizeMode::End);
+      if let Some(item) = item.downcast_ref::<gtk4::ListItem>() {
+        item.set_child(Some(&label));
+      }
+    });
+    factory.connect_bind(move |_, item| {
+      let Some(item) = item.downcast_ref::<gtk4::ListItem>() else {
+        return;
+      };
+      if let (Some(label), Some(object)) = (item.child().and_downcast::<gtk4::Label>(), item.item())
+      {
+        let value = text(&summary(&object));
+        label.set_tooltip_text(Some(&value));
+        label.set_label(&value);
+      }
+    });
+
+    let sorter = gtk4::CustomSorter::new(move |a, b| compare(&summary(a), &summary(b)).into());
+    let column = gtk4::ColumnViewColumn::new(Some(title), Some(factory));
+    column.set_resizable(true);
+    column.set_sorter(Some(&sorter));
+    column
+  }
+
+  fn matches(summary: &MessageSummary, text: &str) -> bool {
+    [&summary.from, &summary.to, &summary.subject]
+      .iter()
+      .any(|value| value.to_lowercase().contains(text))
+  }
+
+  pub fn widget(&self) -> &gtk4::Box {
+    &self.widget
+  }
+
+  pub fn folder(&self) -> Option<gio::File> {
+    self.folder.borrow().clone()
+  }
+
+  /// Calls `open` with the message the user selects.
+  pub fn connect_open<F: Fn(gio::File) + 'static>(&self, open: F) {
+    self
+      .selection
+      .connect_selected_item_notify(move |selection| {
+        if let Some(object) = selection.selected_item() {
+          open(gio::File::for_path(&summary(&object).path));
+        }
+      });
+  }
+
+  pub fn connect_recursive_toggled<F: Fn() + 'static>(&self, toggled: F) {
+    self.recursive.connect_toggled(move |_| toggled());
+  }
+
+  pub fn connect_close<F: Fn() + 'static>(&self, close: F) {
+    self.close.connect_clicked(move |_| close());
+  }
+
+  /// Stops reading the messages of the folder, and forgets them.
+  pub fn clear(&self) {
+    self.cancellable.borrow().cancel();
+    self.folder.replace(None);
+    self.store.remove_all();
+    self.search_entry.set_text("");
+    self.status.set_label("");
+  }
+
+  /// Lists the messages of `folder`, and of its subfolders when asked. They
+  /// are read in the background and show as they are.
+  pub async fn load(&self, folder: &gio::File) -> Result<(), Box<dyn Error>> {
+    log::debug!("MessageList::load({:?})", folder.peek_path());
+    self.clear();
+    let cancellable = gio::Cancellable::new();
+    self.cancellable.replace(cancellable.clone());
+    self.folder.replace(Some(folder.clone()));
+    self.status.set_label(&gettext("Looking for messages…"));
+
+    let paths: Vec<PathBuf> = MailFolder::scan(folder, self.recursive.is_active())
+      .await?
+      .iter()
+      .filter_map(|file| file.path())
+      .collect();
+    for chunk in paths.chunks(SUMMARIES_PER_THREAD) {
+      if cancellable.is_cancelled() {
+        return Ok(());
+      }
+      let chunk = chunk.to_vec();
+      let summaries = gio::spawn_blocking(move || {
+        chunk
+          .iter()
+          .filter_map(|path| match MessageSummary::read(path) {
+            Ok(summary) => Some(summary),
+            Err(e) => {
+              log::debug!("MessageSummary::read({:?}): {}", path, e);
+              None
+            }
+          })
+          .collect::<Vec<MessageSummary>>()
+      })
+      .await
+      .map_err(|_| "Failed to read the messages")?;
+      // A folder loaded meanwhile replaced this one.
+      if cancellable.is_cancelled() {
+        return Ok(());
+      }
+      let objects: Vec<glib::BoxedAnyObject> = summaries
+        .into_iter()
+        .map(glib::BoxedAnyObject::new)
+        .collect();
+      self.store.extend_from_slice(&objects);
+      self.status.set_label(
+        &gettext("Reading messages… {count}/{total}")
+          .replace("{count}", &self.store.n_items().to_string())
+          .replace("{total}", &paths.len().to_string()),
+      );
+    }
+
+    let count = self.store.n_items();
+    self.status.set_label(
+      &ngettext("{count} message", "{count} messages", count)
+        .replace("{count}", &count.to_string()),
+    );
+    Ok(())
+  }
+}
+
+fn summary(object: &glib::Object) -> std::cell::Ref<'_, MessageSummary> {
+  object
+    .downcast_ref::<glib::BoxedAnyObject>()
+    .expect("The list holds message summaries")
+    .borrow::<MessageSummary>()
+}
@@ -0,0 +1,131 @@
+/* mhtml.rs
+ *
+ * Copyright 2024 Alexandre Del Bigio
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+use base64::engine::general_purpose;
+use base64::Engine;
+
+use crate::html::export_mime_type;
+use crate::message::attachment::Attachment;
+
+/// Base64 never writes a `-` nor a `_`, so no line of a part can be taken for
+/// the boundary.
+const BOUNDARY: &str = "----=_MailViewer_Part";
+
+/// The longest line of base64 a part is written with (RFC 2045).
+const LINE_LENGTH: usize = 76;
+
+/// The address an attachment of the archive is linked to from the page.
+pub fn attachment_href(index: usize) -> String {
+  format!("cid:attachment-{index}@mailviewer")
+}
+
+/// A web archive (RFC 2557) of the exported page: the page first, then the
+/// attachments, each one under the address `attachment_href()` gives it.
+pub fn archive(subject: &str, page: &str, attachments: &[Attachment]) -> String {
+  let mut archive = format!(
+    concat!(
+      "From: <Saved by Mail Viewer>\r\n",
+      "Subject: {}\r\n",
+      "MIME-Version: 1.0\r\n",
+      "Content-Type: multipart/related; type=\"text/html\"; boundary=\"{}\"\r\n",
+      "\r\n"
+    ),
+    encode_header(subject),
+    BOUNDARY
+  );
+
+  archive.push_str(&part(
+    "text/html; charset=\"utf-8\"",
+    "Content-ID: <page@mailviewer>\r\n",
+    page.as_bytes(),
+  ));
+  for (index, attachment) in attachments.iter().enumerate() {
+    let filename = encode_header(&attachment.safe_filename()).replace('"', "'");
+    let headers = format!(
+      "Content-ID: <{}>\r\nContent-Disposition: attachment; filename=\"{}\"\r\n",
+      attachment_href(index).trim_start_matches("cid:"),
+      filename
+    );
+    archive.push_str(&part(
+      export_mime_type(attachment),
+      &headers,
+      &attachment.body,
+    ));
+  }
+  archive.push_str(&format!("--{BOUNDARY}--\r\n"));
+  archive
+}
+
+fn part(content_type: &str, headers: &str, body: &[u8]) -> String {
+  let encoded = general_purpose::STANDARD.encode(body);
+  let mut part = format!(
+    "--{BOUNDARY}\r\nContent-Type: {content_type}\r\nContent-Transfer-Encoding: base64\r\n\
+     {headers}\r\n"
+  );
+  // Base64 is ascii, cutting it anywhere is cutting it between characters.
+  for line in encoded.as_bytes().chunks(LINE_LENGTH) {
+    part.push_str(std::str::from_utf8(line).unwrap_or_default());
+    part.push_str("\r\n");
+  }
+  part
+}
+
+/// A header value as it is when it is printable ascii, an encoded word
+/// (RFC 2047) otherwise.
+fn encode_header(value: &str) -> String {
+  let value: String = value.chars().filter(|c| !c.is_control()).collect();
+  if value.chars().all(|c| c.is_ascii() && !c.is_ascii_control()) {
+    return value;
+  }
+  format!(
+    "=?utf-8?B?{}?=",
+    general_purpose::STANDARD.encode(value.as_bytes())
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::message::attachment::Attachment;
+  use crate::mhtml::*;
+
+  #[test]
+  fn web_archive() {
+    let attachments = [Attachment {
+      filename: "Réunion.ics".to_string(),
+      content_id: String::new(),
+      body: vec![b'x'; 60],
+      mime_type: Some("text/calendar".to_string()),
+    }];
+    let archive = archive("Café\r\nBcc: x", "<p>hi</p>", &attachments);
+
+    assert!(archive
+      .starts_with("From: <Saved by Mail Viewer>\r\nSubject: =?utf-8?B?Q2Fmw6lCY2M6IHg=?=\r\n"));
+    assert!(archive.contains(
+      "Content-Type: text/html; charset=\"utf-8\"\r\nContent-Transfer-Encoding: base64\r\n\
+       Content-ID: <page@mailviewer>\r\n\r\nPHA+aGk8L3A+\r\n"
+    ));
+    assert!(archive.contains(
+      "Content-Type: text/calendar\r\nContent-Transfer-Encoding: base64\r\n\
+       Content-ID: <attachment-0@mailviewer>\r\n\
+       Content-Disposition: attachment; filename=\"=?utf-8?B?UsOpdW5pb24uaWNz?=\"\r\n\r\n"
+    ));
+    assert!(archive.contains(&format!("\r\n\r\n{}\r\neHh4\r\n", "eHh4".repeat(19))));
+    assert!(archive.ends_with("------=_MailViewer_Part--\r\n"));
+  }
+}
@@ -0,0 +1,232 @@
+/* previewdialog.rs
+ *
+ * Copyright 2024 Alexandre Del Bigio
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use adw::glib::clone;
+use adw::prelude::*;
+use gtk4::{gdk_pixbuf, glib};
+use webkit6::prelude::{PolicyDecisionExt, WebViewExt};
+use webkit6::{NavigationPolicyDecision, PolicyDecisionType, WebView};
+
+use crate::html::Html;
+use crate::message::attachment::Attachment;
+use crate::message::preview::{decode_text, PreviewKind};
+use crate::utils;
+use crate::utils::image::{load_pixbuf, scale_pixbuf, texture};
+use crate::utils::pdf;
+
+const ZOOM_STEP: f64 = 1.25;
+const ZOOM_MIN: f64 = 0.1;
+const ZOOM_MAX: f64 = 5.0;
+
+/// What a preview may load: the document built here. Anything else, links
+/// included, stays out.
+const PREVIEW_URI_SCHEMES: [&str; 1] = ["about"];
+
+/// Shows an attachment without writing it anywhere nor handing it to another
+/// application. `webview` renders HTML attachments and has to be set up
+/// like the one of the message. `open_external` is called when the user asks
+/// for another application after all.
+pub fn show<F: Fn() + 'static>(
+  parent: &impl IsA<gtk4::Widget>,
+  attachment: &Attachment,
+  kind: PreviewKind,
+  webview: WebView,
+  open_external: F,
+) {
+  let builder = gtk4::Builder::from_string(gtk4::include_blueprint!("src/preview.blp"));
+  let dialog: adw::Dialog = builder.object("preview").unwrap();
+  let stack: adw::ViewStack = builder.object("stack").unwrap();
+  let picture: gtk4::Picture = builder.object("picture").unwrap();
+  let pages_box: gtk4::Box = builder.object("pages").unwrap();
+  let text: gtk4::TextView = builder.object("text").unwrap();
+  let web: gtk4::ScrolledWindow = builder.object("web").unwrap();
+  let error: adw::StatusPage = builder.object("error").unwrap();
+  let zoom_box: gtk4::Box = builder.object("zoom_box").unwrap();
+  let zoom_fit: gtk4::Button = builder.object("zoom_fit").unwrap();
+  let zoom_minus: gtk4::Button = builder.object("zoom_minus").unwrap();
+  let zoom_plus: gtk4::Button = builder.object("zoom_plus").unwrap();
+  let open_button: gtk4::Button = builder.object("open_external").unwrap();
+
+  dialog.set_title(&attachment.filename);
+  zoom_box.set_visible(kind != PreviewKind::Text);
+
+  // The image, or the pages of a PDF once they are drawn, each with the
+  // picture showing it.
+  let pages: Rc<RefCell<Vec<(gtk4::Picture, gdk_pixbuf::Pixbuf)>>> = Rc::new(RefCell::new(vec![]));
+  match kind {
+    PreviewKind::Image => match load_pixbuf(&attachment.body) {
+      Ok(image) => {
+        picture.set_paintable(Some(&texture(&image)));
+        pages.borrow_mut().push((picture.clone(), image));
+        stack.set_visible_child_name("image");
+      }
+      Err(e) => {
+        log::error!("load_pixbuf({}): {}", attachment.filename, e);
+        error.set_description(Some(&e.to_string()));
+        zoom_box.set_visible(false);
+        stack.set_visible_child_name("error");
+      }
+    },
+    PreviewKind::Text => {
+      text.buffer().set_text(&decode_text(&attachment.body));
+      stack.set_visible_child_name("text");
+    }
+    PreviewKind::Pdf => {
+      zoom_box.set_sensitive(false);
+      stack.set_visible_child_name("loading");
+      let filename = attachment.filename.clone();
+      let body = attachment.body.clone();
+      glib::spawn_future_local(clone!(
+        #[weak]
+        stack,
+        #[weak]
+        pages_box,
+        #[weak]
+        error,
+        #[weak]
+        zoom_box,
+        #[strong]
+        pages,
+        async move {
+          match pdf::render_pages(&body).await {
+            Ok(rendered) => {
+              for pixbuf in rendered {
+                let page = gtk4::Picture::for_paintable(&texture(&pixbuf));
+                page.set_content_fit(gtk4::ContentFit::Contain);
+                pages_box.append(&page);
+                pages.borrow_mut().push((page, pixbuf));
+              }
+              zoom_box.set_sensitive(true);
+              stack.set_visible_child_name("pages");
+            }
+            Err(e) => {
+              log::error!("render_pages({}): {}", filename, e);
+              error.set_description(Some(&e.to_string()));
+              zoom_box.set_visible(false);
+              stack.set_visible_child_name("error");
+            }
+          }
+        }
+      ));
+    }
+    PreviewKind::Html => {
+      initialise_preview_webview(&webview);
+      let html = Html::new(&decode_text(&attachment.body), false);
+      webview.load_html(&html.safe(), None);
+      web.set_child(Some(&webview));
+      stack.set_visible_child_name("web");
+    }
+  }
+
+  // None is the best fit, when the image is shrunk to the dialog.
+  let zoom: Rc<Cell<Option<f64>>> = Rc::new(Cell::new(None));
+  let zoom_by: Rc<dyn Fn(Option<f64>)> = Rc::new(clone!(
+    #[weak]
+    webview,
+    #[strong]
+    zoom,
+    #[strong]
+    pages,
+    move |factor: Option<f64>| {
+      let pages = pages.borrow();
+      let Some((first, first_pixbuf)) = pages.first() else {
+        let level = factor.map_or(1.0, |factor| webview.zoom_level() * factor);
+        webview.set_zoom_level(level.clamp(ZOOM_MIN, ZOOM_MAX));
+        return;
+      };
+
+      let Some(factor) = factor else {
+        zoom.set(None);
+        for (picture, pixbuf) in pages.iter() {
+          picture.set_can_shrink(true);
+          picture.set_paintable(Some(&texture(pixbuf)));
+        }
+        return;
+      };
+      // The pages of a PDF are zoomed alike, from the scale of the first.
+      let current = zoom.get().unwrap_or_else(|| fit_scale(first, first_pixbuf));
+      let level = (current * factor).clamp(ZOOM_MIN, ZOOM_MAX);
+      let scaled: Option<Vec<gdk_pixbuf::Pixbuf>> = pages
+        .iter()
+        .map(|(_, pixbuf)| scale_pixbuf(pixbuf, level))
+        .collect();
+      if let Some(scaled) = scaled {
+        zoom.set(Some(level));
+        for ((picture, _), scaled) in pages.iter().zip(&scaled) {
+          picture.set_can_shrink(false);
+          picture.set_paintable(Some(&texture(scaled)));
+        }
+      }
+    }
+  ));
+
+  for (button, factor) in [
+    (zoom_fit, None),
+    (zoom_minus, Some(1.0 / ZOOM_STEP)),
+    (zoom_plus, Some(ZOOM_STEP)),
+  ] {
+    let zoom_by = zoom_by.clone();
+    button.connect_clicked(move |_| zoom_by(factor));
+  }
+
+  open_button.connect_clicked(clone!(
+    #[weak]
+    dialog,
+    move |_| {
+      dialog.close();
+      open_external();
+    }
+  ));
+
+  dialog.present(Some(parent));
+}
+
+/// The scale an image, or a page, is shown at when it fits the dialog.
+fn fit_scale(picture: &gtk4::Picture, pixbuf: &gdk_pixbuf::Pixbuf) -> f64 {
+  let width = picture.width() as f64 / pixbuf.width() as f64;
+  let height = picture.height() as f64 / pixbuf.height() as f64;
+  width.min(height).min(1.0)
+}
+
+fn initialise_preview_webview(webview: &WebView) {
+  webview.connect_decide_policy(|_, policy, decision_type| {
+    if decision_type != PolicyDecisionType::NavigationAction {
+      return false;
+    }
+    let uri = policy
+      .downcast_ref::<NavigationPolicyDecision>()
+      .and_then(|policy| policy.navigation_action())
+      .and_then(|action| action.request())
+      .and_then(|request| request.uri());
+    let allowed = uri
+      .as_deref()
+      .and_then(utils::uri_scheme)
+      .is_some_and(|scheme| PREVIEW_URI_SCHEMES.contains(&scheme.as_str()));
+    if allowed {
+      return false;
+    }
+
+    log::warn!("preview decide_policy(refused) => {:?}", uri);
+    policy.ignore();
+    true
+  });
+}
@@ -0,0 +1,144 @@
+/* reportcard.rs
+ *
+ * Copyright 2024 Alexandre Del Bigio
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+use adw::prelude::*;
+use gettextrs::gettext;
+
+use crate::message::attachment::Attachment;
+use crate::message::dsn::{Action, DeliveryReport};
+
+/// The status of the delivery to each recipient of a bounced message.
+/// `open_original` is called with the returned message when the user asks
+/// for it.
+pub fn report_card<F: Fn(&Attachment) + 'static>(
+  report: &DeliveryReport,
+  open_original: F,
+) -> gtk4::Widget {
+  let (icon_name, title) = if report.has_failed() {
+    ("dialog-error-symbolic", gettext("Delivery Failed"))
+  } else if report.is_delayed() {
+    ("dialog-warning-symbolic", gettext("Delivery Delayed"))
+  } else {
+    ("mail-send-symbolic", gettext("Delivery Report"))
+  };
+  let icon = gtk4::Image::from_icon_name(icon_name);
+  icon.set_pixel_size(24);
+
+  let title = gtk4::Label::new(Some(&title));
+  title.set_xalign(0.0);
+  title.add_css_class("title-4");
+  let titles = gtk4::Box::new(gtk4::Orientation::Vertical, 0);
+  titles.set_hexpand(true);
+  titles.append(&title);
+  if !report.reporting_mta.is_empty() {
+    let subtitle = gtk4::Label::new(Some(
+      &gettext("Reported by {server}").replace("{server}", &report.reporting_mta),
+    ));
+    subtitle.set_xalign(0.0);
+    subtitle.set_selectable(true);
+    subtitle.add_css_class("dim-label");
+    titles.append(&subtitle);
+  }
+
+  let header = gtk4::Box::new(gtk4::Orientation::Horizontal, 12);
+  header.append(&icon);
+  header.append(&titles);
+  if let Some(original) = &report.original {
+    let open = gtk4::Button::with_label(&gettext("Open Original Message"));
+    open.set_valign(gtk4::Align::Center);
+    open.set_tooltip_text(Some(&gettext("Show the message that was returned")));
+    let original = original.clone();
+    open.connect_clicked(move |_| open_original(&original));
+    header.append(&open);
+  }
+
+  let grid = gtk4::Grid::new();
+  grid.set_row_spacing(6);
+  grid.set_column_spacing(12);
+  let columns = [
+    gettext("Recipient"),
+    gettext("Result"),
+    gettext("Status"),
+    gettext("Diagnostic"),
+  ];
+  for (column, name) in columns.iter().enumerate() {
+    let name = gtk4::Label::new(Some(name.as_str()));
+    name.set_xalign(0.0);
+    name.add_css_class("dim-label");
+    grid.attach(&name, column as i32, 0, 1, 1);
+  }
+
+  for (index, recipient) in report.recipients.iter().enumerate() {
+    let row = index as i32 + 1;
+
+    let address = gtk4::Label::new(Some(&recipient.recipient));
+    address.set_xalign(0.0);
+    address.set_yalign(0.0);
+    address.set_selectable(true);
+    if !recipient.remote_mta.is_empty() {
+      address.set_tooltip_text(Some(
+        &gettext("Remote server: {server}").replace("{server}", &recipient.remote_mta),
+      ));
+    }
+    grid.attach(&address, 0, row, 1, 1);
+
+    if let Some(action) = recipient.action {
+      let badge = gtk4::Label::new(Some(&action.label()));
+      badge.set_halign(gtk4::Align::Start);
+      badge.set_valign(gtk4::Align::Start);
+      badge.add_css_class("risk-badge");
+      badge.add_css_class(match action {
+        Action::Failed => "error",
+        Action::Delayed => "warning",
+        Action::Delivered | Action::Relayed | Action::Expanded => "success",
+      });
+      grid.attach(&badge, 1, row, 1, 1);
+    }
+
+    let status = match recipient.status_label() {
+      Some(label) => format!("{} {}", recipient.status, label),
+      None => recipient.status.clone(),
+    };
+    let status = gtk4::Label::new(Some(&status));
+    status.set_xalign(0.0);
+    status.set_yalign(0.0);
+    status.set_wrap(true);
+    status.set_max_width_chars(30);
+    grid.attach(&status, 2, row, 1, 1);
+
+    let diagnostic = gtk4::Label::new(Some(&recipient.diagnostic));
+    diagnostic.set_xalign(0.0);
+    diagnostic.set_yalign(0.0);
+    diagnostic.set_hexpand(true);
+    diagnostic.set_wrap(true);
+    diagnostic.set_wrap_mode(gtk4::pango::WrapMode::WordChar);
+    diagnostic.set_selectable(true);
+    diagnostic.add_css_class("monospace");
+    grid.attach(&diagnostic, 3, row, 1, 1);
+  }
+
+  let card = gtk4::Box::new(gtk4::Orientation::Vertical, 12);
+  card.add_css_class("card");
+  card.add_css_class("event-card");
+  card.append(&header);
+  if !report.recipients.is_empty() {
+    card.append(&grid);
+  }
+  card.upcast()
+}
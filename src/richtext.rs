@@ -0,0 +1,279 @@
+/* richtext.rs
+ *
+ * Copyright 2024 Alexandre Del Bigio
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+
+/// How the line introducing a quote ends, "On <date>, <sender> wrote:".
+const ATTRIBUTION_ENDINGS: [&str; 5] = ["wrote:", "écrit :", "écrit:", "schrieb:", "escribió:"];
+
+/// Fewer quoted lines in a row are part of the reply, not its history.
+const MIN_FOLDED_LINES: usize = 4;
+
+/// An address written in a plain text body, and the uri it opens.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+  /// Offsets in characters, the unit a GtkTextBuffer counts in.
+  pub start: usize,
+  pub end: usize,
+  pub uri: String,
+}
+
+/// The web and mail addresses of `text`. An address is cut from the
+/// punctuation that ends the sentence around it, and from a closing
+/// parenthesis it does not open.
+pub fn find_links(text: &str) -> Vec<Link> {
+  let chars: Vec<char> = text.chars().collect();
+  let separator =
+    |c: &char| c.is_whitespace() || matches!(c, '<' | '>' | '"' | '\'' | '`' | '[' | ']' | '|');
+  let mut links = vec![];
+  let mut start = 0;
+
+  while start < chars.len() {
+    if separator(&chars[start]) {
+      start += 1;
+      continue;
+    }
+    let mut end = start + chars[start..].iter().take_while(|c| !separator(c)).count();
+    let token_end = end;
+
+    let mut first = start;
+    while first < end && !chars[first].is_alphanumeric() {
+      first += 1;
+    }
+    while end > first {
+      let last = chars[end - 1];
+      let unbalanced = last == ')'
+        && chars[first..end].iter().filter(|c| **c == '(').count()
+          < chars[first..end].iter().filter(|c| **c == ')').count();
+      if matches!(last, '.' | ',' | ';' | ':' | '!' | '?' | '*') || unbalanced {
+        end -= 1;
+      } else {
+        break;
+      }
+    }
+
+    let token: String = chars[first..end].iter().collect();
+    if let Some(uri) = uri(&token) {
+      links.push(Link {
+        start: first,
+        end,
+        uri,
+      });
+    }
+    start = token_end;
+  }
+  links
+}
+
+fn uri(token: &str) -> Option<String> {
+  let lower = token.to_ascii_lowercase();
+  for scheme in ["http://", "https://", "mailto:"] {
+    if lower.starts_with(scheme) {
+      return (token.len() > scheme.len()).then(|| token.to_string());
+    }
+  }
+  if lower.starts_with("www.") && lower[4..].contains('.') {
+    return Some(format!("http://{}", token));
+  }
+  is_email(token).then(|| format!("mailto:{}", token))
+}
+
+fn is_email(token: &str) -> bool {
+  let Some((local, domain)) = token.split_once('@') else {
+    return false;
+  };
+  let labels: Vec<&str> = domain.split('.').collect();
+  !local.is_empty()
+    && local
+      .chars()
+      .all(|c| c.is_alphanumeric() || "._%+-".contains(c))
+    && labels.len() > 1
+    && labels
+      .iter()
+      .all(|label| !label.is_empty() && label.chars().all(|c| c.is_alphanumeric() || c == '-'))
+}
+
+/// How deep `line` is in a quoted reply, the number of `>` it starts with,
+/// written `>>` or `> >`.
+pub fn quote_level(line: &str) -> usize {
+  line
+    .chars()
+    .filter(|c| *c != ' ')
+    .take_while(|c| *c == '>')
+    .count()
+}
+
+/// Whether `line` introduces a quote, "On <date>, <sender> wrote:".
+pub fn is_attribution(line: &str) -> bool {
+  let line = line.trim().to_lowercase();
+  ATTRIBUTION_ENDINGS
+    .iter()
+    .any(|ending| line.ends_with(ending))
+}
+
+/// Quoted lines folded behind a line that unfolds them, offsets in characters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fold {
+  /// The line to click, without its line break.
+  pub toggle: (usize, usize),
+  /// The quoted lines, with their line breaks.
+  pub hidden: (usize, usize),
+}
+
+/// Folds the quoted history of `text`: the runs of quoted lines with the line
+/// introducing them, and all that follows the "-----Original Message-----" of
+/// Outlook. Returns the text with a line made by `label`, from the number of
+/// lines folded, before each fold.
+pub fn fold_quotes<F: Fn(usize) -> String>(text: &str, label: F) -> (String, Vec<Fold>) {
+  let lines: Vec<&str> = text.split('\n').collect();
+  let quoted = |index: usize| lines.get(index).is_some_and(|line| quote_level(line) > 0);
+  let blank = |index: usize| lines.get(index).is_some_and(|line| line.trim().is_empty());
+
+  // The runs of lines to fold, as ranges of line indexes.
+  let mut runs: Vec<(usize, usize)> = vec![];
+  let mut index = 0;
+  while index < lines.len() {
+    let line = lines[index].trim();
+    if line.starts_with("-----") && line.to_lowercase().contains("original message") {
+      runs.push((index, lines.len()));
+      break;
+    }
+    if !quoted(index) {
+      index += 1;
+      continue;
+    }
+    let mut end = index;
+    while quoted(end) || (blank(end) && quoted(end + 1)) {
+      end += 1;
+    }
+    if (index..end).filter(|line| quoted(*line)).count() >= MIN_FOLDED_LINES {
+      let mut start = index;
+      let attribution = (1..=2)
+        .filter_map(|back| index.checked_sub(back))
+        .take_while(|line| *line + 1 == index || blank(*line + 1))
+        .find(|line| is_attribution(lines[*line]));
+      if let Some(line) = attribution {
+        start = line;
+      }
+      runs.push((start, end));
+    }
+    index = end;
+  }
+
+  let mut folded = String::with_capacity(text.len());
+  let mut folds: Vec<Fold> = vec![];
+  let mut offset = 0;
+  let mut folding_until = 0;
+  let mut runs = runs.into_iter().peekable();
+  for (index, line) in lines.iter().enumerate() {
+    if let Some((start, end)) = runs.next_if(|(start, _)| *start == index) {
+      let toggle = label(end - start);
+      let toggle_start = offset;
+      folded.push_str(&toggle);
+      folded.push('\n');
+      offset += toggle.chars().count() + 1;
+      folds.push(Fold {
+        toggle: (toggle_start, offset - 1),
+        hidden: (offset, offset),
+      });
+      folding_until = end;
+    }
+    folded.push_str(line);
+    offset += line.chars().count();
+    if index + 1 < lines.len() {
+      folded.push('\n');
+      offset += 1;
+    }
+    if index < folding_until {
+      if let Some(fold) = folds.last_mut() {
+        fold.hidden.1 = offset;
+      }
+    }
+  }
+  (folded, folds)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::richtext::*;
+
+  fn uris(text: &str) -> Vec<(usize, usize, String)> {
+    find_links(text)
+      .into_iter()
+      .map(|link| (link.start, link.end, link.uri))
+      .collect()
+  }
+
+  #[test]
+  fn links() {
+    assert_eq!(
+      uris("Voir https://moon.space/a?b=1. Or (www.moon.space), <john@moon.space>!"),
+      [
+        (5, 29, "https://moon.space/a?b=1".to_string()),
+        (35, 49, "http://www.moon.space".to_string()),
+        (53, 68, "mailto:john@moon.space".to_string()),
+      ]
+    );
+    assert_eq!(
+      uris("é https://en.wikipedia.org/wiki/Rust_(language) mailto:lucas@mercure.space"),
+      [
+        (
+          2,
+          47,
+          "https://en.wikipedia.org/wiki/Rust_(language)".to_string()
+        ),
+        (48, 74, "mailto:lucas@mercure.space".to_string()),
+      ]
+    );
+    assert_eq!(uris("http:// www.moon @moon.space john@ a@b"), []);
+  }
+
+  #[test]
+  fn quote_levels() {
+    assert_eq!(quote_level("Hello"), 0);
+    assert_eq!(quote_level("> Hello"), 1);
+    assert_eq!(quote_level(">> Hello"), 2);
+    assert_eq!(quote_level("> > > Hello > there"), 3);
+  }
+
+  #[test]
+  fn fold_quoted_history() {
+    let text = "Sure.\n> just one\nOK\n\nOn Wed, John wrote:\n> Coming?\n>\n> > Dinner\n\n\
+                > at 8\n\nSee you";
+    let (folded, folds) = fold_quotes(text, |count| format!("[{} lines]", count));
+    assert_eq!(
+      folded,
+      "Sure.\n> just one\nOK\n\n[6 lines]\nOn Wed, John wrote:\n> Coming?\n>\n> > Dinner\n\n\
+       > at 8\n\nSee you"
+    );
+    let chars: Vec<char> = folded.chars().collect();
+    let slice = |(start, end): (usize, usize)| chars[start..end].iter().collect::<String>();
+    assert_eq!(folds.len(), 1);
+    assert_eq!(slice(folds[0].toggle), "[6 lines]");
+    assert_eq!(
+      slice(folds[0].hidden),
+      "On Wed, John wrote:\n> Coming?\n>\n> > Dinner\n\n> at 8\n"
+    );
+
+    let (folded, folds) = fold_quotes("Hi\n-----Original Message-----\nFrom: John", |_| {
+      "…".to_string()
+    });
+    assert_eq!(folded, "Hi\n…\n-----Original Message-----\nFrom: John");
+    assert_eq!(folds[0].hidden, (5, 42));
+  }
+}
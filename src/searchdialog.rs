@@ -0,0 +1,172 @@
+/* searchdialog.rs
+ *
+ * Copyright 2024 Alexandre Del Bigio
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use adw::glib::clone;
+use adw::prelude::*;
+use gettextrs::{gettext, ngettext};
+use gtk4::{gio, glib};
+
+use crate::searchindex::{self, SearchIndex};
+
+/// More would only take long to show, the search is better narrowed down.
+const MAX_RESULTS: usize = 200;
+
+/// Searches the messages of `folder` and of its subfolders, once they are
+/// indexed. `open` is called with the message the user picks, and the words
+/// that were searched for.
+pub fn show<F: Fn(gio::File, Vec<String>) + 'static>(
+  parent: &impl IsA<gtk4::Widget>,
+  folder: &gio::File,
+  open: F,
+) {
+  let builder = gtk4::Builder::from_string(gtk4::include_blueprint!("src/search.blp"));
+  let dialog: adw::Dialog = builder.object("search").unwrap();
+  let search_entry: gtk4::SearchEntry = builder.object("search_entry").unwrap();
+  let stack: adw::ViewStack = builder.object("stack").unwrap();
+  let progress: gtk4::ProgressBar = builder.object("progress").unwrap();
+  let empty: adw::StatusPage = builder.object("empty").unwrap();
+  let results: gtk4::ListBox = builder.object("results").unwrap();
+  let error: adw::StatusPage = builder.object("error").unwrap();
+
+  if let Some(name) = folder.basename() {
+    dialog.set_title(&gettext("Search in {folder}").replace("{folder}", &name.to_string_lossy()));
+  }
+  stack.set_visible_child_name("indexing");
+
+  let index: Rc<RefCell<Option<SearchIndex>>> = Rc::new(RefCell::new(None));
+  let open = Rc::new(open);
+
+  search_entry.connect_search_changed(clone!(
+    #[weak]
+    dialog,
+    #[weak]
+    stack,
+    #[weak]
+    empty,
+    #[weak]
+    results,
+    #[strong]
+    index,
+    move |search_entry| {
+      let index = index.borrow();
+      let Some(index) = index.as_ref() else {
+        return;
+      };
+      results.remove_all();
+      let query = search_entry.text();
+      if searchindex::query_terms(&query).is_empty() {
+        show_indexed(&stack, &empty, index);
+        return;
+      }
+
+      let hits = index.search(&query);
+      if hits.is_empty() {
+        empty.set_title(&gettext("No Results"));
+        empty.set_description(None);
+        stack.set_visible_child_name("empty");
+        return;
+      }
+      for hit in hits.iter().take(MAX_RESULTS) {
+        let row = adw::ActionRow::new();
+        row.set_use_markup(false);
+        let path = hit.path.to_string_lossy();
+        let title: &str = if hit.subject.is_empty() {
+          &path
+        } else {
+          &hit.subject
+        };
+        row.set_title(title);
+        row.set_subtitle(&format!("{} — {}", hit.from, hit.date));
+        row.set_tooltip_text(Some(&path));
+        row.set_activatable(true);
+        let file = gio::File::for_path(&hit.path);
+        row.connect_activated(clone!(
+          #[weak]
+          dialog,
+          #[strong]
+          open,
+          #[strong]
+          query,
+          move |_| {
+            dialog.close();
+            open(file.clone(), searchindex::query_terms(&query));
+          }
+        ));
+        results.append(&row);
+      }
+      stack.set_visible_child_name("results");
+    }
+  ));
+
+  glib::spawn_future_local(clone!(
+    #[weak]
+    stack,
+    #[weak]
+    progress,
+    #[weak]
+    empty,
+    #[weak]
+    error,
+    #[weak]
+    search_entry,
+    #[strong]
+    folder,
+    #[strong]
+    index,
+    async move {
+      let built = SearchIndex::build(&folder, |count, total| {
+        progress.set_fraction(count as f64 / total.max(1) as f64);
+        progress.set_text(Some(
+          &gettext("{count} of {total}")
+            .replace("{count}", &count.to_string())
+            .replace("{total}", &total.to_string()),
+        ));
+      })
+      .await;
+      match built {
+        Ok(built) => {
+          show_indexed(&stack, &empty, &built);
+          index.replace(Some(built));
+          search_entry.set_sensitive(true);
+          search_entry.grab_focus();
+        }
+        Err(e) => {
+          log::error!("SearchIndex::build({})", e);
+          error.set_description(Some(&e.to_string()));
+          stack.set_visible_child_name("error");
+        }
+      }
+    }
+  ));
+
+  dialog.present(Some(parent));
+}
+
+fn show_indexed(stack: &adw::ViewStack, empty: &adw::StatusPage, index: &SearchIndex) {
+  let count = index.message_count() as u32;
+  empty.set_title(&gettext("Search Messages"));
+  empty.set_description(Some(
+    &ngettext("{count} message indexed", "{count} messages indexed", count)
+      .replace("{count}", &count.to_string()),
+  ));
+  stack.set_visible_child_name("empty");
+}
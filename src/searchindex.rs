@@ -0,0 +1,456 @@
+/* searchindex.rs
+ *
+ * Copyright 2024 Alexandre Del Bigio
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! A full-text index of the messages of a folder: the words of their headers,
+//! bodies and attachment names. It is kept in the user cache directory, and
+//! only the messages that changed since are read again.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::config::APP_NAME;
+use crate::mailfolder::MailFolder;
+use crate::message::message::{Message, MessageParser};
+use crate::{gio, glib};
+
+/// The first line of an index file, to start over when the format changes.
+const INDEX_HEADER: &str = "mailviewer-index 2";
+
+const MIN_WORD_LENGTH: usize = 2;
+/// Longer ones are rather encoded data or identifiers than words.
+const MAX_WORD_LENGTH: usize = 40;
+
+/// How many messages a background thread indexes at once.
+const MESSAGES_PER_THREAD: usize = 16;
+
+/// A message as it was when indexed, and the words it contains.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IndexedMessage {
+  pub path: PathBuf,
+  /// The modification time, in seconds since the epoch.
+  modified: i64,
+  size: u64,
+  pub date: String,
+  pub from: String,
+  pub subject: String,
+  /// Sorted, without duplicates.
+  words: Vec<String>,
+}
+
+impl IndexedMessage {
+  fn new(path: &Path, modified: i64, size: u64, message: &dyn Message) -> Self {
+    let mut text: Vec<String> = message
+      .headers()
+      .into_iter()
+      .map(|(_, value)| value)
+      .collect();
+    text.extend(
+      message
+        .attachments()
+        .into_iter()
+        .map(|attachment| attachment.filename),
+    );
+    // A message with only an html part has its text rendered from it.
+    text.extend(message.body_text());
+    let mut words: Vec<String> = text.iter().flat_map(|text| words(text)).collect();
+    words.sort();
+    words.dedup();
+
+    Self {
+      path: path.to_path_buf(),
+      modified,
+      size,
+      date: message.date(),
+      from: message.from(),
+      subject: message.subject(),
+      words,
+    }
+  }
+
+  /// One line: the fields, tab separated, then the words, space separated.
+  fn to_line(&self) -> Option<String> {
+    let path = self
+      .path
+      .to_str()
+      .filter(|path| !path.contains(['\t', '\n']))?;
+    let field = |value: &str| value.replace(['\t', '\r', '\n'], " ");
+    Some(format!(
+      "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+      path,
+      self.modified,
+      self.size,
+      field(&self.date),
+      field(&self.from),
+      field(&self.subject),
+      self.words.join(" ")
+    ))
+  }
+
+  fn from_line(line: &str) -> Option<Self> {
+    let mut fields = line.split('\t');
+    Some(Self {
+      path: PathBuf::from(fields.next()?),
+      modified: fields.next()?.parse().ok()?,
+      size: fields.next()?.parse().ok()?,
+      date: fields.next()?.to_string(),
+      from: fields.next()?.to_string(),
+      subject: fields.next()?.to_string(),
+      words: fields
+        .next()?
+        .split(' ')
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+        .collect(),
+    })
+  }
+}
+
+#[derive(Debug, Default)]
+pub struct SearchIndex {
+  /// Where the index is stored.
+  location: PathBuf,
+  /// Sorted by path.
+  messages: Vec<IndexedMessage>,
+  /// The messages each word is in, by their position in `messages`.
+  postings: BTreeMap<String, Vec<usize>>,
+}
+
+impl SearchIndex {
+  /// Where the index of `folder` is stored, a file named after the hash of its
+  /// path so that folders don't share one.
+  fn location(folder: &Path) -> PathBuf {
+    let hash = glib::compute_checksum_for_string(
+      glib::ChecksumType::Sha256,
+      folder.to_string_lossy().as_ref(),
+    )
+    .unwrap_or_default();
+    let mut path = glib::user_cache_dir();
+    path.push(APP_NAME);
+    path.push("index");
+    path.push(format!("{}.index", hash));
+    path
+  }
+
+  /// Reads the index stored at `location`. A missing or unreadable one is
+  /// empty, and built again.
+  fn load(location: &Path) -> Self {
+    let mut index = Self {
+      location: location.to_path_buf(),
+      ..Default::default()
+    };
+    let content = match fs::read_to_string(location) {
+      Ok(content) => content,
+      Err(e) => {
+        log::debug!("SearchIndex::load({:?}): {}", location, e);
+        return index;
+      }
+    };
+    let mut lines = content.lines();
+    if lines.next() != Some(INDEX_HEADER) {
+      return index;
+    }
+    index.messages = lines.filter_map(IndexedMessage::from_line).collect();
+    index.messages.sort_by(|a, b| a.path.cmp(&b.path));
+    index.messages.dedup_by(|a, b| a.path == b.path);
+    index.build_postings();
+    index
+  }
+
+  fn save(&self) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = self.location.parent() {
+      fs::create_dir_all(parent)?;
+    }
+    let mut content = String::from(INDEX_HEADER);
+    for line in self.messages.iter().filter_map(IndexedMessage::to_line) {
+      content.push('\n');
+      content.push_str(&line);
+    }
+    content.push('\n');
+    // Written aside then renamed, so that a crash never leaves half an index.
+    let temporary = self.location.with_extension("tmp");
+    fs::write(&temporary, content)?;
+    fs::rename(&temporary, &self.location)?;
+    Ok(())
+  }
+
+  fn build_postings(&mut self) {
+    self.postings.clear();
+    for (position, message) in self.messages.iter().enumerate() {
+      for word in &message.words {
+        self
+          .postings
+          .entry(word.clone())
+          .or_default()
+          .push(position);
+      }
+    }
+  }
+
+  /// The modification time and the size of a file when it was indexed.
+  fn stamp(&self, path: &Path) -> Option<(i64, u64)> {
+    let position = self
+      .messages
+      .binary_search_by(|message| message.path.as_path().cmp(path))
+      .ok()?;
+    let message = &self.messages[position];
+    Some((message.modified, message.size))
+  }
+
+  /// Reads a message for the index, unless it is as it was when indexed
+  /// (`stamp`). It blocks.
+  fn index_file(
+    path: &Path,
+    stamp: Option<(i64, u64)>,
+  ) -> Result<Option<IndexedMessage>, Box<dyn Error>> {
+    let metadata = fs::metadata(path)?;
+    let modified = metadata
+      .modified()?
+      .duration_since(UNIX_EPOCH)
+      .map(|duration| duration.as_secs() as i64)
+      .unwrap_or_default();
+    if stamp == Some((modified, metadata.len())) {
+      return Ok(None);
+    }
+    let mut parser = MessageParser::from_content(Some(path), fs::read(path)?);
+    parser.parse(None)?;
+    Ok(Some(IndexedMessage::new(
+      path,
+      modified,
+      metadata.len(),
+      &parser,
+    )))
+  }
+
+  /// Replaces the messages that were indexed again, and forgets the ones that
+  /// are not in the folder anymore.
+  fn update(&mut self, indexed: Vec<IndexedMessage>, paths: &[PathBuf]) {
+    let paths: HashSet<&PathBuf> = paths.iter().collect();
+    let indexed_paths: HashSet<PathBuf> =
+      indexed.iter().map(|message| message.path.clone()).collect();
+    self
+      .messages
+      .retain(|message| paths.contains(&message.path) && !indexed_paths.contains(&message.path));
+    self.messages.extend(indexed);
+    self.messages.sort_by(|a, b| a.path.cmp(&b.path));
+    self.build_postings();
+  }
+
+  /// Brings the index of `folder` and of its subfolders up to date, reading
+  /// the messages that changed in the background. `progress` is called with
+  /// how many messages were looked at, and how many there are.
+  pub async fn build<F: Fn(usize, usize)>(
+    folder: &gio::File,
+    progress: F,
+  ) -> Result<Self, Box<dyn Error>> {
+    let root = folder.path().ok_or("The folder is not a local one")?;
+    let location = Self::location(&root);
+    let mut index = gio::spawn_blocking(move || Self::load(&location))
+      .await
+      .map_err(|_| "Failed to read the index")?;
+
+    let paths: Vec<PathBuf> = MailFolder::scan(folder, true)
+      .await?
+      .iter()
+      .filter_map(|file| file.path())
+      .collect();
+    let mut indexed: Vec<IndexedMessage> = vec![];
+    for (count, chunk) in paths.chunks(MESSAGES_PER_THREAD).enumerate() {
+      let files: Vec<(PathBuf, Option<(i64, u64)>)> = chunk
+        .iter()
+        .map(|path| (path.clone(), index.stamp(path)))
+        .collect();
+      let messages = gio::spawn_blocking(move || {
+        files
+          .iter()
+          .filter_map(|(path, stamp)| match Self::index_file(path, *stamp) {
+            Ok(message) => message,
+            Err(e) => {
+              log::debug!("SearchIndex::index_file({:?}): {}", path, e);
+              None
+            }
+          })
+          .collect::<Vec<IndexedMessage>>()
+      })
+      .await
+      .map_err(|_| "Failed to index the messages")?;
+      indexed.extend(messages);
+      progress(
+        (count * MESSAGES_PER_THREAD + chunk.len()).min(paths.len()),
+        paths.len(),
+      );
+    }
+
+    index.update(indexed, &paths);
+    gio::spawn_blocking(move || {
+      if let Err(e) = index.save() {
+        log::error!("SearchIndex::save({:?}): {}", index.location, e);
+      }
+      index
+    })
+    .await
+    .map_err(|_| "Failed to save the index".into())
+  }
+
+  pub fn message_count(&self) -> usize {
+    self.messages.len()
+  }
+
+  /// The messages with all the words of `query`, or words starting with them,
+  /// the latest first.
+  pub fn search(&self, query: &str) -> Vec<&IndexedMessage> {
+    let mut hits: Option<BTreeSet<usize>> = None;
+    for term in query_terms(query) {
+      let matching: BTreeSet<usize> = self
+        .postings
+        .range(term.clone()..)
+        .take_while(|(word, _)| word.starts_with(&term))
+        .flat_map(|(_, positions)| positions.iter().copied())
+        .collect();
+      hits = Some(match hits {
+        Some(hits) => hits.intersection(&matching).copied().collect(),
+        None => matching,
+      });
+    }
+    let mut messages: Vec<&IndexedMessage> = hits
+      .unwrap_or_default()
+      .into_iter()
+      .map(|position| &self.messages[position])
+      .collect();
+    messages.sort_by(|a, b| b.date.cmp(&a.date));
+    messages
+  }
+}
+
+/// The words of a text, in lower case.
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+  text
+    .split(|c: char| !c.is_alphanumeric())
+    .filter(|word| word.chars().count() >= MIN_WORD_LENGTH && word.len() <= MAX_WORD_LENGTH)
+    .map(str::to_lowercase)
+}
+
+/// The words searched for, without duplicates.
+pub fn query_terms(query: &str) -> Vec<String> {
+  let mut terms: Vec<String> = vec![];
+  for term in words(query) {
+    if !terms.contains(&term) {
+      terms.push(term);
+    }
+  }
+  terms
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::htmltext;
+  use crate::searchindex::*;
+
+  fn message(path: &str, date: &str, text: &str) -> IndexedMessage {
+    let mut words: Vec<String> = words(text).collect();
+    words.sort();
+    words.dedup();
+    IndexedMessage {
+      path: PathBuf::from(path),
+      date: date.to_string(),
+      words,
+      ..Default::default()
+    }
+  }
+
+  fn paths(index: &SearchIndex, query: &str) -> Vec<String> {
+    index
+      .search(query)
+      .iter()
+      .map(|message| message.path.to_string_lossy().to_string())
+      .collect()
+  }
+
+  #[test]
+  fn search_words() {
+    let mut index = SearchIndex::default();
+    index.update(
+      vec![
+        message("/mails/a.eml", "2024-03-01", "Invoice for March, John"),
+        message("/mails/b.eml", "2024-04-01", "Re: invoice of April"),
+        message("/mails/c.eml", "2024-03-02", "Holidays in March"),
+      ],
+      &[
+        PathBuf::from("/mails/a.eml"),
+        PathBuf::from("/mails/b.eml"),
+        PathBuf::from("/mails/c.eml"),
+      ],
+    );
+    assert_eq!(paths(&index, "invoice"), ["/mails/b.eml", "/mails/a.eml"]);
+    assert_eq!(paths(&index, "MARCH invo"), ["/mails/a.eml"]);
+    assert_eq!(paths(&index, "march"), ["/mails/c.eml", "/mails/a.eml"]);
+    assert!(paths(&index, "june").is_empty());
+    assert!(paths(&index, "").is_empty());
+
+    // b.eml was indexed again, c.eml is gone.
+    index.update(vec![message("/mails/b.eml", "2024-04-01", "Payment")], &[
+      PathBuf::from("/mails/a.eml"),
+      PathBuf::from("/mails/b.eml"),
+    ]);
+    assert_eq!(index.message_count(), 2);
+    assert_eq!(paths(&index, "invoice"), ["/mails/a.eml"]);
+    assert_eq!(paths(&index, "payment"), ["/mails/b.eml"]);
+  }
+
+  #[test]
+  fn save_and_load() {
+    let location = std::env::temp_dir().join(format!(
+      "mailviewer-index-test-{}.index",
+      std::process::id()
+    ));
+    let mut index = SearchIndex {
+      location: location.clone(),
+      ..Default::default()
+    };
+    let mut invoice = message("/mails/a.eml", "2024-03-01", "Invoice");
+    invoice.subject = "Invoice\tfor March".to_string();
+    invoice.modified = 1709251200;
+    invoice.size = 1234;
+    index.update(vec![invoice], &[PathBuf::from("/mails/a.eml")]);
+    index.save().unwrap();
+
+    let loaded = SearchIndex::load(&location);
+    fs::remove_file(&location).unwrap();
+    assert_eq!(loaded.message_count(), 1);
+    assert_eq!(loaded.messages[0].subject, "Invoice for March");
+    assert_eq!(
+      loaded.stamp(Path::new("/mails/a.eml")),
+      Some((1709251200, 1234))
+    );
+    assert_eq!(loaded.search("invoice").len(), 1);
+    assert_eq!(SearchIndex::load(&location).message_count(), 0);
+  }
+
+  #[test]
+  fn html_words() {
+    let text = htmltext::to_text(
+      "<html><style>p { color: red }</style><p class=\"x\">Caf&eacute; &amp; \
+       <b>bar</b></p><script>var hidden;</script></html>",
+    );
+    let words: Vec<String> = words(&text).collect();
+    assert_eq!(words, ["café", "bar"]);
+  }
+}
@@ -0,0 +1,96 @@
+/* textsearch.rs
+ *
+ * Copyright 2024 Alexandre Del Bigio
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+
+/// How the search bar matches text.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SearchOptions {
+  pub case_sensitive: bool,
+  /// The match may not start nor end in the middle of a word.
+  pub whole_word: bool,
+}
+
+/// Where `query` is in `text`, as start and end offsets in characters, the
+/// unit a GtkTextBuffer counts in. Matches don't overlap.
+pub fn find_all(text: &str, query: &str, options: SearchOptions) -> Vec<(usize, usize)> {
+  // One character for one, for the offsets to stay those of `text`.
+  let fold = |c: char| {
+    if options.case_sensitive {
+      c
+    } else {
+      c.to_lowercase().next().unwrap_or(c)
+    }
+  };
+  let text: Vec<char> = text.chars().map(fold).collect();
+  let query: Vec<char> = query.chars().map(fold).collect();
+  if query.is_empty() || query.len() > text.len() {
+    return vec![];
+  }
+
+  let is_word = |index: usize| {
+    text
+      .get(index)
+      .is_some_and(|c| c.is_alphanumeric() || *c == '_')
+  };
+  let mut matches = vec![];
+  let mut start = 0;
+  while start + query.len() <= text.len() {
+    let end = start + query.len();
+    let at_word_bounds = || (start == 0 || !is_word(start - 1)) && !is_word(end);
+    let found = text[start..end] == query[..] && (!options.whole_word || at_word_bounds());
+    if found {
+      matches.push((start, end));
+      start = end;
+    } else {
+      start += 1;
+    }
+  }
+  matches
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::textsearch::*;
+
+  #[test]
+  fn find_matches() {
+    let text = "Été: the cat, the Catalog and THE_CAT. Ça été";
+    let options = SearchOptions::default();
+    assert_eq!(find_all(text, "cat", options), [
+      (9, 12),
+      (18, 21),
+      (34, 37)
+    ]);
+    assert_eq!(find_all(text, "été", options), [(0, 3), (42, 45)]);
+    assert_eq!(find_all(text, "", options), []);
+
+    let case_sensitive = SearchOptions {
+      case_sensitive: true,
+      ..Default::default()
+    };
+    assert_eq!(find_all(text, "Cat", case_sensitive), [(18, 21)]);
+
+    let whole_word = SearchOptions {
+      whole_word: true,
+      ..Default::default()
+    };
+    assert_eq!(find_all(text, "cat", whole_word), [(9, 12)]);
+    assert_eq!(find_all("aaaa", "aa", options), [(0, 2), (2, 4)]);
+  }
+}
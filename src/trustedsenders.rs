@@ -0,0 +1,146 @@
+/* trustedsenders.rs
+ *
+ * Copyright 2024 Alexandre Del Bigio
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+
+/// The senders whose messages are rendered with remote content without asking.
+/// An entry is either an address, or a domain which then covers its subdomains
+/// too. The From header is whatever the sender wrote, so this is a convenience,
+/// not a proof of anything.
+pub struct TrustedSenders {
+  entries: Vec<String>,
+}
+
+impl TrustedSenders {
+  pub fn new(entries: &[String]) -> Self {
+    Self {
+      entries: entries
+        .iter()
+        .filter_map(|entry| Self::normalize(entry))
+        .collect(),
+    }
+  }
+
+  /// An entry as it is stored: trimmed, in lower case and without the `@` a
+  /// domain may have been typed with. None when nothing is left.
+  pub fn normalize(entry: &str) -> Option<String> {
+    let entry = entry.trim().trim_start_matches('@').to_lowercase();
+    if entry.is_empty() || entry.contains(char::is_whitespace) {
+      return None;
+    }
+    Some(entry)
+  }
+
+  /// The address of the first mailbox of a From header, in lower case.
+  pub fn sender_address(from: &str) -> Option<String> {
+    let first = from.split(',').next()?;
+    let address = match (first.rfind('<'), first.rfind('>')) {
+      (Some(start), Some(end)) if start < end => &first[start + 1..end],
+      _ => first,
+    };
+    let address = address.trim().to_lowercase();
+    let (local, domain) = address.split_once('@')?;
+    if local.is_empty() || domain.is_empty() || address.contains(char::is_whitespace) {
+      return None;
+    }
+    Some(address)
+  }
+
+  pub fn contains(&self, address: &str) -> bool {
+    let address = address.to_lowercase();
+    let Some((_, domain)) = address.split_once('@') else {
+      return false;
+    };
+
+    self.entries.iter().any(|entry| {
+      if entry.contains('@') {
+        *entry == address
+      } else {
+        domain == entry || domain.ends_with(&format!(".{entry}"))
+      }
+    })
+  }
+
+  /// Whether the sender of a message with this From header is trusted.
+  pub fn trusts(&self, from: &str) -> bool {
+    Self::sender_address(from).is_some_and(|address| self.contains(&address))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::trustedsenders::TrustedSenders;
+
+  fn trusted(entries: &[&str]) -> TrustedSenders {
+    TrustedSenders::new(
+      &entries
+        .iter()
+        .map(|entry| entry.to_string())
+        .collect::<Vec<_>>(),
+    )
+  }
+
+  #[test]
+  fn sender_addresses() {
+    assert_eq!(
+      TrustedSenders::sender_address("John Doe <John@Moon.space>").as_deref(),
+      Some("john@moon.space")
+    );
+    assert_eq!(
+      TrustedSenders::sender_address("john@moon.space").as_deref(),
+      Some("john@moon.space")
+    );
+    assert_eq!(
+      TrustedSenders::sender_address("a <a@x.org>, b <b@y.org>").as_deref(),
+      Some("a@x.org")
+    );
+    assert_eq!(TrustedSenders::sender_address(""), None);
+    assert_eq!(TrustedSenders::sender_address("John Doe"), None);
+    assert_eq!(TrustedSenders::sender_address("<@moon.space>"), None);
+  }
+
+  #[test]
+  fn entries_are_normalized() {
+    assert_eq!(
+      TrustedSenders::normalize(" @Moon.Space ").as_deref(),
+      Some("moon.space")
+    );
+    assert_eq!(TrustedSenders::normalize("   "), None);
+    assert_eq!(TrustedSenders::normalize("john doe"), None);
+  }
+
+  #[test]
+  fn an_address_only_matches_itself() {
+    let trusted = trusted(&["john@moon.space"]);
+
+    assert!(trusted.trusts("John Doe <JOHN@moon.space>"));
+    assert!(!trusted.trusts("Jane <jane@moon.space>"));
+    assert!(!trusted.trusts("John <john@moon.space.evil.example>"));
+  }
+
+  #[test]
+  fn a_domain_covers_its_subdomains() {
+    let trusted = trusted(&["@moon.space"]);
+
+    assert!(trusted.trusts("john@moon.space"));
+    assert!(trusted.trusts("news@mail.moon.space"));
+    assert!(!trusted.trusts("john@fullmoon.space"));
+    assert!(!trusted.trusts("john@moon.space.evil.example"));
+    assert!(!trusted.trusts("not an address"));
+  }
+}
@@ -0,0 +1,133 @@
+// Decoding of image attachments.
+use std::error::Error;
+
+use gtk4::prelude::*;
+use gtk4::{gdk, gdk_pixbuf, glib};
+
+/// The largest side an image is scaled to, past it a zoomed copy takes more
+/// memory than it is worth.
+pub const MAX_IMAGE_SIZE: i32 = 8192;
+
+/// Decodes an image and turns it upright, as its EXIF orientation tells.
+pub fn load_pixbuf(body: &[u8]) -> Result<gdk_pixbuf::Pixbuf, Box<dyn Error>> {
+  let loader = gdk_pixbuf::PixbufLoader::new();
+  let written = loader.write(body);
+  // The loader has to be closed even when writing failed.
+  let closed = loader.close();
+  written?;
+  closed?;
+
+  let pixbuf = loader.pixbuf().ok_or("No image found")?;
+  Ok(pixbuf.apply_embedded_orientation().unwrap_or(pixbuf))
+}
+
+/// A copy of `pixbuf` scaled by `zoom`, None when it would be too large.
+pub fn scale_pixbuf(pixbuf: &gdk_pixbuf::Pixbuf, zoom: f64) -> Option<gdk_pixbuf::Pixbuf> {
+  let width = (pixbuf.width() as f64 * zoom).round() as i32;
+  let height = (pixbuf.height() as f64 * zoom).round() as i32;
+  if width < 1 || height < 1 || width > MAX_IMAGE_SIZE || height > MAX_IMAGE_SIZE {
+    return None;
+  }
+  pixbuf.scale_simple(width, height, gdk_pixbuf::InterpType::Bilinear)
+}
+
+/// The pixels of a decoded image. Unlike a Pixbuf they can be sent across
+/// threads, so an image can be decoded away from the main loop.
+pub struct Pixels {
+  width: i32,
+  height: i32,
+  rowstride: usize,
+  has_alpha: bool,
+  bytes: glib::Bytes,
+}
+
+impl Pixels {
+  pub fn new(pixbuf: &gdk_pixbuf::Pixbuf) -> Self {
+    Self {
+      width: pixbuf.width(),
+      height: pixbuf.height(),
+      rowstride: pixbuf.rowstride() as usize,
+      has_alpha: pixbuf.has_alpha(),
+      bytes: pixbuf.read_pixel_bytes(),
+    }
+  }
+
+  pub fn texture(&self) -> gdk::Texture {
+    // gdk-pixbuf only knows 8 bit RGB, with or without alpha.
+    let format = if self.has_alpha {
+      gdk::MemoryFormat::R8g8b8a8
+    } else {
+      gdk::MemoryFormat::R8g8b8
+    };
+    gdk::MemoryTexture::new(self.width, self.height, format, &self.bytes, self.rowstride).upcast()
+  }
+
+  /// The image back on the main thread, to be scaled.
+  pub fn pixbuf(&self) -> gdk_pixbuf::Pixbuf {
+    gdk_pixbuf::Pixbuf::from_bytes(
+      &self.bytes,
+      gdk_pixbuf::Colorspace::Rgb,
+      self.has_alpha,
+      8,
+      self.width,
+      self.height,
+      self.rowstride as i32,
+    )
+  }
+}
+
+pub fn texture(pixbuf: &gdk_pixbuf::Pixbuf) -> gdk::Texture {
+  Pixels::new(pixbuf).texture()
+}
+
+/// Decodes an image into a thumbnail which fits a `size` pixels square. Meant
+/// to be run with gio::spawn_blocking().
+pub fn thumbnail(body: &[u8], size: i32) -> Result<Pixels, String> {
+  let pixbuf = load_pixbuf(body).map_err(|e| e.to_string())?;
+  let zoom = (size as f64 / pixbuf.width().max(pixbuf.height()) as f64).min(1.0);
+  let pixbuf = scale_pixbuf(&pixbuf, zoom).ok_or("Image too small")?;
+  Ok(Pixels::new(&pixbuf))
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::utils::image::*;
+
+  #[test]
+  fn load_and_scale() {
+    let pixbuf = load_pixbuf(&std::fs::read("images/mailviewer.png").unwrap()).unwrap();
+    let scaled = scale_pixbuf(&pixbuf, 0.5).unwrap();
+
+    assert_eq!(scaled.width(), (pixbuf.width() as f64 * 0.5).round() as i32);
+    assert_eq!(
+      scaled.height(),
+      (pixbuf.height() as f64 * 0.5).round() as i32
+    );
+    assert!(scale_pixbuf(&pixbuf, MAX_IMAGE_SIZE as f64).is_none());
+  }
+
+  #[test]
+  fn thumbnails_fit_their_square() {
+    let thumbnail = thumbnail(&std::fs::read("images/mailviewer.png").unwrap(), 64).unwrap();
+
+    assert!(thumbnail.width <= 64 && thumbnail.height <= 64);
+    assert_eq!(thumbnail.width.max(thumbnail.height), 64);
+  }
+
+  #[test]
+  fn pixels_back_to_pixbuf() {
+    let pixbuf = load_pixbuf(&std::fs::read("images/mailviewer.png").unwrap()).unwrap();
+    let copy = Pixels::new(&pixbuf).pixbuf();
+
+    assert_eq!(
+      (copy.width(), copy.height()),
+      (pixbuf.width(), pixbuf.height())
+    );
+    assert_eq!(copy.read_pixel_bytes(), pixbuf.read_pixel_bytes());
+  }
+
+  #[test]
+  fn load_garbage() {
+    assert!(load_pixbuf(b"MZ\x90\x00 not an image").is_err());
+  }
+}
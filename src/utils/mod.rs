@@ -1,4 +1,7 @@
 // Common utilities.
+pub mod image;
+pub mod pdf;
+
 use crate::glib;
 
 /// Returns the lowercased scheme of `uri`, or None if it has no valid one.
@@ -21,6 +24,107 @@ pub fn uri_scheme(uri: &str) -> Option<String> {
   Some(scheme.to_ascii_lowercase())
 }
 
+/// Returns the lowercased host `value` points at, without its `www.` prefix
+/// and in its ASCII form, so that an international name compares with the
+/// punycode WebKit rewrites it to. `value` is either a link or the text of a
+/// link, so a bare `example.com` or `john@moon.space` counts too, as long as
+/// it looks like a domain.
+pub fn link_host(value: &str) -> Option<String> {
+  let value = value.trim();
+  let (rest, bare) = match uri_scheme(value) {
+    Some(scheme) => {
+      let rest = &value[scheme.len() + 1..];
+      (rest.strip_prefix("//").unwrap_or(rest), false)
+    }
+    None => (value, true),
+  };
+  if bare && rest.contains(char::is_whitespace) {
+    return None;
+  }
+
+  let authority = rest.split(['/', '?', '#']).next()?;
+  let host = authority.rsplit('@').next()?;
+  let host = host.split(':').next()?.trim_end_matches('.');
+  let host = host.to_lowercase();
+  let host = host.strip_prefix("www.").unwrap_or(&host);
+
+  if host.is_empty() {
+    return None;
+  }
+  if bare {
+    // Plain text is only taken for a domain when it ends like one.
+    let (_, tld) = host.rsplit_once('.')?;
+    if tld.len() < 2 || !tld.chars().all(|c| c.is_alphabetic()) {
+      return None;
+    }
+  }
+  Some(ascii_host(host))
+}
+
+/// The ASCII (punycode) form of a lower case host name.
+fn ascii_host(host: &str) -> String {
+  glib::hostname_to_ascii(host).map_or(host.to_string(), |ascii| ascii.to_lowercase())
+}
+
+/// `uri` the way WebKit hands it over when the link is followed: the scheme
+/// and host lower case, the host in its ASCII form, an empty http path as "/"
+/// and what a URI can't hold percent encoded.
+pub fn normalize_link(uri: &str) -> String {
+  let encode = |text: &str| -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for c in text.chars() {
+      if c.is_ascii_graphic() {
+        encoded.push(c);
+      } else {
+        let mut bytes = [0; 4];
+        for byte in c.encode_utf8(&mut bytes).bytes() {
+          encoded.push_str(&format!("%{byte:02X}"));
+        }
+      }
+    }
+    encoded
+  };
+  let uri = uri.trim();
+  let Some(scheme) = uri_scheme(uri) else {
+    return encode(uri);
+  };
+  let rest = &uri[scheme.len() + 1..];
+  let Some(rest) = rest.strip_prefix("//") else {
+    return format!("{scheme}:{}", encode(rest));
+  };
+
+  let (authority, path) = rest.split_at(rest.find(['/', '?', '#']).unwrap_or(rest.len()));
+  let (user, host) = match authority.rsplit_once('@') {
+    Some((user, host)) => (format!("{user}@"), host),
+    None => (String::new(), authority),
+  };
+  let (host, port) = match host.rsplit_once(':') {
+    Some((name, port)) if port.bytes().all(|byte| byte.is_ascii_digit()) => {
+      (name, &host[name.len()..])
+    }
+    _ => (host, ""),
+  };
+  let path = match path {
+    "" if scheme == "http" || scheme == "https" => "/",
+    path => path,
+  };
+  format!(
+    "{scheme}://{}{}{port}{}",
+    encode(&user),
+    ascii_host(&host.to_lowercase()),
+    encode(path)
+  )
+}
+
+/// Whether the text of a link looks like an address that is not the one the
+/// link actually leads to, the classic way of dressing up a phishing link.
+pub fn deceptive_link(text: &str, uri: &str) -> bool {
+  let Some(shown) = link_host(text) else {
+    return false;
+  };
+  link_host(uri).is_none_or(|target| target != shown)
+}
+
 pub fn spawn_and_wait<R: 'static, F: std::future::Future<Output = R> + 'static>(
   ctx: Option<&glib::MainContext>,
   f: F,
@@ -88,6 +192,76 @@ mod tests {
     assert_eq!(uri_scheme("1http://example.com"), None);
   }
 
+  #[test]
+  fn link_hosts() {
+    assert_eq!(
+      link_host("https://www.Example.com/path?q=1").as_deref(),
+      Some("example.com")
+    );
+    assert_eq!(
+      link_host("http://user@example.com:8080").as_deref(),
+      Some("example.com")
+    );
+    assert_eq!(
+      link_host("mailto:john@moon.space?subject=hi").as_deref(),
+      Some("moon.space")
+    );
+    assert_eq!(link_host("www.example.com").as_deref(), Some("example.com"));
+    assert_eq!(
+      link_host(" paypal.com/login ").as_deref(),
+      Some("paypal.com")
+    );
+    assert_eq!(link_host("john@moon.space").as_deref(), Some("moon.space"));
+    assert_eq!(link_host("Click here"), None);
+    assert_eq!(link_host("version 1.2"), None);
+    assert_eq!(link_host("1.2"), None);
+    assert_eq!(link_host("https://"), None);
+    assert_eq!(
+      link_host("https://Bücher.de/").as_deref(),
+      Some("xn--bcher-kva.de")
+    );
+  }
+
+  #[test]
+  fn normalized_links() {
+    assert_eq!(
+      normalize_link("HTTPS://Bücher.DE"),
+      "https://xn--bcher-kva.de/"
+    );
+    assert_eq!(
+      normalize_link("https://user@Example.com:8080/a b?q=é#top"),
+      "https://user@example.com:8080/a%20b?q=%C3%A9#top"
+    );
+    assert_eq!(
+      normalize_link("mailto:john@moon.space"),
+      "mailto:john@moon.space"
+    );
+  }
+
+  #[test]
+  fn deceptive_links() {
+    assert!(deceptive_link(
+      "https://paypal.com",
+      "https://evil.example/paypal"
+    ));
+    assert!(deceptive_link(
+      "paypal.com",
+      "https://paypal.com.evil.example/"
+    ));
+    assert!(deceptive_link("support@paypal.com", "https://evil.example"));
+    // A Cyrillic "а" in place of the Latin one.
+    assert!(deceptive_link("paypal.com", "https://xn--pypal-4ve.com/"));
+
+    assert!(!deceptive_link(
+      "paypal.com",
+      "https://www.paypal.com/signin"
+    ));
+    assert!(!deceptive_link("john@moon.space", "mailto:john@moon.space"));
+    assert!(!deceptive_link("bücher.de", "https://xn--bcher-kva.de/"));
+    assert!(!deceptive_link("Click here", "https://evil.example"));
+    assert!(!deceptive_link("", "https://evil.example"));
+  }
+
   #[test]
   fn wait_for_no_result() {
     assert_eq!(
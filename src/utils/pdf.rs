@@ -0,0 +1,82 @@
+// Rendering of PDF attachments.
+use std::error::Error;
+use std::ffi::OsStr;
+
+use gtk4::{gdk_pixbuf, gio, glib};
+
+use super::image::{load_pixbuf, Pixels};
+
+/// The pages of a PDF shown in a preview, the rest is left to another
+/// application.
+pub const MAX_PAGES: u32 = 20;
+
+/// The resolution the pages are drawn at, their size on a common screen.
+const RESOLUTION: &str = "96";
+
+/// Draws the first pages of a PDF. They are drawn by pdftoppm, from poppler,
+/// in a process of its own which is handed the document on its input: no
+/// script of the document is run, and it is never written anywhere.
+pub async fn render_pages(body: &[u8]) -> Result<Vec<gdk_pixbuf::Pixbuf>, Box<dyn Error>> {
+  let body = glib::Bytes::from(body);
+  let mut pngs: Vec<glib::Bytes> = vec![];
+  for page in 1..=MAX_PAGES {
+    match render_page(&body, page).await {
+      Ok(png) => pngs.push(png),
+      // There is no telling how many pages there are before asking for one
+      // past the last.
+      Err(e) if page > 1 => {
+        log::debug!("render_page({}) => {}", page, e);
+        break;
+      }
+      Err(e) => return Err(e),
+    }
+  }
+
+  let pages = gio::spawn_blocking(move || {
+    pngs
+      .iter()
+      .map(|png| {
+        load_pixbuf(png)
+          .map(|pixbuf| Pixels::new(&pixbuf))
+          .map_err(|e| e.to_string())
+      })
+      .collect::<Result<Vec<Pixels>, String>>()
+  })
+  .await
+  .map_err(|_| "Failed to decode the pages")??;
+  Ok(pages.iter().map(Pixels::pixbuf).collect())
+}
+
+/// The PNG image of a page, counted from 1.
+async fn render_page(body: &glib::Bytes, page: u32) -> Result<glib::Bytes, Box<dyn Error>> {
+  let page = page.to_string();
+  let args = [
+    "pdftoppm",
+    "-png",
+    "-r",
+    RESOLUTION,
+    "-f",
+    &page,
+    "-l",
+    &page,
+    "-singlefile",
+    "-",
+  ];
+  let args: Vec<&OsStr> = args.iter().map(OsStr::new).collect();
+  let process = gio::Subprocess::newv(
+    &args,
+    gio::SubprocessFlags::STDIN_PIPE
+      | gio::SubprocessFlags::STDOUT_PIPE
+      | gio::SubprocessFlags::STDERR_PIPE,
+  )?;
+  let (png, errors) = process.communicate_future(Some(body)).await?;
+  if !process.is_successful() {
+    let errors = errors.map_or(String::new(), |errors| {
+      String::from_utf8_lossy(&errors).trim().to_string()
+    });
+    return Err(format!("pdftoppm failed on page {}: {}", page, errors).into());
+  }
+  png
+    .filter(|png| !png.is_empty())
+    .ok_or_else(|| format!("pdftoppm drew nothing for page {}", page).into())
+}
@@ -17,35 +17,103 @@
  *
  * SPDX-License-Identifier: GPL-3.0-or-later
  */
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
 use std::option::Option;
+use std::rc::Rc;
+use std::sync::Arc;
 
 use adw::glib::clone;
 use adw::prelude::{AlertDialogExt, *};
 use adw::subclass::prelude::*;
 use gettextrs::{gettext, ngettext};
-use gtk4::{gio, glib, template_callbacks};
+use gtk4::{gdk, gio, glib, template_callbacks};
 use webkit6::prelude::{PolicyDecisionExt, WebViewExt};
 use webkit6::{
   FindOptions, NavigationPolicyDecision, PolicyDecision, PolicyDecisionType, PrintOperation, PrintOperationResponse, WebView
 };
 
-use crate::html::Html;
+use crate::attachmentcontent::AttachmentContent;
+use crate::html::{self, Html};
+use crate::indicators::Indicators;
+use crate::mailfolder::{FolderSort, MailFolder};
 use crate::mailservice::MailService;
+use crate::message::archive::Archive;
 use crate::message::attachment::Attachment;
+use crate::message::calendar::CalendarEvent;
 use crate::message::message::MessageParser;
-use crate::utils;
+use crate::message::preview::PreviewKind;
+use crate::message::risk::RiskLevel;
+use crate::message::vcard::{self, Contact};
+use crate::messagelist::MessageList;
+use crate::richtext::{self, Fold, Link};
+use crate::textsearch::{self, SearchOptions};
+use crate::trustedsenders::TrustedSenders;
+use crate::{eventcard, lightbox, mhtml, previewdialog, reportcard, searchdialog, utils};
 
 const SETTINGS_SHOW_FILE_NAME: &str = "show-file-name";
 const SETTINGS_FORCE_CSS: &str = "force-css";
+const SETTINGS_TRUSTED_SENDERS: &str = "trusted-senders";
+const SETTINGS_FOLDER_SORT: &str = "folder-sort";
+const SETTINGS_TEXT_MONOSPACE: &str = "text-monospace";
+const SETTINGS_DARK_RENDERING: &str = "dark-rendering";
+
+const TAG_MATCH: &str = "search-match";
+const TAG_CURRENT_MATCH: &str = "search-current-match";
+const TAG_LINK: &str = "link";
+const TAG_FOLD: &str = "fold";
+/// The line at the top of a plain text view, for it to be scrolled back to.
+const MARK_TOP: &str = "top";
+/// The colours of the quote levels of the plain text view, deeper levels start
+/// over.
+const QUOTE_COLOURS: [&str; 3] = ["#1c71d8", "#2ec27e", "#e66100"];
 
 /// Links in a message are opened by the system handler, so only hand over the
 /// schemes a mail is expected to link to.
 const ALLOWED_URI_SCHEMES: [&str; 3] = ["http", "https", "mailto"];
 
+const THUMBNAIL_SIZE: i32 = 160;
+
 const ZOOM_STEP: f64 = 0.1;
 const ZOOM_MIN: f64 = 0.3;
 const ZOOM_MAX: f64 = 5.0;
 
+/// A message opened in a tab. The tabs share the reading pane of the window,
+/// which shows the views of the selected one: they are kept with the tab, for
+/// its message to come back scrolled, zoomed and unfolded as it was left.
+#[derive(Debug)]
+pub struct MessageTab {
+  page: adw::TabPage,
+  service: Rc<MailService>,
+  cancellable: RefCell<gio::Cancellable>,
+  /// The folder the message is in, to go to the previous or the next one.
+  folder: RefCell<MailFolder>,
+  webview: webkit6::WebView,
+  text: gtk4::TextBuffer,
+  /// Whether the views show the message of the service, not a previous one.
+  rendered: Cell<bool>,
+  /// How the html view was last rendered, see `MailViewerWindow::rendering`.
+  rendering: Cell<Option<Rendering>>,
+  /// Whether the plain text view is the one shown.
+  show_text: Cell<bool>,
+  /// The address and the text of each link of the html view.
+  links: RefCell<Vec<(String, String)>>,
+  /// The addresses written in the plain text view.
+  text_links: RefCell<Vec<Link>>,
+  /// The quoted history folded in the plain text view, and the tags hiding it.
+  text_folds: RefCell<Vec<(Fold, gtk4::TextTag)>>,
+}
+
+/// The buttons and the sender a message is rendered for, it is rendered again
+/// when one of them changed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Rendering {
+  force_css: bool,
+  dark_rendering: bool,
+  remote_allowed: bool,
+  dark: bool,
+}
+
 mod imp {
   use std::cell::{OnceCell, RefCell};
 
@@ -70,6 +138,8 @@ mod imp {
     #[template_child]
     pub force_css: TemplateChild<gtk4::ToggleButton>,
     #[template_child]
+    pub dark_rendering: TemplateChild<gtk4::ToggleButton>,
+    #[template_child]
     pub zoom_minus: TemplateChild<gtk4::Button>,
     #[template_child]
     pub zoom_plus: TemplateChild<gtk4::Button>,
@@ -93,16 +163,59 @@ mod imp {
     pub search_bar: TemplateChild<gtk4::SearchBar>,
     #[template_child]
     pub search_entry: TemplateChild<gtk4::SearchEntry>,
+    #[template_child]
+    pub search_count: TemplateChild<gtk4::Label>,
+    #[template_child]
+    pub search_case_sensitive: TemplateChild<gtk4::ToggleButton>,
+    #[template_child]
+    pub search_whole_word: TemplateChild<gtk4::ToggleButton>,
+    #[template_child]
+    pub search_places: TemplateChild<gtk4::Box>,
+    #[template_child]
+    pub text_monospace: TemplateChild<gtk4::ToggleButton>,
+    #[template_child]
+    pub link_status: TemplateChild<gtk4::Label>,
+    #[template_child]
+    pub privacy_banner: TemplateChild<adw::Banner>,
+    #[template_child]
+    pub attachments_stack: TemplateChild<adw::ViewStack>,
+    #[template_child]
+    pub attachments_view: TemplateChild<gtk4::ToggleButton>,
+    #[template_child]
+    pub image_grid: TemplateChild<gtk4::FlowBox>,
+    #[template_child]
+    pub save_selected: TemplateChild<gtk4::Button>,
+    #[template_child]
+    pub events_box: TemplateChild<gtk4::Box>,
+    #[template_child]
+    pub report_box: TemplateChild<gtk4::Box>,
+    #[template_child]
+    pub export_contacts: TemplateChild<gtk4::Button>,
+    #[template_child]
+    pub tab_view: TemplateChild<adw::TabView>,
+    #[template_child]
+    pub folder_navigation: TemplateChild<gtk4::Box>,
+    #[template_child]
+    pub message_list_box: TemplateChild<gtk4::Box>,
     //
     pub scrolled_window: ScrolledWindow,
     pub network_session: webkit6::NetworkSession,
-    pub webview: webkit6::WebView,
-    pub websettings: webkit6::Settings,
+    pub message_list: MessageList,
     pub settings: OnceCell<gio::Settings>,
-    pub service: MailService,
-    pub cancellable: RefCell<gio::Cancellable>,
+    pub tabs: RefCell<Vec<Rc<MessageTab>>>,
     pub print_webview: RefCell<Option<webkit6::WebView>>,
     pub print_operation: RefCell<Option<webkit6::PrintOperation>>,
+    /// The uri and the text of the link under the pointer.
+    pub hovered_link: RefCell<Option<(String, String)>>,
+    /// What to find in the next message shown, opened from a folder search.
+    pub pending_highlight: RefCell<Option<String>>,
+    /// The matches of the search in the plain text view, and the current one.
+    pub text_matches: RefCell<Vec<(usize, usize)>>,
+    pub text_match: Cell<Option<usize>>,
+    pub images: RefCell<Vec<Attachment>>,
+    /// The rows of the attachment sheet, for the search to find them by name.
+    pub attachment_rows: RefCell<Vec<adw::PreferencesRow>>,
+    pub contacts: RefCell<Vec<Contact>>,
   }
 
   impl Default for MailViewerWindow {
@@ -112,10 +225,9 @@ mod imp {
       let network_session = webkit6::NetworkSession::new_ephemeral();
 
       MailViewerWindow {
-        webview: WebView::builder().network_session(&network_session).build(),
         network_session,
-        websettings: webkit6::Settings::new(),
         scrolled_window: ScrolledWindow::new(),
+        message_list: MessageList::new(),
         from: TemplateChild::default(),
         to: TemplateChild::default(),
         subject: TemplateChild::default(),
@@ -123,6 +235,7 @@ mod imp {
         placeholder: TemplateChild::default(),
         show_images: TemplateChild::default(),
         force_css: TemplateChild::default(),
+        dark_rendering: TemplateChild::default(),
         zoom_minus: TemplateChild::default(),
         zoom_plus: TemplateChild::default(),
         show_text: TemplateChild::default(),
@@ -132,13 +245,36 @@ mod imp {
         attachments_clamp: TemplateChild::default(),
         search_bar: TemplateChild::default(),
         search_entry: TemplateChild::default(),
+        search_count: TemplateChild::default(),
+        search_case_sensitive: TemplateChild::default(),
+        search_whole_word: TemplateChild::default(),
+        search_places: TemplateChild::default(),
+        text_monospace: TemplateChild::default(),
+        link_status: TemplateChild::default(),
+        privacy_banner: TemplateChild::default(),
+        attachments_stack: TemplateChild::default(),
+        attachments_view: TemplateChild::default(),
+        image_grid: TemplateChild::default(),
+        save_selected: TemplateChild::default(),
+        events_box: TemplateChild::default(),
+        report_box: TemplateChild::default(),
+        export_contacts: TemplateChild::default(),
+        tab_view: TemplateChild::default(),
+        folder_navigation: TemplateChild::default(),
+        message_list_box: TemplateChild::default(),
         content_box: TemplateChild::default(),
         sheet: TemplateChild::default(),
         settings: OnceCell::new(),
-        service: MailService::new(),
-        cancellable: RefCell::new(gio::Cancellable::new()),
+        tabs: RefCell::new(vec![]),
         print_webview: RefCell::new(None),
         print_operation: RefCell::new(None),
+        hovered_link: RefCell::new(None),
+        pending_highlight: RefCell::new(None),
+        text_matches: RefCell::new(vec![]),
+        text_match: Cell::new(None),
+        images: RefCell::new(vec![]),
+        attachment_rows: RefCell::new(vec![]),
+        contacts: RefCell::new(vec![]),
       }
     }
   }
@@ -166,29 +302,65 @@ mod imp {
           window.open_file_dialog(close).await;
         },
       );
+      klass.install_action_async("win.open-folder-dialog", None, |window, _, _| async move {
+        window.open_folder_dialog().await;
+      });
+      klass.install_action_async("win.search-folder", None, |window, _, _| async move {
+        window.search_folder().await;
+      });
       klass.install_action_async("win.print", None, |window, _, _| async move {
         window.print().await;
       });
+      klass.install_action_async("win.export-indicators", None, |window, _, _| async move {
+        window.export_indicators().await;
+      });
+      klass.install_action_async("win.save-html", None, |window, _, _| async move {
+        window.save_page(false).await;
+      });
+      klass.install_action_async("win.save-mhtml", None, |window, _, _| async move {
+        window.save_page(true).await;
+      });
+      klass.install_action_async("win.save-eml", None, |window, _, _| async move {
+        window.save_eml().await;
+      });
+      klass.install_action_async(
+        "win.save-attachments-zip",
+        None,
+        |window, _, _| async move {
+          window.save_attachments_zip().await;
+        },
+      );
       klass.install_action_async(
-        "win.open-file",
+        "win.open-files",
         None,
         |window, _, parameter: Option<glib::Variant>| async move {
-          let mut filename: Option<String> = None;
+          let mut filenames: Vec<String> = vec![];
           if let Some(parameter) = parameter {
-            filename = parameter.get::<Option<String>>().unwrap();
+            filenames = parameter.get::<Vec<String>>().unwrap_or_default();
           }
-          if let Some(filename) = filename {
-            let file = if filename.starts_with("/") {
-              gio::File::for_path(filename.as_str())
-            } else {
-              gio::File::for_uri(filename.as_str())
-            };
-            window.open_file(&file).await;
-          } else {
+          if filenames.is_empty() {
             window.open_file_dialog(true).await;
+            return;
           }
+          let files: Vec<gio::File> = filenames
+            .iter()
+            .map(|filename| {
+              if filename.starts_with("/") {
+                gio::File::for_path(filename.as_str())
+              } else {
+                gio::File::for_uri(filename.as_str())
+              }
+            })
+            .collect();
+          window.open_files(&files).await;
         },
       );
+      klass.install_action_async("win.previous-message", None, |window, _, _| async move {
+        window.open_neighbour(-1).await;
+      });
+      klass.install_action_async("win.next-message", None, |window, _, _| async move {
+        window.open_neighbour(1).await;
+      });
       klass.install_action("win.search", None, move |win, _, _| {
         win.start_search();
       });
@@ -247,6 +419,14 @@ impl MailViewerWindow {
     self.load_html(self.imp().force_css.is_active());
   }
 
+  #[template_callback]
+  pub fn on_dark_rendering_toggled(&self) {
+    log::debug!("on_dark_rendering_toggled()");
+    if self.imp().tab_view.n_pages() > 0 {
+      self.load_html(self.imp().force_css.is_active());
+    }
+  }
+
   #[template_callback]
   pub fn on_show_text_clicked(&self) {
     let show = self.imp().show_text.is_active();
@@ -258,54 +438,168 @@ impl MailViewerWindow {
   pub fn on_show_images_clicked(&self) {
     let show = self.imp().show_images.is_active();
     log::debug!("on_show_images_clicked({})", show);
-    self.imp().websettings.set_auto_load_images(show);
     // The policy travels with the html, so the message has to be rendered again.
     self.load_html(self.imp().force_css.is_active());
   }
 
-  /// Ctrl+F. The bar rides on the html view, the plain text one is a
-  /// GtkTextView and does not go through the find controller.
-  fn start_search(&self) {
-    if self.imp().show_text.is_active() {
+  /// "Always load from this sender" on the banner of a message whose remote
+  /// content is blocked.
+  #[template_callback]
+  pub fn on_privacy_banner_button_clicked(&self) {
+    let Some(address) = self
+      .service()
+      .and_then(|service| TrustedSenders::sender_address(&service.from()))
+    else {
       return;
+    };
+    log::debug!("on_privacy_banner_button_clicked({})", address);
+    let mut entries = self.get_settings_trusted_senders();
+    if !entries.contains(&address) {
+      entries.push(address);
+      self.set_settings_trusted_senders(entries);
     }
+    self.load_html(self.imp().force_css.is_active());
+  }
+
+  /// Ctrl+F. The html view is searched by its find controller, the plain
+  /// text one through the tags of its buffer.
+  fn start_search(&self) {
     self.imp().search_bar.set_search_mode(true);
     self.imp().search_entry.grab_focus();
   }
 
+  /// Finds `text` in the message, as if the user searched for it.
+  fn highlight(&self, text: &str) {
+    log::debug!("highlight({})", text);
+    self.start_search();
+    self.imp().search_entry.set_text(text);
+  }
+
+  /// Finds `text` in the message `file` was just opened as, when it did open:
+  /// at once in the text view, once loaded in the html one.
+  fn highlight_opened(&self, file: &gio::File, text: String) {
+    let opened = self
+      .service()
+      .and_then(|service| service.get_file())
+      .is_some_and(|opened| opened.equal(file));
+    if !opened {
+      return;
+    }
+    if self.imp().show_text.is_active() {
+      self.highlight(&text);
+    } else {
+      self.imp().pending_highlight.replace(Some(text));
+    }
+  }
+
   fn find_controller(&self) -> Option<webkit6::FindController> {
-    self.imp().webview.find_controller()
+    self.selected_tab()?.webview.find_controller()
+  }
+
+  #[template_callback]
+  pub fn on_attachments_view_toggled(&self) {
+    let imp = self.imp();
+    let grid = imp.attachments_view.is_active();
+    log::debug!("on_attachments_view_toggled({})", grid);
+    imp
+      .attachments_stack
+      .set_visible_child_name(if grid { "grid" } else { "list" });
+  }
+
+  #[template_callback]
+  pub fn on_image_activated(&self, child: &gtk4::FlowBoxChild) {
+    let images = self.imp().images.borrow().clone();
+    lightbox::show(self, images, child.index() as usize);
+  }
+
+  #[template_callback]
+  pub fn on_image_selection_changed(&self) {
+    let imp = self.imp();
+    imp
+      .save_selected
+      .set_sensitive(!imp.image_grid.selected_children().is_empty());
+  }
+
+  #[template_callback]
+  pub fn on_save_selected_clicked(&self) {
+    glib::spawn_future_local(clone!(
+      #[weak(rename_to = window)]
+      self,
+      async move {
+        window.save_selected_images().await;
+      }
+    ));
+  }
+
+  #[template_callback]
+  pub fn on_save_all_clicked(&self) {
+    glib::spawn_future_local(clone!(
+      #[weak(rename_to = window)]
+      self,
+      async move {
+        window.save_all_attachments().await;
+      }
+    ));
+  }
+
+  #[template_callback]
+  pub fn on_export_contacts_clicked(&self) {
+    glib::spawn_future_local(clone!(
+      #[weak(rename_to = window)]
+      self,
+      async move {
+        window.export_contacts().await;
+      }
+    ));
   }
 
   #[template_callback]
   pub fn on_search_changed(&self) {
-    let text = self.imp().search_entry.text();
+    let imp = self.imp();
+    let text = imp.search_entry.text();
+    log::debug!("on_search_changed({})", text);
+    self.search_places(&text);
+    if imp.show_text.is_active() {
+      if let Some(controller) = self.find_controller() {
+        controller.search_finish();
+      }
+      self.search_text(&text);
+      return;
+    }
+
+    self.clear_text_matches();
     let Some(controller) = self.find_controller() else {
       return;
     };
-
     if text.is_empty() {
       controller.search_finish();
+      imp.search_count.set_label("");
       return;
     }
-    log::debug!("on_search_changed({})", text);
-    controller.search(
-      &text,
-      (FindOptions::CASE_INSENSITIVE | FindOptions::WRAP_AROUND).bits(),
-      u32::MAX,
-    );
+    let mut options = FindOptions::WRAP_AROUND;
+    if !imp.search_case_sensitive.is_active() {
+      options |= FindOptions::CASE_INSENSITIVE;
+    }
+    if imp.search_whole_word.is_active() {
+      options |= FindOptions::AT_WORD_STARTS;
+    }
+    controller.search(&text, options.bits(), u32::MAX);
   }
 
   #[template_callback]
   pub fn on_search_next(&self) {
-    if let Some(controller) = self.find_controller() {
+    if self.imp().show_text.is_active() {
+      self.move_text_match(1);
+    } else if let Some(controller) = self.find_controller() {
       controller.search_next();
     }
   }
 
   #[template_callback]
   pub fn on_search_previous(&self) {
-    if let Some(controller) = self.find_controller() {
+    if self.imp().show_text.is_active() {
+      self.move_text_match(-1);
+    } else if let Some(controller) = self.find_controller() {
       controller.search_previous();
     }
   }
@@ -313,22 +607,217 @@ impl MailViewerWindow {
   #[template_callback]
   pub fn on_search_stopped(&self) {
     log::debug!("on_search_stopped()");
-    if let Some(controller) = self.find_controller() {
-      controller.search_finish();
+    // The tabs in the background keep the highlights of the search too.
+    for tab in self.imp().tabs.borrow().iter() {
+      if let Some(controller) = tab.webview.find_controller() {
+        controller.search_finish();
+      }
+      Self::clear_text_tags(&tab.text);
     }
+    self.clear_text_matches();
+    self.search_places("");
+    self.imp().search_count.set_label("");
     self.imp().search_bar.set_search_mode(false);
   }
 
+  /// Lists in the search bar the other places `text` is found in: the headers
+  /// and the names of the attachments. Each one goes to its matches.
+  fn search_places(&self, text: &str) {
+    let imp = self.imp();
+    while let Some(child) = imp.search_places.first_child() {
+      imp.search_places.remove(&child);
+    }
+    if text.is_empty() {
+      imp.search_places.set_visible(false);
+      return;
+    }
+
+    let options = self.search_options();
+    for (name, entry) in [
+      (gettext("From"), imp.from.get()),
+      (gettext("To"), imp.to.get()),
+      (gettext("Subject"), imp.subject.get()),
+      (gettext("Date"), imp.date.get()),
+    ] {
+      let matches = textsearch::find_all(&entry.text(), text, options);
+      let Some((start, end)) = matches.first().copied() else {
+        continue;
+      };
+      let button = Self::search_place_button(&name);
+      button.connect_clicked(clone!(
+        #[weak]
+        entry,
+        move |_| {
+          entry.grab_focus();
+          entry.select_region(start as i32, end as i32);
+        }
+      ));
+      imp.search_places.append(&button);
+    }
+
+    let rows: Vec<adw::PreferencesRow> = imp
+      .attachment_rows
+      .borrow()
+      .iter()
+      .filter(|row| !textsearch::find_all(&row.title(), text, options).is_empty())
+      .cloned()
+      .collect();
+    if !rows.is_empty() {
+      let count = rows.len() as u32;
+      let button = Self::search_place_button(
+        &ngettext("{count} Attachment", "{count} Attachments", count)
+          .replace("{count}", &count.to_string()),
+      );
+      // Each click goes to the next attachment found.
+      let next = Cell::new(0);
+      button.connect_clicked(clone!(
+        #[weak(rename_to = window)]
+        self,
+        move |_| {
+          let imp = window.imp();
+          imp.attachments_view.set_active(false);
+          imp.sheet.set_open(true);
+          let row = &rows[next.get() % rows.len()];
+          next.set(next.get() + 1);
+          row.grab_focus();
+        }
+      ));
+      imp.search_places.append(&button);
+    }
+    imp
+      .search_places
+      .set_visible(imp.search_places.first_child().is_some());
+  }
+
+  fn search_place_button(label: &str) -> gtk4::Button {
+    let button = gtk4::Button::with_label(label);
+    button.add_css_class("flat");
+    button.set_tooltip_text(Some(&gettext("Go to the matches")));
+    button
+  }
+
+  fn search_options(&self) -> SearchOptions {
+    SearchOptions {
+      case_sensitive: self.imp().search_case_sensitive.is_active(),
+      whole_word: self.imp().search_whole_word.is_active(),
+    }
+  }
+
+  /// Highlights all the matches of `text` in the plain text view, and goes to
+  /// the first one.
+  fn search_text(&self, text: &str) {
+    let imp = self.imp();
+    self.clear_text_matches();
+    if text.is_empty() {
+      imp.search_count.set_label("");
+      return;
+    }
+
+    let buffer = imp.body_text.buffer();
+    let (start, end) = buffer.bounds();
+    let matches = textsearch::find_all(
+      &buffer.text(&start, &end, true),
+      text,
+      self.search_options(),
+    );
+    for (start, end) in &matches {
+      buffer.apply_tag_by_name(
+        TAG_MATCH,
+        &buffer.iter_at_offset(*start as i32),
+        &buffer.iter_at_offset(*end as i32),
+      );
+    }
+    let found = !matches.is_empty();
+    imp.text_matches.replace(matches);
+    if found {
+      self.select_text_match(0);
+    } else {
+      imp.search_count.set_label(&gettext("No matches"));
+    }
+  }
+
+  fn clear_text_matches(&self) {
+    let imp = self.imp();
+    Self::clear_text_tags(&imp.body_text.buffer());
+    imp.text_matches.borrow_mut().clear();
+    imp.text_match.set(None);
+  }
+
+  fn clear_text_tags(buffer: &gtk4::TextBuffer) {
+    let (start, end) = buffer.bounds();
+    buffer.remove_tag_by_name(TAG_MATCH, &start, &end);
+    buffer.remove_tag_by_name(TAG_CURRENT_MATCH, &start, &end);
+  }
+
+  /// Goes `step` matches forward or backward in the plain text view, around
+  /// the ends.
+  fn move_text_match(&self, step: isize) {
+    let imp = self.imp();
+    let count = imp.text_matches.borrow().len();
+    if count == 0 {
+      return;
+    }
+    let index = match imp.text_match.get() {
+      Some(index) => (index as isize + step).rem_euclid(count as isize) as usize,
+      None if step < 0 => count - 1,
+      None => 0,
+    };
+    self.select_text_match(index);
+  }
+
+  fn select_text_match(&self, index: usize) {
+    let imp = self.imp();
+    let Some((start, end)) = imp.text_matches.borrow().get(index).copied() else {
+      return;
+    };
+    let buffer = imp.body_text.buffer();
+    if let Some((previous_start, previous_end)) = imp
+      .text_match
+      .get()
+      .and_then(|previous| imp.text_matches.borrow().get(previous).copied())
+    {
+      buffer.remove_tag_by_name(
+        TAG_CURRENT_MATCH,
+        &buffer.iter_at_offset(previous_start as i32),
+        &buffer.iter_at_offset(previous_end as i32),
+      );
+    }
+
+    // A match in the quoted history unfolds it.
+    if let Some(tab) = self.selected_tab() {
+      for (fold, tag) in tab.text_folds.borrow().iter() {
+        if fold.hidden.0 <= start && start < fold.hidden.1 {
+          tag.set_invisible(false);
+        }
+      }
+    }
+    let start = buffer.iter_at_offset(start as i32);
+    let end = buffer.iter_at_offset(end as i32);
+    buffer.apply_tag_by_name(TAG_CURRENT_MATCH, &start, &end);
+    buffer.select_range(&start, &end);
+    imp
+      .body_text
+      .scroll_to_mark(&buffer.get_insert(), 0.1, false, 0.0, 0.0);
+    imp.text_match.set(Some(index));
+
+    let count = imp.text_matches.borrow().len();
+    imp.search_count.set_label(
+      &gettext("{current} of {count}")
+        .replace("{current}", &(index + 1).to_string())
+        .replace("{count}", &count.to_string()),
+    );
+  }
+
   #[template_callback]
   pub fn on_zoom_minus_clicked(&self) {
     log::debug!("on_zoom_minus_clicked()");
-    self.set_zoom_level(self.imp().webview.zoom_level() - ZOOM_STEP);
+    self.set_zoom_level(self.zoom_level() - ZOOM_STEP);
   }
 
   #[template_callback]
   pub fn on_zoom_plus_clicked(&self) {
     log::debug!("on_zoom_plus_clicked()");
-    self.set_zoom_level(self.imp().webview.zoom_level() + ZOOM_STEP);
+    self.set_zoom_level(self.zoom_level() + ZOOM_STEP);
   }
 
   fn initialize(&self) {
@@ -337,87 +826,383 @@ impl MailViewerWindow {
 
     self.initialize_settings();
     self.initialize_actions();
-    self.initialise_webview(&imp.webview, &imp.websettings);
 
-    imp.placeholder.set_child(Some(&imp.webview));
-  }
-
-  fn initialise_webview(&self, webview: &webkit6::WebView, websettings: &webkit6::Settings) {
-    websettings.set_allow_file_access_from_file_urls(false);
-    websettings.set_enable_back_forward_navigation_gestures(false);
-    websettings.set_enable_developer_extras(false);
-    websettings.set_enable_dns_prefetching(false);
-    websettings.set_allow_modal_dialogs(false);
-    websettings.set_allow_universal_access_from_file_urls(false);
-    websettings.set_enable_javascript(false);
-    websettings.set_enable_webgl(false);
-    websettings.set_enable_webaudio(false);
-    websettings.set_auto_load_images(self.imp().show_images.is_active());
-    webview.set_settings(websettings);
-    webview.set_editable(false);
-    webview.connect_context_menu(move |_, _, _| {
-      log::debug!("WebView() => context_menu() cancelled");
-      true
-    });
-    webview.set_receives_default(false);
+    imp.message_list_box.append(imp.message_list.widget());
+    self.initialize_text_view();
+    self.initialize_text_search();
   }
 
-  fn initialize_actions(&self) {
-    let win = self;
+  fn initialize_text_view(&self) {
     let imp = self.imp();
+    let buffer = imp.body_text.buffer();
+    buffer.create_tag(Some(TAG_LINK), &[
+      ("foreground", &"#3584e4"),
+      ("underline", &gtk4::pango::Underline::Single),
+    ]);
+    for (level, colour) in QUOTE_COLOURS.iter().enumerate() {
+      buffer.create_tag(Some(Self::quote_tag(level + 1).as_str()), &[(
+        "foreground",
+        colour,
+      )]);
+    }
+    buffer.create_tag(Some(TAG_FOLD), &[
+      ("foreground", &"#77767b"),
+      ("style", &gtk4::pango::Style::Italic),
+    ]);
 
-    let drop_target = gtk4::DropTarget::new(gio::File::static_type(), gtk4::gdk::DragAction::COPY);
-    imp.body_text.add_controller(drop_target.clone());
-    drop_target.connect_drop(clone!(
-      #[strong]
-      win,
-      move |_, data, _, _| {
-        if let Ok(file) = data.get::<gio::File>() {
-          glib::spawn_future_local(glib::clone!(
-            #[strong]
-            win,
-            #[weak]
-            file,
-            async move {
-              win.open_file(&file).await;
-            }
-          ));
+    let click = gtk4::GestureClick::new();
+    click.connect_released(clone!(
+      #[weak(rename_to = window)]
+      self,
+      move |_, _, x, y| {
+        // Letting go after selecting some text is not a click on a link.
+        if window.imp().body_text.buffer().has_selection() {
+          return;
         }
-
-        false
+        let Some(offset) = window.text_offset_at(x, y) else {
+          return;
+        };
+        if let Some(fold) = window.text_fold_at(offset) {
+          fold.set_invisible(!fold.is_invisible());
+          return;
+        }
+        let Some(link) = window.text_link_at(offset) else {
+          return;
+        };
+        glib::spawn_future_local(clone!(
+          #[weak]
+          window,
+          async move {
+            if let Err(e) = window.open_link(None, &link.uri).await {
+              window.alert_error(&gettext("Link Error"), &e.to_string(), false);
+            }
+          }
+        ));
       }
     ));
+    imp.body_text.add_controller(click);
 
-    // The forced css carries the colours, so it has to be rendered again when
-    // the desktop switches between light and dark.
-    adw::StyleManager::default().connect_dark_notify(clone!(
+    let motion = gtk4::EventControllerMotion::new();
+    motion.connect_motion(clone!(
       #[weak(rename_to = window)]
       self,
-      move |_| {
-        if window.imp().force_css.is_active() {
-          log::debug!("colour scheme changed, rendering again");
-          window.load_html(true);
-        }
+      move |_, x, y| {
+        let clickable = window.text_offset_at(x, y).is_some_and(|offset| {
+          window.text_link_at(offset).is_some() || window.text_fold_at(offset).is_some()
+        });
+        let cursor = if clickable { "pointer" } else { "text" };
+        window.imp().body_text.set_cursor_from_name(Some(cursor));
       }
     ));
+    imp.body_text.add_controller(motion);
+  }
 
-    imp.webview.connect_decide_policy(clone!(
-      #[strong]
-      win,
-      move |webview: &WebView, policy: &PolicyDecision, decision_type: PolicyDecisionType| {
-        return win.on_decide_policy(webview, policy, decision_type);
+  fn quote_tag(level: usize) -> String {
+    format!("quote-{}", level)
+  }
+
+  /// The character under the pointer, at `x`, `y` of the plain text view.
+  fn text_offset_at(&self, x: f64, y: f64) -> Option<usize> {
+    let view = &self.imp().body_text;
+    let (x, y) = view.window_to_buffer_coords(gtk4::TextWindowType::Widget, x as i32, y as i32);
+    Some(view.iter_at_location(x, y)?.offset() as usize)
+  }
+
+  fn text_link_at(&self, offset: usize) -> Option<Link> {
+    self
+      .selected_tab()?
+      .text_links
+      .borrow()
+      .iter()
+      .find(|link| link.start <= offset && offset < link.end)
+      .cloned()
+  }
+
+  /// The tag hiding the quoted lines the line at `offset` folds, if it is one.
+  fn text_fold_at(&self, offset: usize) -> Option<gtk4::TextTag> {
+    self
+      .selected_tab()?
+      .text_folds
+      .borrow()
+      .iter()
+      .find(|(fold, _)| fold.toggle.0 <= offset && offset <= fold.toggle.1)
+      .map(|(_, tag)| tag.clone())
+  }
+
+  /// Writes `text` in the plain text view of `tab`, with its links and its
+  /// quote levels coloured, and its quoted history folded.
+  fn set_body_text(tab: &MessageTab, text: &str) {
+    let buffer = &tab.text;
+    let (text, folds) = richtext::fold_quotes(text, |count| {
+      ngettext(
+        "▸ {count} quoted line",
+        "▸ {count} quoted lines",
+        count as u32,
+      )
+      .replace("{count}", &count.to_string())
+    });
+    let text = text.as_str();
+    buffer.set_text(text);
+
+    let tag_table = buffer.tag_table();
+    for (_, tag) in tab.text_folds.take() {
+      tag_table.remove(&tag);
+    }
+    let mut fold_tags = vec![];
+    for fold in folds {
+      buffer.apply_tag_by_name(
+        TAG_FOLD,
+        &buffer.iter_at_offset(fold.toggle.0 as i32),
+        &buffer.iter_at_offset(fold.toggle.1 as i32),
+      );
+      let tag = gtk4::TextTag::new(None);
+      tag.set_invisible(true);
+      tag_table.add(&tag);
+      buffer.apply_tag(
+        &tag,
+        &buffer.iter_at_offset(fold.hidden.0 as i32),
+        &buffer.iter_at_offset(fold.hidden.1 as i32),
+      );
+      fold_tags.push((fold, tag));
+    }
+    tab.text_folds.replace(fold_tags);
+
+    let links = richtext::find_links(text);
+    for link in &links {
+      buffer.apply_tag_by_name(
+        TAG_LINK,
+        &buffer.iter_at_offset(link.start as i32),
+        &buffer.iter_at_offset(link.end as i32),
+      );
+    }
+    tab.text_links.replace(links);
+
+    let mut offset = 0;
+    for line in text.split('\n') {
+      let length = line.chars().count();
+      let level = richtext::quote_level(line);
+      if level > 0 {
+        buffer.apply_tag_by_name(
+          &Self::quote_tag((level - 1) % QUOTE_COLOURS.len() + 1),
+          &buffer.iter_at_offset(offset as i32),
+          &buffer.iter_at_offset((offset + length) as i32),
+        );
       }
-    ));
+      offset += length + 1;
+    }
   }
 
-  fn initialize_settings(&self) {
-    let settings = gio::Settings::new(crate::config::APP_ID);
+  fn initialize_text_search(&self) {
+    let imp = self.imp();
+    let buffer = imp.body_text.buffer();
+    buffer.create_tag(Some(TAG_MATCH), &[(
+      "background",
+      &"rgba(246, 211, 45, 0.5)",
+    )]);
+    buffer.create_tag(Some(TAG_CURRENT_MATCH), &[(
+      "background",
+      &"rgba(255, 120, 0, 0.8)",
+    )]);
+  }
+
+  /// A plain text view for a tab, sharing the tags of the one of the window.
+  fn text_buffer(&self) -> gtk4::TextBuffer {
+    let buffer = gtk4::TextBuffer::new(Some(&self.imp().body_text.buffer().tag_table()));
+    // Another message, the matches are those of the previous one.
+    buffer.connect_changed(clone!(
+      #[weak(rename_to = window)]
+      self,
+      move |buffer| {
+        let imp = window.imp();
+        if imp.body_text.buffer() == *buffer
+          && imp.search_bar.is_search_mode()
+          && imp.show_text.is_active()
+        {
+          window.search_text(&imp.search_entry.text());
+        }
+      }
+    ));
+    buffer
+  }
+
+  /// An html view for a tab, at the zoom level last chosen.
+  fn message_webview(&self) -> webkit6::WebView {
+    let webview = WebView::builder()
+      .network_session(&self.imp().network_session)
+      .build();
+    // Each view has its own settings, a sender trusted in a tab isn't in the
+    // others.
+    self.initialise_webview(&webview, &webkit6::Settings::new());
+    if let Some(settings) = self.imp().settings.get() {
+      webview.set_zoom_level(settings.get::<f64>("zoom").clamp(ZOOM_MIN, ZOOM_MAX));
+    }
+
+    webview.connect_decide_policy(clone!(
+      #[weak(rename_to = window)]
+      self,
+      #[upgrade_or]
+      false,
+      move |webview: &WebView, policy: &PolicyDecision, decision_type: PolicyDecisionType| {
+        window.on_decide_policy(webview, policy, decision_type)
+      }
+    ));
+
+    webview.connect_load_changed(clone!(
+      #[weak(rename_to = window)]
+      self,
+      move |webview, event| {
+        if event != webkit6::LoadEvent::Finished {
+          return;
+        }
+        if !window
+          .selected_tab()
+          .is_some_and(|tab| &tab.webview == webview)
+        {
+          return;
+        }
+        let highlight = window.imp().pending_highlight.take();
+        if let Some(text) = highlight {
+          window.highlight(&text);
+        }
+      }
+    ));
+
+    webview.connect_mouse_target_changed(clone!(
+      #[weak(rename_to = window)]
+      self,
+      move |_, hit_test_result, _| {
+        window.on_mouse_target_changed(hit_test_result);
+      }
+    ));
+
+    if let Some(controller) = webview.find_controller() {
+      controller.connect_found_text(clone!(
+        #[weak(rename_to = window)]
+        self,
+        move |_, count| {
+          window.imp().search_count.set_label(
+            &ngettext("{count} match", "{count} matches", count)
+              .replace("{count}", &count.to_string()),
+          );
+        }
+      ));
+      controller.connect_failed_to_find_text(clone!(
+        #[weak(rename_to = window)]
+        self,
+        move |_| {
+          let imp = window.imp();
+          let label = if imp.search_entry.text().is_empty() {
+            String::new()
+          } else {
+            gettext("No matches")
+          };
+          imp.search_count.set_label(&label);
+        }
+      ));
+    }
+    webview
+  }
+
+  fn initialise_webview(&self, webview: &webkit6::WebView, websettings: &webkit6::Settings) {
+    websettings.set_allow_file_access_from_file_urls(false);
+    websettings.set_enable_back_forward_navigation_gestures(false);
+    websettings.set_enable_developer_extras(false);
+    websettings.set_enable_dns_prefetching(false);
+    websettings.set_allow_modal_dialogs(false);
+    websettings.set_allow_universal_access_from_file_urls(false);
+    websettings.set_enable_javascript(false);
+    websettings.set_enable_webgl(false);
+    websettings.set_enable_webaudio(false);
+    websettings.set_auto_load_images(self.imp().show_images.is_active());
+    webview.set_settings(websettings);
+    webview.set_editable(false);
+    webview.connect_context_menu(move |_, _, _| {
+      log::debug!("WebView() => context_menu() cancelled");
+      true
+    });
+    webview.set_receives_default(false);
+  }
+
+  fn initialize_actions(&self) {
+    let win = self;
+    let imp = self.imp();
+
+    let drop_target = gtk4::DropTarget::new(
+      gtk4::gdk::FileList::static_type(),
+      gtk4::gdk::DragAction::COPY,
+    );
+    imp.body_text.add_controller(drop_target.clone());
+    drop_target.connect_drop(clone!(
+      #[strong]
+      win,
+      move |_, data, _, _| {
+        if let Ok(files) = data.get::<gtk4::gdk::FileList>() {
+          glib::spawn_future_local(glib::clone!(
+            #[strong]
+            win,
+            async move {
+              win.open_files(&files.files()).await;
+            }
+          ));
+        }
+
+        false
+      }
+    ));
+
+    imp.message_list.connect_open(clone!(
+      #[weak(rename_to = window)]
+      self,
+      move |file| {
+        glib::spawn_future_local(clone!(
+          #[weak]
+          window,
+          async move {
+            window.open_file(&file).await;
+          }
+        ));
+      }
+    ));
+    imp.message_list.connect_recursive_toggled(clone!(
+      #[weak(rename_to = window)]
+      self,
+      move || {
+        if let Some(folder) = window.imp().message_list.folder() {
+          glib::spawn_future_local(clone!(
+            #[weak]
+            window,
+            async move {
+              window.open_folder(&folder).await;
+            }
+          ));
+        }
+      }
+    ));
+    imp.message_list.connect_close(clone!(
+      #[weak(rename_to = window)]
+      self,
+      move || window.close_folder()
+    ));
+
+    // The forced css carries the colours, so it has to be rendered again when
+    // the desktop switches between light and dark.
+    adw::StyleManager::default().connect_dark_notify(clone!(
+      #[weak(rename_to = window)]
+      self,
+      move |_| {
+        if window.imp().force_css.is_active() {
+          log::debug!("colour scheme changed, rendering again");
+          window.load_html(true);
+        }
+      }
+    ));
+  }
+
+  fn initialize_settings(&self) {
+    let settings = gio::Settings::new(crate::config::APP_ID);
     let imp = self.imp();
 
     imp.settings.set(settings.clone()).unwrap();
-    imp
-      .webview
-      .set_zoom_level(settings.get::<f64>("zoom").clamp(ZOOM_MIN, ZOOM_MAX));
 
     settings
       .bind("width", self, "default-width")
@@ -436,20 +1221,32 @@ impl MailViewerWindow {
       .flags(gio::SettingsBindFlags::DEFAULT)
       .build();
 
-    imp.service.connect_title_changed(clone!(
-      #[weak(rename_to = window)]
-      self,
-      move |_, title| {
-        window.set_title(Some(title));
-      }
-    ));
-
-    imp
-      .service
-      .set_show_file_name(self.get_settings_show_file_name());
     imp
       .force_css
       .set_active(self.get_settings_bool(SETTINGS_FORCE_CSS));
+
+    settings
+      .bind(SETTINGS_TEXT_MONOSPACE, &*imp.text_monospace, "active")
+      .flags(gio::SettingsBindFlags::DEFAULT)
+      .build();
+    settings
+      .bind(SETTINGS_DARK_RENDERING, &*imp.dark_rendering, "active")
+      .flags(gio::SettingsBindFlags::DEFAULT)
+      .build();
+
+    self.add_action(&settings.create_action(SETTINGS_FOLDER_SORT));
+    settings.connect_changed(
+      Some(SETTINGS_FOLDER_SORT),
+      clone!(
+        #[weak(rename_to = window)]
+        self,
+        move |_, _| {
+          for tab in window.imp().tabs.borrow().iter() {
+            window.update_folder(tab.clone());
+          }
+        }
+      ),
+    );
   }
 
   fn reset_zoom(&self) {
@@ -464,12 +1261,30 @@ impl MailViewerWindow {
       .mime_type
       .clone()
       .unwrap_or("Unknown".to_string());
-    let icon = if mime.starts_with("image") {
+    let risk = attachment.risk();
+    let icon = if risk.level == RiskLevel::Dangerous {
+      "dialog-warning-symbolic"
+    } else if mime.starts_with("image") {
       "image-x-generic-symbolic"
     } else {
       "document-open"
     };
 
+    // Hashing a large attachment takes a while, only do it when asked to.
+    let details = gtk4::MenuButton::new();
+    details.set_valign(gtk4::Align::Center);
+    details.set_icon_name("dialog-information-symbolic");
+    details.set_tooltip_text(Some(&gettext("Details")));
+    details.set_create_popup_func(clone!(
+      #[strong]
+      attachment,
+      move |button| {
+        if button.popover().is_none() {
+          button.set_popover(Some(&Self::attachment_details(&attachment)));
+        }
+      }
+    ));
+
     let save = gtk4::Button::new();
     save.set_valign(gtk4::Align::Center);
     save.set_icon_name("document-save-as-symbolic");
@@ -491,6 +1306,15 @@ impl MailViewerWindow {
         ));
       }
     ));
+    if Archive::is_archive(
+      &attachment.filename,
+      attachment.mime_type.as_deref(),
+      &attachment.body,
+    ) {
+      self.add_archive(attachment, mime, icon, &details, &save, preferences_group);
+      return;
+    }
+
     // The file name and the mime type come from the message, don't let them
     // through as pango markup.
     let btn = adw::ActionRow::builder()
@@ -500,7 +1324,13 @@ impl MailViewerWindow {
       .activatable(true)
       .build();
     btn.add_prefix(&gtk4::Image::from_icon_name(icon));
+    if let Some(badge) = Self::risk_badge(risk.level) {
+      badge.set_tooltip_text(Some(&risk.description()));
+      btn.add_suffix(&badge);
+    }
+    btn.add_suffix(&details);
     btn.add_suffix(&save);
+    Self::add_drag_source(&btn, attachment);
 
     btn.connect_activated(clone!(
       #[strong]
@@ -519,19 +1349,581 @@ impl MailViewerWindow {
           btn,
           async move {
             btn.set_sensitive(false);
-            window.on_attachment_open(&attachment).await;
+            window.on_attachment_activated(&attachment).await;
             btn.set_sensitive(true);
           }
         ));
       }
     ));
     preferences_group.add(&btn);
+    self.imp().attachment_rows.borrow_mut().push(btn.upcast());
+  }
+
+  /// Lets the attachment be dropped from its row onto a file manager, a chat
+  /// or an upload field. It is only written to the temporary folder once
+  /// dropped.
+  fn add_drag_source(row: &impl IsA<gtk4::Widget>, attachment: &Attachment) {
+    let row: gtk4::Widget = row.clone().upcast();
+    let drag_source = gtk4::DragSource::new();
+    drag_source.set_actions(gdk::DragAction::COPY);
+    drag_source.connect_prepare(clone!(
+      #[strong]
+      attachment,
+      move |_, _, _| Some(AttachmentContent::new(&attachment).upcast())
+    ));
+    drag_source.connect_drag_begin(clone!(
+      #[weak]
+      row,
+      move |drag_source, _| {
+        drag_source.set_icon(Some(&gtk4::WidgetPaintable::new(Some(&row))), 0, 0);
+      }
+    ));
+    row.add_controller(drag_source);
+  }
+
+  /// An archive is shown as a row which lists its files when expanded. The
+  /// archive is only read then, in the background.
+  fn add_archive(
+    &self,
+    attachment: &Attachment,
+    mime: &str,
+    icon: &str,
+    details: &gtk4::MenuButton,
+    save: &gtk4::Button,
+    preferences_group: &adw::PreferencesGroup,
+  ) {
+    let row = adw::ExpanderRow::builder()
+      .title(attachment.filename.to_string())
+      .subtitle(mime)
+      .use_markup(false)
+      .build();
+    row.add_prefix(&gtk4::Image::from_icon_name(icon));
+    let risk = attachment.risk();
+    if let Some(badge) = Self::risk_badge(risk.level) {
+      badge.set_tooltip_text(Some(&risk.description()));
+      row.add_suffix(&badge);
+    }
+
+    let open = gtk4::Button::new();
+    open.set_valign(gtk4::Align::Center);
+    open.set_icon_name("document-open-symbolic");
+    open.set_tooltip_text(Some(&gettext("Open")));
+    open.connect_clicked(clone!(
+      #[weak(rename_to = window)]
+      self,
+      #[strong]
+      attachment,
+      move |_| {
+        glib::spawn_future_local(clone!(
+          #[weak]
+          window,
+          #[strong]
+          attachment,
+          async move {
+            window.on_attachment_open(&attachment).await;
+          }
+        ));
+      }
+    ));
+    row.add_suffix(details);
+    row.add_suffix(save);
+    Self::add_drag_source(&row, attachment);
+    row.add_suffix(&open);
+
+    let loading = adw::ActionRow::builder()
+      .title(gettext("Reading the archive..."))
+      .build();
+    loading.add_prefix(&adw::Spinner::new());
+    row.add_row(&loading);
+
+    let loaded = Rc::new(Cell::new(false));
+    row.connect_expanded_notify(clone!(
+      #[weak(rename_to = window)]
+      self,
+      #[strong]
+      attachment,
+      move |row| {
+        if !row.is_expanded() || loaded.replace(true) {
+          return;
+        }
+        let filename = attachment.filename.clone();
+        let body = attachment.body.clone();
+        let name = filename.clone();
+        glib::spawn_future_local(clone!(
+          #[weak]
+          window,
+          #[weak]
+          row,
+          #[weak]
+          loading,
+          async move {
+            let archive = gio::spawn_blocking(move || {
+              Archive::open(&filename, &body).map_err(|e| e.to_string())
+            })
+            .await;
+            row.remove(&loading);
+            match archive {
+              Ok(Ok(archive)) => {
+                let archive = Arc::new(archive);
+                for index in 0..archive.entries().len() {
+                  row.add_row(&window.archive_entry_row(&archive, index));
+                }
+              }
+              Ok(Err(e)) => {
+                log::error!("Archive::open({}): {}", name, e);
+                window.alert_error(&gettext("Archive Error"), &e, false);
+              }
+              Err(_) => log::error!("Archive::open({}) panicked", name),
+            }
+          }
+        ));
+      }
+    ));
+    preferences_group.add(&row);
+    self.imp().attachment_rows.borrow_mut().push(row.upcast());
+  }
+
+  /// A file of an archive, extracted when opened or saved. It then goes through
+  /// the same previews, checks and file name sanitising as any attachment.
+  fn archive_entry_row(&self, archive: &Arc<Archive>, index: usize) -> adw::ActionRow {
+    let entry = &archive.entries()[index];
+    let row = adw::ActionRow::builder()
+      .title(entry.name.as_str())
+      .subtitle(glib::format_size(entry.size))
+      .use_markup(false)
+      .activatable(true)
+      .build();
+    if entry.escapes() {
+      let badge = gtk4::Label::new(Some(&gettext("Unsafe Path")));
+      badge.set_valign(gtk4::Align::Center);
+      badge.set_tooltip_text(Some(&gettext(
+        "The path of this file points outside of the archive, it is saved under its name only",
+      )));
+      badge.add_css_class("risk-badge");
+      badge.add_css_class("error");
+      row.add_suffix(&badge);
+    }
+
+    let save = gtk4::Button::new();
+    save.set_valign(gtk4::Align::Center);
+    save.set_icon_name("document-save-as-symbolic");
+    save.set_tooltip_text(Some(&gettext("Save as...")));
+    row.add_suffix(&save);
+
+    save.connect_clicked(clone!(
+      #[weak(rename_to = window)]
+      self,
+      #[strong]
+      archive,
+      move |_| {
+        glib::spawn_future_local(clone!(
+          #[weak]
+          window,
+          #[strong]
+          archive,
+          async move {
+            if let Some(attachment) = window.extract_archive_entry(archive, index).await {
+              window.on_attachment_save(&attachment).await;
+            }
+          }
+        ));
+      }
+    ));
+    row.connect_activated(clone!(
+      #[weak(rename_to = window)]
+      self,
+      #[strong]
+      archive,
+      move |row| {
+        glib::spawn_future_local(clone!(
+          #[weak]
+          window,
+          #[weak]
+          row,
+          #[strong]
+          archive,
+          async move {
+            row.set_sensitive(false);
+            if let Some(attachment) = window.extract_archive_entry(archive, index).await {
+              window.on_attachment_activated(&attachment).await;
+            }
+            row.set_sensitive(true);
+          }
+        ));
+      }
+    ));
+    row
+  }
+
+  async fn extract_archive_entry(&self, archive: Arc<Archive>, index: usize) -> Option<Attachment> {
+    let name = archive.entries()[index].name.clone();
+    log::debug!("extract_archive_entry({})", name);
+    match gio::spawn_blocking(move || archive.extract(index).map_err(|e| e.to_string())).await {
+      Ok(Ok(attachment)) => Some(attachment),
+      Ok(Err(e)) => {
+        log::error!("extract({}): {}", name, e);
+        self.alert_error(&gettext("Archive Error"), &e, false);
+        None
+      }
+      Err(_) => {
+        log::error!("extract({}) panicked", name);
+        None
+      }
+    }
+  }
+
+  /// The size, the type and the hashes of an attachment, the hashes with a
+  /// button to copy them.
+  fn attachment_details(attachment: &Attachment) -> gtk4::Popover {
+    let hashes = attachment.hashes();
+    let rows = [
+      (
+        gettext("Size"),
+        glib::format_size(attachment.body.len() as u64).to_string(),
+        false,
+      ),
+      (
+        gettext("Type"),
+        attachment.mime_type.clone().unwrap_or_default(),
+        false,
+      ),
+      ("SHA-256".to_string(), hashes.sha256, true),
+      ("SHA-1".to_string(), hashes.sha1, true),
+      ("MD5".to_string(), hashes.md5, true),
+    ];
+
+    let grid = gtk4::Grid::new();
+    grid.set_row_spacing(6);
+    grid.set_column_spacing(12);
+    for (row, (name, value, copy)) in rows.into_iter().enumerate() {
+      let row = row as i32;
+      let name = gtk4::Label::new(Some(&name));
+      name.set_xalign(0.0);
+      name.add_css_class("dim-label");
+      grid.attach(&name, 0, row, 1, 1);
+
+      let label = gtk4::Label::new(Some(&value));
+      label.set_xalign(0.0);
+      label.set_selectable(true);
+      grid.attach(&label, 1, row, 1, 1);
+
+      if copy {
+        label.add_css_class("monospace");
+        let button = gtk4::Button::from_icon_name("edit-copy-symbolic");
+        button.set_tooltip_text(Some(&gettext("Copy")));
+        button.add_css_class("flat");
+        button.connect_clicked(move |button| button.clipboard().set_text(&value));
+        grid.attach(&button, 2, row, 1, 1);
+      }
+    }
+
+    let popover = gtk4::Popover::new();
+    popover.set_child(Some(&grid));
+    popover
+  }
+
+  /// A contact of a vCard attachment: its name, its organization and its photo,
+  /// expanding to its addresses and numbers, each with a button to copy it.
+  fn contact_row(contact: &Contact) -> adw::ExpanderRow {
+    let subtitle = [contact.title.as_str(), contact.organization.as_str()]
+      .into_iter()
+      .filter(|part| !part.is_empty())
+      .collect::<Vec<&str>>()
+      .join(", ");
+    let row = adw::ExpanderRow::builder()
+      .title(contact.name.as_str())
+      .subtitle(subtitle)
+      .use_markup(false)
+      .build();
+
+    let avatar = adw::Avatar::new(32, Some(&contact.name), true);
+    if let Some(photo) = contact.photo.clone() {
+      let name = contact.name.clone();
+      glib::spawn_future_local(clone!(
+        #[weak]
+        avatar,
+        async move {
+          match gio::spawn_blocking(move || utils::image::thumbnail(&photo, 64)).await {
+            Ok(Ok(pixels)) => avatar.set_custom_image(Some(&pixels.texture())),
+            Ok(Err(e)) => log::error!("contact photo({}): {}", name, e),
+            Err(_) => log::error!("contact photo({}) panicked", name),
+          }
+        }
+      ));
+    }
+    row.add_prefix(&avatar);
+
+    let mut fields: Vec<(String, String)> = vec![];
+    for (kind, values) in [
+      (gettext("Email"), &contact.emails),
+      (gettext("Phone"), &contact.phones),
+    ] {
+      for field in values {
+        let kind = if field.types.is_empty() {
+          kind.clone()
+        } else {
+          format!("{} ({})", kind, field.types.join(", "))
+        };
+        fields.push((field.value.clone(), kind));
+      }
+    }
+    for (value, kind) in [
+      (&contact.organization, gettext("Organization")),
+      (&contact.title, gettext("Title")),
+    ] {
+      if !value.is_empty() {
+        fields.push((value.clone(), kind));
+      }
+    }
+
+    for (value, kind) in fields {
+      let field = adw::ActionRow::builder()
+        .title(value.as_str())
+        .subtitle(kind)
+        .use_markup(false)
+        .build();
+      let copy = gtk4::Button::from_icon_name("edit-copy-symbolic");
+      copy.set_valign(gtk4::Align::Center);
+      copy.set_tooltip_text(Some(&gettext("Copy")));
+      copy.add_css_class("flat");
+      copy.connect_clicked(move |button| button.clipboard().set_text(&value));
+      field.add_suffix(&copy);
+      row.add_row(&field);
+    }
+    row
+  }
+
+  /// Saves the contacts of all the vCard attachments to a single file.
+  async fn export_contacts(&self) {
+    let contacts = self.imp().contacts.borrow().clone();
+    log::debug!("export_contacts({})", contacts.len());
+    if contacts.is_empty() {
+      return;
+    }
+
+    let vcf = gtk4::FileFilter::new();
+    vcf.set_name(Some("vCard"));
+    vcf.add_pattern("*.vcf");
+    vcf.add_mime_type("text/vcard");
+    let filters = gio::ListStore::new::<gtk4::FileFilter>();
+    filters.append(&vcf);
+
+    let save_dialog = gtk4::FileDialog::builder()
+      .title(gettext("Export Contacts"))
+      .modal(true)
+      .filters(&filters)
+      .initial_name(gettext("contacts.vcf"))
+      .build();
+
+    match save_dialog.save_future(Some(self)).await {
+      Ok(file) => {
+        if let Err((_, e)) = file
+          .replace_contents_future(
+            vcard::to_vcf(&contacts),
+            None,
+            false,
+            gio::FileCreateFlags::REPLACE_DESTINATION,
+          )
+          .await
+        {
+          log::error!("export_contacts({})", e);
+          self.alert_error(&gettext("File Error"), &e.to_string(), false);
+        }
+      }
+      Err(e) => match e.kind() {
+        Some(gtk4::DialogError::Dismissed) | Some(gtk4::DialogError::Cancelled) => (),
+        _ => log::error!("save_dialog({})", e),
+      },
+    }
+  }
+
+  /// A tile of the image grid, the thumbnail being decoded in the background.
+  fn add_thumbnail(&self, attachment: &Attachment) {
+    let picture = gtk4::Picture::new();
+    picture.set_size_request(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+    let label = gtk4::Label::new(Some(&attachment.filename));
+    label.set_ellipsize(gtk4::pango::EllipsizeMode::Middle);
+    label.set_max_width_chars(18);
+
+    let tile = gtk4::Box::new(gtk4::Orientation::Vertical, 6);
+    tile.append(&picture);
+    tile.append(&label);
+    let child = gtk4::FlowBoxChild::new();
+    child.set_child(Some(&tile));
+    child.set_tooltip_text(Some(&attachment.filename));
+    child.add_css_class("thumbnail");
+    self.imp().image_grid.append(&child);
+
+    let body = attachment.body.clone();
+    let filename = attachment.filename.clone();
+    glib::spawn_future_local(clone!(
+      #[weak]
+      picture,
+      async move {
+        match gio::spawn_blocking(move || utils::image::thumbnail(&body, THUMBNAIL_SIZE)).await {
+          Ok(Ok(pixels)) => picture.set_paintable(Some(&pixels.texture())),
+          Ok(Err(e)) => log::error!("thumbnail({}): {}", filename, e),
+          Err(_) => log::error!("thumbnail({}) panicked", filename),
+        }
+      }
+    ));
+  }
+
+  /// Saves the images selected in the grid to a folder, and tells about the
+  /// ones which failed all at once.
+  async fn save_selected_images(&self) {
+    let imp = self.imp();
+    let selected: Vec<Attachment> = {
+      let images = imp.images.borrow();
+      imp
+        .image_grid
+        .selected_children()
+        .iter()
+        .filter_map(|child| images.get(child.index() as usize).cloned())
+        .collect()
+    };
+    log::debug!("save_selected_images({})", selected.len());
+    if selected.is_empty() {
+      return;
+    }
+    self
+      .save_to_folder(&gettext("Save Selected Images"), &selected)
+      .await;
+  }
+
+  async fn save_all_attachments(&self) {
+    let attachments = self
+      .service()
+      .map(|service| service.attachments())
+      .unwrap_or_default();
+    log::debug!("save_all_attachments({})", attachments.len());
+    if attachments.is_empty() {
+      return;
+    }
+    self
+      .save_to_folder(&gettext("Save All Attachments"), &attachments)
+      .await;
+  }
+
+  /// Asks for a folder, then writes the attachments in it. A name which is
+  /// taken, by a file of the folder or by another attachment, is numbered
+  /// rather than replaced, and what could not be written is told at the end.
+  async fn save_to_folder(&self, title: &str, attachments: &[Attachment]) {
+    let folder_dialog = gtk4::FileDialog::builder().title(title).modal(true).build();
+    if let Some(folder) = self
+      .service()
+      .and_then(|service| service.get_file())
+      .and_then(|file| file.parent())
+    {
+      folder_dialog.set_initial_folder(Some(&folder));
+    }
+
+    match folder_dialog.select_folder_future(Some(self)).await {
+      Ok(folder) => {
+        let taken = match Self::file_names(&folder).await {
+          Ok(taken) => taken,
+          Err(e) => {
+            log::error!("file_names({})", e);
+            self.alert_error(&gettext("File Error"), &e.to_string(), false);
+            return;
+          }
+        };
+        let names =
+          Attachment::unique_filenames(attachments, |name| taken.contains(&name.to_lowercase()));
+        let mut failures: Vec<String> = vec![];
+        for (attachment, name) in attachments.iter().zip(&names) {
+          if let Err(e) = attachment.write_to_file(&folder.child(name)).await {
+            log::error!("write_to_file({})", e);
+            failures.push(format!("{}: {}", attachment.filename, e));
+          }
+        }
+        if !failures.is_empty() {
+          let title = ngettext(
+            "{count} attachment could not be saved",
+            "{count} attachments could not be saved",
+            failures.len().try_into().unwrap_or(u32::MAX),
+          )
+          .replace("{count}", &failures.len().to_string());
+          self.alert_error(&title, &failures.join("\n"), false);
+        }
+      }
+      Err(e) => match e.kind() {
+        Some(gtk4::DialogError::Dismissed) | Some(gtk4::DialogError::Cancelled) => (),
+        _ => log::error!("folder_dialog({})", e),
+      },
+    }
+  }
+
+  /// The names of the files in `folder`, lower case as
+  /// `Attachment::unique_filenames()` compares them.
+  async fn file_names(folder: &gio::File) -> Result<HashSet<String>, glib::Error> {
+    let enumerator = folder
+      .enumerate_children_future(
+        gio::FILE_ATTRIBUTE_STANDARD_NAME,
+        gio::FileQueryInfoFlags::NOFOLLOW_SYMLINKS,
+        glib::Priority::DEFAULT,
+      )
+      .await?;
+    let mut names = HashSet::new();
+    loop {
+      let infos = enumerator
+        .next_files_future(100, glib::Priority::DEFAULT)
+        .await?;
+      if infos.is_empty() {
+        break;
+      }
+      names.extend(
+        infos
+          .iter()
+          .map(|info| info.name().to_string_lossy().to_lowercase()),
+      );
+    }
+    enumerator.close_future(glib::Priority::DEFAULT).await?;
+    Ok(names)
+  }
+
+  fn risk_badge(level: RiskLevel) -> Option<gtk4::Label> {
+    let (label, style) = match level {
+      RiskLevel::Safe => return None,
+      RiskLevel::Suspicious => (gettext("Suspicious"), "warning"),
+      RiskLevel::Dangerous => (gettext("Dangerous"), "error"),
+    };
+    let badge = gtk4::Label::new(Some(&label));
+    badge.set_valign(gtk4::Align::Center);
+    badge.add_css_class("risk-badge");
+    badge.add_css_class(style);
+    Some(badge)
+  }
+
+  /// Asks before opening an attachment that looks dangerous. Returns whether it
+  /// should be opened.
+  async fn confirm_attachment_open(&self, attachment: &Attachment) -> bool {
+    let risk = attachment.risk();
+    if risk.level != RiskLevel::Dangerous {
+      return true;
+    }
+
+    let body = format!(
+      "{}\n\n{}",
+      gettext("{filename} may harm your computer:").replace("{filename}", &attachment.filename),
+      risk.description()
+    );
+    let alert = adw::AlertDialog::new(Some(&gettext("Open Dangerous Attachment?")), Some(&body));
+    alert.add_response("cancel", &gettext("Cancel"));
+    alert.add_response("open", &gettext("Open Anyway"));
+    alert.set_response_appearance("open", adw::ResponseAppearance::Destructive);
+    alert.set_default_response(Some("cancel"));
+    alert.set_close_response("cancel");
+
+    alert.choose_future(Some(self)).await == "open"
   }
 
   async fn on_attachment_save(&self, attachment: &Attachment) {
     log::debug!("on_attachment_save({})", attachment.filename);
 
-    let current_file = self.imp().service.get_file().unwrap();
+    let Some(current_file) = self.service().and_then(|service| service.get_file()) else {
+      return;
+    };
     let initial_file = current_file
       .parent()
       .unwrap()
@@ -563,8 +1955,53 @@ impl MailViewerWindow {
     }
   }
 
+  /// Shows an attachment inside the viewer when it can be, and hands it to
+  /// another application otherwise.
+  async fn on_attachment_activated(&self, attachment: &Attachment) {
+    match attachment.preview_kind() {
+      Some(kind) => self.preview_attachment(attachment, kind),
+      None => self.on_attachment_open(attachment).await,
+    }
+  }
+
+  fn preview_attachment(&self, attachment: &Attachment, kind: PreviewKind) {
+    log::debug!("preview_attachment({}, {:?})", attachment.filename, kind);
+    let webview = WebView::builder()
+      .network_session(&self.imp().network_session)
+      .build();
+    self.initialise_webview(&webview, &webkit6::Settings::new());
+
+    previewdialog::show(
+      self,
+      attachment,
+      kind,
+      webview,
+      clone!(
+        #[weak(rename_to = window)]
+        self,
+        #[strong]
+        attachment,
+        move || {
+          glib::spawn_future_local(clone!(
+            #[weak]
+            window,
+            #[strong]
+            attachment,
+            async move {
+              window.on_attachment_open(&attachment).await;
+            }
+          ));
+        }
+      ),
+    );
+  }
+
   async fn on_attachment_open(&self, attachment: &Attachment) {
     log::debug!("on_button_clicked({})", attachment.filename);
+    if !self.confirm_attachment_open(attachment).await {
+      log::warn!("on_attachment_open({}) not confirmed", attachment.filename);
+      return;
+    }
     match attachment.write_to_tmp().await {
       Ok(file) => {
         let path = file.peek_path().unwrap().to_string_lossy().to_string();
@@ -581,11 +2018,33 @@ impl MailViewerWindow {
     };
   }
 
+  /// The zoom level of the selected tab, the one the next tab starts at
+  /// without one.
+  fn zoom_level(&self) -> f64 {
+    match self.selected_tab() {
+      Some(tab) => tab.webview.zoom_level(),
+      None => self
+        .imp()
+        .settings
+        .get()
+        .map_or(1.0, |settings| settings.get::<f64>("zoom")),
+    }
+  }
+
   fn set_zoom_level(&self, zoom: f64) {
     let zoom = zoom.clamp(ZOOM_MIN, ZOOM_MAX);
     log::debug!("set_zoom({})", zoom);
-    self.imp().webview.set_zoom_level(zoom);
+    if let Some(tab) = self.selected_tab() {
+      tab.webview.set_zoom_level(zoom);
+    }
+    self.update_zoom_buttons(zoom);
+
+    if let Some(settings) = self.imp().settings.get() {
+      let _ = settings.set("zoom", zoom);
+    }
+  }
 
+  fn update_zoom_buttons(&self, zoom: f64) {
     if zoom <= ZOOM_MIN {
       self.imp().zoom_minus.set_sensitive(false);
     } else {
@@ -596,29 +2055,221 @@ impl MailViewerWindow {
     } else {
       self.imp().zoom_plus.set_sensitive(true);
     }
+  }
 
-    if let Some(settings) = self.imp().settings.get() {
-      let _ = settings.set("zoom", zoom);
-    }
+  /// Whether the message may pull content from the network : the "Show remote
+  /// images" button is on, or its sender is a trusted one.
+  fn remote_allowed(&self) -> bool {
+    self.imp().show_images.is_active() || self.sender_is_trusted()
+  }
+
+  fn sender_is_trusted(&self) -> bool {
+    self.service().is_some_and(|service| {
+      TrustedSenders::new(&self.get_settings_trusted_senders()).trusts(&service.from())
+    })
   }
 
-  /// Sanitizes the body of the message, allowing remote content only while the
-  /// "Show remote images" button is on.
-  fn sanitized_html(&self, html: &str, force_css: bool) -> String {
+  /// The body of the message as it is sanitized, allowing remote content only
+  /// when `remote_allowed()`.
+  fn message_html(&self, html: &str, force_css: bool) -> Html {
     Html::new(html, force_css)
-      .allow_remote(self.imp().show_images.is_active())
+      .allow_remote(self.remote_allowed())
       .dark(adw::StyleManager::default().is_dark())
-      .inline_images(&self.imp().service.attachments())
-      .safe()
+      .inline_images(
+        &self
+          .service()
+          .map(|service| service.attachments())
+          .unwrap_or_default(),
+      )
+  }
+
+  /// How the message of the selected tab is to be rendered.
+  fn rendering(&self, force_css: bool) -> Rendering {
+    Rendering {
+      force_css,
+      dark_rendering: self.imp().dark_rendering.is_active(),
+      remote_allowed: self.remote_allowed(),
+      dark: adw::StyleManager::default().is_dark(),
+    }
+  }
+
+  /// The html view of the message of `tab`.
+  fn message_page(&self, tab: &MessageTab, rendering: Rendering) -> Html {
+    let body = tab.service.body_html().unwrap_or_default();
+    self
+      .message_html(&body, rendering.force_css)
+      .dark_rendering(rendering.dark_rendering)
+      .collapse_quotes(&gettext("Quoted text"))
   }
 
   fn load_html(&self, force_css: bool) {
     log::debug!("load_html({})", force_css);
-    let html = self.imp().service.body_html().unwrap_or_default();
-    self
-      .imp()
-      .webview
-      .load_html(&self.sanitized_html(&html, force_css), None);
+    let Some(tab) = self.selected_tab() else {
+      return;
+    };
+    let rendering = self.rendering(force_css);
+    let html = self.message_page(&tab, rendering);
+    if let Some(websettings) = tab.webview.settings() {
+      websettings.set_auto_load_images(rendering.remote_allowed);
+    }
+    let page = html.safe();
+    tab.links.replace(html::link_labels(&page));
+    tab.webview.load_html(&page, None);
+    tab.rendering.set(Some(rendering));
+    self.update_privacy_banner(&html);
+  }
+
+  /// Tells what was kept from reporting back to the sender, and offers to trust
+  /// them when remote content is blocked. Only known once the body has been
+  /// sanitized.
+  fn update_privacy_banner(&self, html: &Html) {
+    let mut blocked: Vec<String> = vec![];
+    let remote_blocked = !self.remote_allowed() && html.remote_image_count() > 0;
+
+    if remote_blocked {
+      blocked.push(gettext("Remote images are blocked"));
+    }
+
+    let trackers = html.tracker_count();
+    if trackers > 0 {
+      blocked.push(
+        ngettext(
+          "{total} tracking image blocked",
+          "{total} tracking images blocked",
+          trackers.try_into().unwrap(),
+        )
+        .replace("{total}", &trackers.to_string()),
+      );
+    }
+    let links = html.tracking_link_count();
+    if links > 0 {
+      blocked.push(
+        ngettext(
+          "{total} tracking link cleaned",
+          "{total} tracking links cleaned",
+          links.try_into().unwrap(),
+        )
+        .replace("{total}", &links.to_string()),
+      );
+    }
+
+    log::debug!("update_privacy_banner({:?})", blocked);
+    let banner = &self.imp().privacy_banner;
+    let sender = self
+      .service()
+      .and_then(|service| TrustedSenders::sender_address(&service.from()));
+    banner.set_title(&blocked.join(", "));
+    banner.set_button_label(
+      (remote_blocked && sender.is_some())
+        .then(|| gettext("Always load from this sender"))
+        .as_deref(),
+    );
+    banner.set_revealed(!blocked.is_empty());
+  }
+
+  /// Shows where the link under the pointer really leads, whatever its text
+  /// claims.
+  fn on_mouse_target_changed(&self, hit_test_result: &webkit6::HitTestResult) {
+    let imp = self.imp();
+    let uri = hit_test_result
+      .link_uri()
+      .filter(|_| hit_test_result.context_is_link());
+
+    match uri {
+      Some(uri) => {
+        imp.link_status.set_label(&uri);
+        imp.link_status.set_visible(true);
+        let label = hit_test_result.link_label().unwrap_or_default();
+        imp
+          .hovered_link
+          .replace(Some((uri.to_string(), label.to_string())));
+      }
+      None => {
+        imp.link_status.set_visible(false);
+        imp.hovered_link.replace(None);
+      }
+    }
+  }
+
+  /// The text of the link of the message that leads to `uri`. A click
+  /// followed the link under the pointer. WebKit doesn't tell which link was
+  /// followed from the keyboard, the links of the message to the same
+  /// address are looked at then, a deceptive one first.
+  fn link_label(&self, clicked: bool, uri: &str) -> Option<String> {
+    let imp = self.imp();
+    let target = utils::normalize_link(uri);
+    if clicked {
+      if let Some((href, label)) = imp.hovered_link.borrow().as_ref() {
+        if utils::normalize_link(href) == target {
+          return Some(label.clone());
+        }
+      }
+    }
+    let tab = self.selected_tab()?;
+    let links = tab.links.borrow();
+    let labels: Vec<&String> = links
+      .iter()
+      .filter(|(href, _)| utils::normalize_link(href) == target)
+      .map(|(_, label)| label)
+      .collect();
+    labels
+      .iter()
+      .find(|label| utils::deceptive_link(label, uri))
+      .or(labels.first())
+      .map(|label| label.to_string())
+  }
+
+  /// Opens `uri` outside of the application, if its scheme is one a message
+  /// may lead to. `label` is the text of the link, to tell a deceptive one.
+  async fn open_link(
+    &self,
+    label: Option<String>,
+    uri: &str,
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    let allowed =
+      utils::uri_scheme(uri).is_some_and(|scheme| ALLOWED_URI_SCHEMES.contains(&scheme.as_str()));
+    if !allowed {
+      log::warn!("open_link(refused) => {}", uri);
+      return Ok(());
+    }
+
+    if let Some(label) = label {
+      if utils::deceptive_link(&label, uri) && !self.confirm_link(&label, uri).await {
+        log::warn!("open_link(not confirmed) => {}", uri);
+        return Ok(());
+      }
+    }
+
+    log::debug!("open_link(launch) => {}", uri);
+    if let Err(e) = gtk4::UriLauncher::new(uri).launch_future(Some(self)).await {
+      return Err(format!("{} ({}): {}", gettext("Failed to open uri"), uri, e).into());
+    }
+    Ok(())
+  }
+
+  /// Asks before following a link whose text shows another address than the
+  /// one it leads to. Returns whether the link should be opened, copying it
+  /// instead is handled here.
+  async fn confirm_link(&self, label: &str, uri: &str) -> bool {
+    let body = gettext("The text of this link shows:\n{label}\n\nbut it actually leads to:\n{uri}")
+      .replace("{label}", label.trim())
+      .replace("{uri}", uri);
+    let alert = adw::AlertDialog::new(Some(&gettext("Open Suspicious Link?")), Some(&body));
+    alert.add_response("cancel", &gettext("Cancel"));
+    alert.add_response("copy", &gettext("Copy Link"));
+    alert.add_response("open", &gettext("Open Link"));
+    alert.set_response_appearance("open", adw::ResponseAppearance::Destructive);
+    alert.set_default_response(Some("cancel"));
+    alert.set_close_response("cancel");
+
+    match alert.choose_future(Some(self)).await.as_str() {
+      "open" => true,
+      "copy" => {
+        self.clipboard().set_text(uri);
+        false
+      }
+      _ => false,
+    }
   }
 
   async fn decide_policy(
@@ -637,18 +2288,8 @@ impl MailViewerWindow {
               // Decide before launching, so that a refused or failing uri is never
               // loaded in the webview instead.
               policy.ignore();
-
-              let allowed = utils::uri_scheme(uri.as_str())
-                .is_some_and(|scheme| ALLOWED_URI_SCHEMES.contains(&scheme.as_str()));
-              if !allowed {
-                log::warn!("WebView decide_policy(refused) => {}", uri);
-                return Ok(true);
-              }
-
-              log::debug!("WebView decide_policy(launch) => {}", uri);
-              if let Err(e) = gtk4::UriLauncher::new(&uri).launch_future(Some(self)).await {
-                return Err(format!("{} ({}): {}", gettext("Failed to open uri"), uri, e).into());
-              }
+              let clicked = navigation_action.mouse_button() != 0;
+              self.open_link(self.link_label(clicked, &uri), &uri).await?;
               return Ok(true);
             }
             policy.ignore();
@@ -700,6 +2341,12 @@ impl MailViewerWindow {
       .set_visible_child_name(if show { "text" } else { "html" });
 
     imp.show_text.set_active(show);
+    if let Some(tab) = self.selected_tab() {
+      tab.show_text.set(show);
+    }
+    if imp.search_bar.is_search_mode() {
+      self.on_search_changed();
+    }
   }
 
   fn build_mail_file_dialog(&self, title: &String) -> gtk4::FileDialog {
@@ -722,27 +2369,37 @@ impl MailViewerWindow {
   }
 
   pub fn get_print_html(&self) -> String {
+    self.page_html(&[])
+  }
+
+  /// The printed page, with the attachments linked to `hrefs`.
+  fn page_html(&self, hrefs: &[String]) -> String {
     let imp = self.imp();
+    // Without a tab, there is only an empty page to print.
+    let service = self
+      .service()
+      .unwrap_or_else(|| Rc::new(MailService::new()));
     let content: String;
 
-    if let Some(html) = imp.service.body_html() {
+    if let Some(html) = service.body_html() {
       content = html;
-    } else if let Some(text) = imp.service.body_text() {
+    } else if let Some(text) = service.body_text() {
       content = format!("<pre>{}</pre>", Html::escape(&text));
     } else {
       content = String::new();
     }
-    let attachments = &imp.service.attachments();
+    let attachments = &service.attachments();
 
     Html::new(&content, false)
       .allow_remote(imp.show_images.is_active())
       .inline_images(attachments)
-      .safe_print(
-        imp.service.from().as_str(),
-        imp.service.to().as_str(),
-        imp.service.date().as_str(),
-        imp.service.subject().as_str(),
+      .safe_export(
+        service.from().as_str(),
+        service.to().as_str(),
+        service.date().as_str(),
+        service.subject().as_str(),
         attachments,
+        hrefs,
       )
   }
 
@@ -756,57 +2413,400 @@ impl MailViewerWindow {
     let html: String = self.get_print_html();
     self.initialise_webview(&webview, &websettings);
 
-    // The view has to stay alive until it has loaded, but holding it in its own
-    // signal handler makes a reference cycle and neither the view nor its web
-    // process are ever freed. Hold it here and let go once printing is done.
-    self.imp().print_webview.replace(Some(webview.clone()));
+    // The view has to stay alive until it has loaded, but holding it in its own
+    // signal handler makes a reference cycle and neither the view nor its web
+    // process are ever freed. Hold it here and let go once printing is done.
+    self.imp().print_webview.replace(Some(webview.clone()));
+
+    webview.connect_load_changed(clone!(
+      #[weak(rename_to = window)]
+      self,
+      move |webview, e| {
+        log::debug!("print load_html() event : {:?}", e);
+        if e == webkit6::LoadEvent::Finished {
+          let print_operation = PrintOperation::new(webview);
+
+          print_operation.connect_failed(move |_, error| {
+            log::error!("print failed: {}", error);
+          });
+          print_operation.connect_finished(clone!(
+            #[weak(rename_to = window)]
+            window,
+            move |_| {
+              log::debug!("print finished");
+              window.imp().print_operation.replace(None);
+              window.imp().print_webview.replace(None);
+            }
+          ));
+
+          window
+            .imp()
+            .print_operation
+            .replace(Some(print_operation.clone()));
+          let response = print_operation.run_dialog(Some(&window));
+          if response == PrintOperationResponse::Print {
+            log::debug!("print started");
+          } else {
+            log::debug!("print cancelled");
+            window.imp().print_operation.replace(None);
+            window.imp().print_webview.replace(None);
+          }
+        }
+      }
+    ));
+    webview.load_html(&html, None);
+  }
+
+  /// The name of the message file without its extension, for the files made
+  /// from it. None when no message is open.
+  fn message_file_stem(&self) -> Option<String> {
+    let current_file = self.service()?.get_file()?;
+    Some(
+      current_file
+        .basename()
+        .and_then(|name| {
+          name
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+        })
+        .unwrap_or("message".to_string()),
+    )
+  }
+
+  /// Saves the printed page on its own, for the places that cannot read a
+  /// mail file: as html, or as a web archive when `archive`. The attachments
+  /// are carried along when asked, as `data:` links or as parts of the archive.
+  async fn save_page(&self, archive: bool) {
+    log::debug!("save_page({})", archive);
+    let Some(service) = self.service() else {
+      return;
+    };
+    let Some(name) = self.message_file_stem() else {
+      return;
+    };
+    let attachments = service.attachments();
+
+    let embed = match attachments.len() {
+      0 => false,
+      count => match self.confirm_embed_attachments(count).await {
+        Some(embed) => embed,
+        None => return,
+      },
+    };
+
+    let (title, extension, filter) = if archive {
+      (gettext("Save as MHTML"), "mhtml", "MHTML")
+    } else {
+      (gettext("Save as HTML"), "html", "HTML")
+    };
+    let file_filter = gtk4::FileFilter::new();
+    file_filter.set_name(Some(filter));
+    file_filter.add_suffix(extension);
+    let filters = gio::ListStore::new::<gtk4::FileFilter>();
+    filters.append(&file_filter);
+
+    let save_dialog = gtk4::FileDialog::builder()
+      .title(title)
+      .modal(true)
+      .filters(&filters)
+      .initial_name(format!("{name}.{extension}"))
+      .build();
+
+    match save_dialog.save_future(Some(self)).await {
+      Ok(file) => {
+        let embedded: &[Attachment] = if embed { &attachments } else { &[] };
+        let content = if archive {
+          let hrefs: Vec<String> = (0..embedded.len()).map(mhtml::attachment_href).collect();
+          mhtml::archive(&service.subject(), &self.page_html(&hrefs), embedded)
+        } else {
+          let hrefs: Vec<String> = embedded.iter().map(Html::data_uri).collect();
+          self.page_html(&hrefs)
+        };
+
+        if let Err((_, e)) = file
+          .replace_contents_future(
+            content,
+            None,
+            false,
+            gio::FileCreateFlags::REPLACE_DESTINATION,
+          )
+          .await
+        {
+          log::error!("save_page({})", e);
+          self.alert_error(&gettext("File Error"), &e.to_string(), false);
+        }
+      }
+      Err(e) => match e.kind() {
+        Some(gtk4::DialogError::Dismissed) | Some(gtk4::DialogError::Cancelled) => (),
+        _ => log::error!("save_dialog({})", e),
+      },
+    }
+  }
+
+  /// Saves the message as an .eml file, converting it when it is an Outlook
+  /// one, for the tools that only read those.
+  async fn save_eml(&self) {
+    log::debug!("save_eml()");
+    let Some(service) = self.service() else {
+      return;
+    };
+    let Some(name) = self.message_file_stem() else {
+      return;
+    };
+
+    let eml = gtk4::FileFilter::new();
+    eml.set_name(Some("EML"));
+    eml.add_suffix("eml");
+    eml.add_mime_type("message/rfc822");
+    let filters = gio::ListStore::new::<gtk4::FileFilter>();
+    filters.append(&eml);
+
+    let save_dialog = gtk4::FileDialog::builder()
+      .title(gettext("Save as EML"))
+      .modal(true)
+      .filters(&filters)
+      .initial_name(format!("{name}.eml"))
+      .build();
+
+    match save_dialog.save_future(Some(self)).await {
+      Ok(file) => {
+        if let Err((_, e)) = file
+          .replace_contents_future(
+            service.to_eml(),
+            None,
+            false,
+            gio::FileCreateFlags::REPLACE_DESTINATION,
+          )
+          .await
+        {
+          log::error!("save_eml({})", e);
+          self.alert_error(&gettext("File Error"), &e.to_string(), false);
+        }
+      }
+      Err(e) => match e.kind() {
+        Some(gtk4::DialogError::Dismissed) | Some(gtk4::DialogError::Cancelled) => (),
+        _ => log::error!("save_dialog({})", e),
+      },
+    }
+  }
+
+  async fn save_attachments_zip(&self) {
+    let attachments = self
+      .service()
+      .map(|service| service.attachments())
+      .unwrap_or_default();
+    log::debug!("save_attachments_zip({})", attachments.len());
+    let Some(name) = self.message_file_stem() else {
+      return;
+    };
+    if attachments.is_empty() {
+      return;
+    }
+
+    let zip = gtk4::FileFilter::new();
+    zip.set_name(Some("ZIP"));
+    zip.add_suffix("zip");
+    zip.add_mime_type("application/zip");
+    let filters = gio::ListStore::new::<gtk4::FileFilter>();
+    filters.append(&zip);
+
+    let save_dialog = gtk4::FileDialog::builder()
+      .title(gettext("Save Attachments as ZIP"))
+      .modal(true)
+      .filters(&filters)
+      .initial_name(format!("{name}-attachments.zip"))
+      .build();
+
+    match save_dialog.save_future(Some(self)).await {
+      Ok(file) => {
+        // Compressing takes a while with large attachments.
+        let zip =
+          gio::spawn_blocking(move || Archive::zip(&attachments).map_err(|e| e.to_string()))
+            .await
+            .unwrap_or_else(|_| Err("Archive::zip() panicked".to_string()));
+        let result: Result<(), Box<dyn std::error::Error>> = match zip {
+          Ok(zip) => file
+            .replace_contents_future(zip, None, false, gio::FileCreateFlags::REPLACE_DESTINATION)
+            .await
+            .map(|_| ())
+            .map_err(|(_, e)| e.into()),
+          Err(e) => Err(e.into()),
+        };
+        if let Err(e) = result {
+          log::error!("save_attachments_zip({})", e);
+          self.alert_error(&gettext("File Error"), &e.to_string(), false);
+        }
+      }
+      Err(e) => match e.kind() {
+        Some(gtk4::DialogError::Dismissed) | Some(gtk4::DialogError::Cancelled) => (),
+        _ => log::error!("save_dialog({})", e),
+      },
+    }
+  }
+
+  /// Whether the attachments go in the saved page too, None when cancelled.
+  async fn confirm_embed_attachments(&self, count: usize) -> Option<bool> {
+    let body = ngettext(
+      "The message has {count} attachment. It can be saved in the page too, which makes \
+       it larger.",
+      "The message has {count} attachments. They can be saved in the page too, which makes \
+       it larger.",
+      count.try_into().unwrap_or(u32::MAX),
+    )
+    .replace("{count}", &count.to_string());
+    let alert = adw::AlertDialog::new(Some(&gettext("Embed Attachments?")), Some(&body));
+    alert.add_response("cancel", &gettext("Cancel"));
+    alert.add_response("list", &gettext("Names Only"));
+    alert.add_response("embed", &gettext("Embed"));
+    alert.set_response_appearance("embed", adw::ResponseAppearance::Suggested);
+    alert.set_default_response(Some("embed"));
+    alert.set_close_response("cancel");
+
+    match alert.choose_future(Some(self)).await.as_str() {
+      "embed" => Some(true),
+      "list" => Some(false),
+      _ => None,
+    }
+  }
+
+  /// Writes the indicators of compromise of the message to a JSON file, or to
+  /// a CSV one when the chosen name ends with `.csv`.
+  async fn export_indicators(&self) {
+    log::debug!("export_indicators()");
+    let Some(service) = self.service() else {
+      return;
+    };
+    let Some(name) = self.message_file_stem() else {
+      return;
+    };
+
+    let json = gtk4::FileFilter::new();
+    json.set_name(Some("JSON"));
+    json.add_pattern("*.json");
+    let csv = gtk4::FileFilter::new();
+    csv.set_name(Some("CSV"));
+    csv.add_pattern("*.csv");
+    let filters = gio::ListStore::new::<gtk4::FileFilter>();
+    filters.append(&json);
+    filters.append(&csv);
+
+    let save_dialog = gtk4::FileDialog::builder()
+      .title(gettext("Export Indicators"))
+      .modal(true)
+      .filters(&filters)
+      .initial_name(format!("{name}-indicators.json"))
+      .build();
+
+    match save_dialog.save_future(Some(self)).await {
+      Ok(file) => {
+        let body_text = service.body_text().unwrap_or_default();
+        let body_html = service.body_html().unwrap_or_default();
+        let indicators = Indicators::collect(
+          &service.from(),
+          &service.headers(),
+          &[&body_text, &body_html],
+          &service.attachments(),
+        );
+        let is_csv = file
+          .basename()
+          .and_then(|name| name.extension().map(|ext| ext.eq_ignore_ascii_case("csv")))
+          .unwrap_or(false);
+        let content = if is_csv {
+          indicators.to_csv()
+        } else {
+          indicators.to_json()
+        };
+
+        if let Err((_, e)) = file
+          .replace_contents_future(
+            content,
+            None,
+            false,
+            gio::FileCreateFlags::REPLACE_DESTINATION,
+          )
+          .await
+        {
+          log::error!("export_indicators({})", e);
+          self.alert_error(&gettext("File Error"), &e.to_string(), false);
+        }
+      }
+      Err(e) => match e.kind() {
+        Some(gtk4::DialogError::Dismissed) | Some(gtk4::DialogError::Cancelled) => (),
+        _ => log::error!("save_dialog({})", e),
+      },
+    }
+  }
+
+  /// Writes an event to an .ics file, which calendar applications import.
+  async fn export_event(&self, event: &CalendarEvent) {
+    log::debug!("export_event({})", event.summary);
+    let name: String = event
+      .summary
+      .chars()
+      .filter(|c| !c.is_control() && !matches!(c, '/' | '\\'))
+      .collect();
+    let name = match name.trim() {
+      "" => gettext("invitation"),
+      name => name.to_string(),
+    };
 
-    webview.connect_load_changed(clone!(
-      #[weak(rename_to = window)]
-      self,
-      move |webview, e| {
-        log::debug!("print load_html() event : {:?}", e);
-        if e == webkit6::LoadEvent::Finished {
-          let print_operation = PrintOperation::new(webview);
+    let ics = gtk4::FileFilter::new();
+    ics.set_name(Some(&gettext("iCalendar")));
+    ics.add_pattern("*.ics");
+    ics.add_mime_type("text/calendar");
+    let filters = gio::ListStore::new::<gtk4::FileFilter>();
+    filters.append(&ics);
 
-          print_operation.connect_failed(move |_, error| {
-            log::error!("print failed: {}", error);
-          });
-          print_operation.connect_finished(clone!(
-            #[weak(rename_to = window)]
-            window,
-            move |_| {
-              log::debug!("print finished");
-              window.imp().print_operation.replace(None);
-              window.imp().print_webview.replace(None);
-            }
-          ));
+    let save_dialog = gtk4::FileDialog::builder()
+      .title(gettext("Add to Calendar"))
+      .modal(true)
+      .filters(&filters)
+      .initial_name(format!("{name}.ics"))
+      .build();
 
-          window
-            .imp()
-            .print_operation
-            .replace(Some(print_operation.clone()));
-          let response = print_operation.run_dialog(Some(&window));
-          if response == PrintOperationResponse::Print {
-            log::debug!("print started");
-          } else {
-            log::debug!("print cancelled");
-            window.imp().print_operation.replace(None);
-            window.imp().print_webview.replace(None);
-          }
+    match save_dialog.save_future(Some(self)).await {
+      Ok(file) => {
+        if let Err((_, e)) = file
+          .replace_contents_future(
+            event.to_ics(),
+            None,
+            false,
+            gio::FileCreateFlags::REPLACE_DESTINATION,
+          )
+          .await
+        {
+          log::error!("export_event({})", e);
+          self.alert_error(&gettext("File Error"), &e.to_string(), false);
         }
       }
-    ));
-    webview.load_html(&html, None);
+      Err(e) => match e.kind() {
+        Some(gtk4::DialogError::Dismissed) | Some(gtk4::DialogError::Cancelled) => (),
+        _ => log::error!("save_dialog({})", e),
+      },
+    }
+  }
+
+  /// Shows the message a delivery report was sent back with in place of the
+  /// report.
+  async fn open_original(&self, original: &Attachment) {
+    log::debug!("open_original({})", original.filename);
+    match original.write_to_tmp().await {
+      Ok(file) => self.open_file(&file).await,
+      Err(e) => {
+        log::error!("open_original({})", e);
+        self.alert_error(&gettext("File Error"), &e.to_string(), false);
+      }
+    }
   }
 
   pub async fn open_file_dialog(&self, close_on_cancel: bool) -> bool {
     log::debug!("open_file_dialog()");
 
     let load_dialog = self.build_mail_file_dialog(&gettext("Open Mail File"));
-    match load_dialog.open_future(Some(self)).await {
-      Ok(file) => {
-        self.open_file(&file).await;
+    match load_dialog.open_multiple_future(Some(self)).await {
+      Ok(files) => {
+        let files: Vec<gio::File> = files.iter::<gio::File>().filter_map(Result::ok).collect();
+        self.open_files(&files).await;
         return true;
       }
       Err(e) => match e.kind() {
@@ -822,79 +2822,476 @@ impl MailViewerWindow {
     false
   }
 
+  pub async fn open_folder_dialog(&self) {
+    log::debug!("open_folder_dialog()");
+
+    let dialog = gtk4::FileDialog::builder()
+      .title(gettext("Open Folder"))
+      .modal(true)
+      .build();
+    match dialog.select_folder_future(Some(self)).await {
+      Ok(folder) => self.open_folder(&folder).await,
+      Err(e) => match e.kind() {
+        Some(gtk4::DialogError::Dismissed) | Some(gtk4::DialogError::Cancelled) => (),
+        _ => log::error!("open_folder_dialog({})", e),
+      },
+    }
+  }
+
+  /// Lists the messages of a folder next to the reading pane, the one
+  /// selected there is opened in the selected tab.
+  pub async fn open_folder(&self, folder: &gio::File) {
+    let imp = self.imp();
+    imp.message_list_box.set_visible(true);
+    if let Err(e) = imp.message_list.load(folder).await {
+      log::error!("open_folder({})", e);
+      self.close_folder();
+      self.alert_error(
+        &gettext("Folder Error"),
+        &format!("{}:\n{}", gettext("Failed to open folder"), e),
+        false,
+      );
+    }
+  }
+
+  /// Asks for a folder, then searches its messages. The one picked is opened
+  /// with the longest of the words searched for highlighted.
+  pub async fn search_folder(&self) {
+    log::debug!("search_folder()");
+
+    let dialog = gtk4::FileDialog::builder()
+      .title(gettext("Search Folder"))
+      .modal(true)
+      .build();
+    if let Some(folder) = self.imp().message_list.folder() {
+      dialog.set_initial_folder(Some(&folder));
+    }
+    let folder = match dialog.select_folder_future(Some(self)).await {
+      Ok(folder) => folder,
+      Err(e) => {
+        match e.kind() {
+          Some(gtk4::DialogError::Dismissed) | Some(gtk4::DialogError::Cancelled) => (),
+          _ => log::error!("search_folder({})", e),
+        }
+        return;
+      }
+    };
+
+    searchdialog::show(
+      self,
+      &folder,
+      clone!(
+        #[weak(rename_to = window)]
+        self,
+        move |file, terms| {
+          let term = terms.into_iter().max_by_key(|term| term.chars().count());
+          glib::spawn_future_local(clone!(
+            #[weak]
+            window,
+            async move {
+              window.open_file(&file).await;
+              if let Some(term) = term {
+                window.highlight_opened(&file, term);
+              }
+            }
+          ));
+        }
+      ),
+    );
+  }
+
+  fn close_folder(&self) {
+    let imp = self.imp();
+    imp.message_list.clear();
+    imp.message_list_box.set_visible(false);
+  }
+
+  /// Opens a message in place of the one of the selected tab.
   pub async fn open_file(&self, file: &gio::File) {
+    let tab = self.selected_tab().unwrap_or_else(|| self.add_tab());
+    self.load_tab(&tab, file).await;
+  }
+
+  /// Opens messages each in a tab of its own. A tab nothing was loaded in yet
+  /// is used first.
+  pub async fn open_files(&self, files: &[gio::File]) {
+    for file in files {
+      let tab = match self.selected_tab() {
+        Some(tab) if tab.service.get_file().is_none() => tab,
+        _ => self.add_tab(),
+      };
+      self.load_tab(&tab, file).await;
+    }
+  }
+
+  async fn load_tab(&self, tab: &Rc<MessageTab>, file: &gio::File) {
     log::debug!("open_file({:?})", file.peek_path().unwrap_or_default());
+    let imp = self.imp();
 
+    imp.tab_view.set_selected_page(&tab.page);
+    // What was to be found in the previous message is not in this one.
+    imp.pending_highlight.take();
+    tab.page.set_loading(true);
     self.on_show_text(true);
     self.on_search_stopped();
-    self.imp().content_box.get().set_sensitive(false);
-    self.imp().sheet.get().set_open(false);
+    imp.content_box.get().set_sensitive(false);
+    imp.sheet.get().set_open(false);
+    self.action_set_enabled("win.previous-message", false);
+    self.action_set_enabled("win.next-message", false);
 
     let cancellable = gio::Cancellable::new();
-    {
-      self.imp().cancellable.replace_with(|old_cancellable| {
-        old_cancellable.cancel();
-        cancellable.clone()
-      });
+    tab.cancellable.replace(cancellable.clone()).cancel();
+
+    let result = tab.service.open_message(file, Some(&cancellable)).await;
+    if cancellable.is_cancelled() {
+      log::debug!(
+        "Ignoring loading of {}, action was cancelled",
+        file.peek_path().unwrap_or_default().display()
+      );
+      return;
     }
+    tab.page.set_loading(false);
+    // The views are to be rendered again, from the message or the one kept.
+    tab.rendered.set(false);
 
-    match self
-      .imp()
-      .service
-      .open_message(file, Some(&cancellable))
-      .await
-    {
+    let selected = imp.tab_view.selected_page().as_ref() == Some(&tab.page);
+    match result {
       Ok(_) => {
-        self.display_message();
+        if selected {
+          self.display_message();
+        }
+        self.update_folder(tab.clone());
       }
       Err(e) => {
-        if cancellable.is_cancelled() {
-          log::debug!(
-            "Ignoring loading of {}, action was cancelled",
-            file.peek_path().unwrap_or_default().display()
-          );
-          return;
-        }
         log::error!("service(ERR) : {}", e);
+        // A tab keeps the message it was showing. One that had none is closed,
+        // and so is the window when it has nothing else to show.
+        let keep_tab = tab.service.get_file().is_some();
+        let close_window = !keep_tab && imp.tab_view.n_pages() <= 1;
+        if keep_tab && selected {
+          self.display_message();
+        } else if !close_window {
+          imp.tab_view.close_page(&tab.page);
+        }
         self.alert_error(
           &gettext("File Error"),
           &format!("{}:\n{}", gettext("Failed to open file"), e),
-          true,
+          close_window,
         );
       }
     };
-    self.imp().content_box.get().set_sensitive(true);
+    if selected {
+      imp.content_box.get().set_sensitive(true);
+      self.update_navigation();
+    }
   }
 
-  pub fn display_message(&self) {
-    log::debug!("display_eml()");
+  /// Lists the folder the message of a tab is in, then reads the previous and
+  /// the next messages ahead, for them to show at once.
+  fn update_folder(&self, tab: Rc<MessageTab>) {
+    let Some(file) = tab.service.get_file() else {
+      return;
+    };
+    glib::spawn_future_local(clone!(
+      #[weak(rename_to = window)]
+      self,
+      async move {
+        let folder = match MailFolder::list(&file, window.get_settings_folder_sort()).await {
+          Ok(folder) => folder,
+          Err(e) => {
+            log::debug!("update_folder({})", e);
+            MailFolder::default()
+          }
+        };
+        let neighbours: Vec<gio::File> = [-1, 1]
+          .into_iter()
+          .filter_map(|offset| folder.neighbour(&file, offset))
+          .collect();
+        tab.folder.replace(folder);
+        if window.imp().tab_view.selected_page().as_ref() == Some(&tab.page) {
+          window.update_navigation();
+        }
+
+        let cancellable = tab.cancellable.borrow().clone();
+        for neighbour in neighbours {
+          if let Err(e) = tab.service.preload(&neighbour, Some(&cancellable)).await {
+            log::debug!("preload({})", e);
+          }
+        }
+      }
+    ));
+  }
+
+  /// Enables going to the previous and the next message of the selected tab.
+  fn update_navigation(&self) {
+    let selected = self
+      .selected_tab()
+      .and_then(|tab| Some((tab.service.get_file()?, tab)));
+    let (previous, next) = match selected {
+      Some((file, tab)) => {
+        let folder = tab.folder.borrow();
+        (
+          folder.neighbour(&file, -1).is_some(),
+          folder.neighbour(&file, 1).is_some(),
+        )
+      }
+      None => (false, false),
+    };
+    self.action_set_enabled("win.previous-message", previous);
+    self.action_set_enabled("win.next-message", next);
+    self.imp().folder_navigation.set_visible(previous || next);
+  }
+
+  /// Opens the message `offset` places away in the folder of the selected
+  /// tab, in place of its message.
+  async fn open_neighbour(&self, offset: isize) {
+    let Some(tab) = self.selected_tab() else {
+      return;
+    };
+    let Some(file) = tab.service.get_file() else {
+      return;
+    };
+    let neighbour = tab.folder.borrow().neighbour(&file, offset);
+    if let Some(neighbour) = neighbour {
+      self.open_file(&neighbour).await;
+    }
+  }
+
+  /// Adds a tab for a message to be loaded in, and selects it.
+  fn add_tab(&self) -> Rc<MessageTab> {
     let imp = self.imp();
+    // The reading pane is shared by the tabs, a page only stands for its
+    // message and the views are put in the pane when it is selected.
+    let page = imp
+      .tab_view
+      .append(&gtk4::Box::new(gtk4::Orientation::Vertical, 0));
+    page.set_title(&gettext("New Tab"));
+
+    let service = MailService::new();
+    service.set_show_file_name(self.get_settings_show_file_name());
+    service.connect_title_changed(clone!(
+      #[weak(rename_to = window)]
+      self,
+      #[weak]
+      page,
+      move |_, title| {
+        page.set_title(title);
+        if window.imp().tab_view.selected_page().as_ref() == Some(&page) {
+          window.set_title(Some(title));
+        }
+      }
+    ));
+
+    let tab = Rc::new(MessageTab {
+      page: page.clone(),
+      service: Rc::new(service),
+      cancellable: RefCell::new(gio::Cancellable::new()),
+      folder: RefCell::new(MailFolder::default()),
+      webview: self.message_webview(),
+      text: self.text_buffer(),
+      rendered: Cell::new(false),
+      rendering: Cell::new(None),
+      show_text: Cell::new(false),
+      links: RefCell::new(vec![]),
+      text_links: RefCell::new(vec![]),
+      text_folds: RefCell::new(vec![]),
+    });
+    imp.tabs.borrow_mut().push(tab.clone());
+    imp.tab_view.set_selected_page(&page);
+    tab
+  }
+
+  fn tab(&self, page: &adw::TabPage) -> Option<Rc<MessageTab>> {
+    self
+      .imp()
+      .tabs
+      .borrow()
+      .iter()
+      .find(|tab| &tab.page == page)
+      .cloned()
+  }
+
+  /// The tab shown in the reading pane, None before the first message is
+  /// opened and once the last tab is closed.
+  fn selected_tab(&self) -> Option<Rc<MessageTab>> {
+    self
+      .imp()
+      .tab_view
+      .selected_page()
+      .and_then(|page| self.tab(&page))
+  }
 
-    imp.from.set_text(imp.service.from().as_str());
-    imp.date.set_text(imp.service.date().as_str());
-    imp.to.set_text(imp.service.to().as_str());
-    imp.subject.set_text(imp.service.subject().as_str());
+  /// The message of the selected tab.
+  fn service(&self) -> Option<Rc<MailService>> {
+    self.selected_tab().map(|tab| tab.service.clone())
+  }
 
-    let mut has_text: bool = false;
-    let mut has_html: bool = false;
+  #[template_callback]
+  pub fn on_selected_page_changed(&self) {
+    let imp = self.imp();
+    let Some(page) = imp.tab_view.selected_page() else {
+      // The last tab was closed, its message goes with it.
+      self.set_title(Some(&format!("Mail Viewer v{}", crate::config::VERSION)));
+      self.on_search_stopped();
+      imp.sheet.get().set_open(false);
+      self.display_message();
+      self.update_navigation();
+      return;
+    };
+    // A page being added isn't a tab yet.
+    if self.tab(&page).is_none() {
+      return;
+    }
+    log::debug!("on_selected_page_changed({})", page.title());
+    self.set_title(Some(&page.title()));
+    imp.sheet.get().set_open(false);
+    imp.content_box.get().set_sensitive(!page.is_loading());
+    self.display_message();
+    self.update_navigation();
+  }
 
-    if let Some(text) = imp.service.body_text() {
-      imp.body_text.buffer().set_text(&text);
-      has_text = true;
+  #[template_callback]
+  pub fn on_page_detached(&self, page: &adw::TabPage) {
+    log::debug!("on_page_detached({})", page.title());
+    let tabs = self.imp().tabs.take();
+    let (detached, tabs): (Vec<_>, Vec<_>) = tabs.into_iter().partition(|tab| &tab.page == page);
+    self.imp().tabs.replace(tabs);
+    for tab in detached {
+      tab.cancellable.borrow().cancel();
+      // The tags of its folds are in the table the tabs share.
+      let tag_table = tab.text.tag_table();
+      for (_, tag) in tab.text_folds.take() {
+        tag_table.remove(&tag);
+      }
     }
+  }
 
-    if let Some(html) = imp.service.body_html() {
-      // The button keeps its state across messages, so the new one has to be
-      // rendered the way it says.
-      let force_css = imp.force_css.is_active();
-      imp
-        .webview
-        .load_html(&self.sanitized_html(&html, force_css), None);
-      has_html = true;
+  /// Puts the views of `tab` in the reading pane, where they are as they were
+  /// left. Its message is rendered in them when they don't show it yet, or not
+  /// the way the buttons say.
+  fn show_views(&self, tab: &MessageTab) {
+    let imp = self.imp();
+    let has_html = tab.service.body_html().is_some();
+    imp
+      .show_text
+      .set_visible(has_html && tab.service.body_text().is_some());
+
+    // The plain text view comes back to the line it was left at.
+    let previous = imp.body_text.buffer();
+    if previous != tab.text {
+      let top = imp.body_text.visible_rect();
+      if let Some(iter) = imp.body_text.iter_at_location(top.x(), top.y()) {
+        match previous.mark(MARK_TOP) {
+          Some(mark) => previous.move_mark(&mark, &iter),
+          None => {
+            previous.create_mark(Some(MARK_TOP), &iter, true);
+          }
+        }
+      }
+      imp.body_text.set_buffer(Some(&tab.text));
+      if let Some(mark) = tab.text.mark(MARK_TOP) {
+        imp.body_text.scroll_to_mark(&mark, 0.0, true, 0.0, 0.0);
+      }
+    }
+    imp.placeholder.set_child(Some(&tab.webview));
+
+    if !tab.rendered.get() {
+      Self::set_body_text(tab, &tab.service.body_text().unwrap_or_default());
+      tab.rendering.set(None);
+      tab.show_text.set(!has_html);
+      if !has_html {
+        tab.links.take();
+        tab.webview.load_html("", None);
+      }
+      tab.rendered.set(true);
+    }
+    if has_html {
+      // The buttons keep their state across messages, so the message has to
+      // be rendered the way they say.
+      let rendering = self.rendering(imp.force_css.is_active());
+      if tab.rendering.get() == Some(rendering) {
+        self.update_privacy_banner(&self.message_page(tab, rendering));
+      } else {
+        self.load_html(rendering.force_css);
+      }
+    }
+    self.on_show_text(tab.show_text.get());
+    self.update_zoom_buttons(tab.webview.zoom_level());
+  }
+
+  pub fn display_message(&self) {
+    log::debug!("display_eml()");
+    let imp = self.imp();
+    // Without a tab, an empty message empties the reading pane.
+    let service = self
+      .service()
+      .unwrap_or_else(|| Rc::new(MailService::new()));
+
+    imp.from.set_text(service.from().as_str());
+    imp.date.set_text(service.date().as_str());
+    imp.to.set_text(service.to().as_str());
+    imp.subject.set_text(service.subject().as_str());
+
+    imp.privacy_banner.set_revealed(false);
+    match self.selected_tab() {
+      Some(tab) => self.show_views(&tab),
+      None => {
+        imp.placeholder.set_child(None::<&gtk4::Widget>);
+        imp.body_text.set_buffer(Some(&self.text_buffer()));
+        imp.show_text.set_visible(false);
+        self.on_show_text(true);
+      }
+    }
+
+    while let Some(child) = imp.events_box.first_child() {
+      imp.events_box.remove(&child);
+    }
+    let events = service.events();
+    for event in &events {
+      imp.events_box.append(&eventcard::event_card(
+        event,
+        clone!(
+          #[weak(rename_to = window)]
+          self,
+          move |event| {
+            let event = event.clone();
+            glib::spawn_future_local(clone!(
+              #[weak]
+              window,
+              async move {
+                window.export_event(&event).await;
+              }
+            ));
+          }
+        ),
+      ));
     }
+    imp.events_box.set_visible(!events.is_empty());
 
-    imp.show_text.set_visible(has_text && has_html);
-    self.on_show_text(!has_html);
+    while let Some(child) = imp.report_box.first_child() {
+      imp.report_box.remove(&child);
+    }
+    let report = service.delivery_report();
+    if let Some(report) = &report {
+      imp.report_box.append(&reportcard::report_card(
+        report,
+        clone!(
+          #[weak(rename_to = window)]
+          self,
+          move |original| {
+            let original = original.clone();
+            glib::spawn_future_local(clone!(
+              #[weak]
+              window,
+              async move {
+                window.open_original(&original).await;
+              }
+            ));
+          }
+        ),
+      ));
+    }
+    imp.report_box.set_visible(report.is_some());
 
     let preferences_group: adw::PreferencesGroup = adw::PreferencesGroup::new();
     self
@@ -902,11 +3299,17 @@ impl MailViewerWindow {
       .attachments_clamp
       .set_child(Some(&preferences_group));
 
-    let attachments = imp.service.attachments();
+    let attachments = service.attachments();
     let total = attachments.len();
+    let mut contacts: Vec<Contact> = vec![];
+    imp.attachment_rows.borrow_mut().clear();
     if total > 0 {
       for attachment in &attachments {
         self.add_attachment(attachment, &preferences_group);
+        for contact in attachment.contacts() {
+          preferences_group.add(&Self::contact_row(&contact));
+          contacts.push(contact);
+        }
       }
       let fmt: String = ngettext(
         "{total} attachment",
@@ -922,6 +3325,23 @@ impl MailViewerWindow {
       imp.pull_label.set_text(&gettext("No attachments"));
     }
 
+    imp.image_grid.remove_all();
+    imp.save_selected.set_sensitive(false);
+    let images: Vec<Attachment> = attachments
+      .into_iter()
+      .filter(|attachment| attachment.preview_kind() == Some(PreviewKind::Image))
+      .collect();
+    for image in &images {
+      self.add_thumbnail(image);
+    }
+    imp.attachments_view.set_visible(!images.is_empty());
+    if images.is_empty() {
+      imp.attachments_view.set_active(false);
+    }
+    imp.images.replace(images);
+    imp.export_contacts.set_visible(!contacts.is_empty());
+    imp.contacts.replace(contacts);
+
     if let Some(widget) = imp.sheet.bottom_bar() {
       if total > 0 {
         widget.set_visible(true)
@@ -929,6 +3349,9 @@ impl MailViewerWindow {
         widget.set_visible(false)
       }
     }
+    if imp.search_bar.is_search_mode() {
+      self.search_places(&imp.search_entry.text());
+    }
   }
 
   pub fn alert_error(&self, title: &str, message: &str, close_window: bool) -> adw::AlertDialog {
@@ -965,6 +3388,13 @@ impl MailViewerWindow {
     }
   }
 
+  fn get_settings_folder_sort(&self) -> FolderSort {
+    match self.imp().settings.get() {
+      Some(settings) => FolderSort::from_setting(&settings.string(SETTINGS_FOLDER_SORT)),
+      None => FolderSort::Name,
+    }
+  }
+
   fn get_settings_show_file_name(&self) -> bool {
     self.get_settings_bool(SETTINGS_SHOW_FILE_NAME)
   }
@@ -973,6 +3403,64 @@ impl MailViewerWindow {
     self.get_settings_bool(SETTINGS_FORCE_CSS)
   }
 
+  fn get_settings_trusted_senders(&self) -> Vec<String> {
+    if let Some(settings) = self.imp().settings.get() {
+      settings
+        .strv(SETTINGS_TRUSTED_SENDERS)
+        .iter()
+        .map(|entry| entry.to_string())
+        .collect()
+    } else {
+      vec![]
+    }
+  }
+
+  fn set_settings_trusted_senders(&self, entries: Vec<String>) {
+    if let Some(settings) = self.imp().settings.get() {
+      if let Err(e) = settings.set_strv(SETTINGS_TRUSTED_SENDERS, entries) {
+        log::error!("set_settings_trusted_senders({})", e);
+      }
+    }
+  }
+
+  /// Fills the trusted senders page of the preferences, every entry with a
+  /// button to remove it. Built again whenever the list changes.
+  fn fill_trusted_senders(
+    &self,
+    group: &adw::PreferencesGroup,
+    rows: &RefCell<Vec<adw::ActionRow>>,
+  ) {
+    for row in rows.take() {
+      group.remove(&row);
+    }
+
+    for entry in self.get_settings_trusted_senders() {
+      let remove = gtk4::Button::from_icon_name("user-trash-symbolic");
+      remove.set_valign(gtk4::Align::Center);
+      remove.set_tooltip_text(Some(&gettext("Remove")));
+      remove.add_css_class("flat");
+      remove.connect_clicked(clone!(
+        #[weak(rename_to = window)]
+        self,
+        #[strong]
+        entry,
+        move |_| {
+          let mut entries = window.get_settings_trusted_senders();
+          entries.retain(|trusted| *trusted != entry);
+          window.set_settings_trusted_senders(entries);
+        }
+      ));
+
+      let row = adw::ActionRow::builder()
+        .title(entry.as_str())
+        .use_markup(false)
+        .build();
+      row.add_suffix(&remove);
+      group.add(&row);
+      rows.borrow_mut().push(row);
+    }
+  }
+
   fn show_preferences(&self) {
     log::debug!("show_preferences()");
     match self.imp().settings.get() {
@@ -987,17 +3475,55 @@ impl MailViewerWindow {
           .bind(SETTINGS_FORCE_CSS, &force_css, "active")
           .build();
 
+        let trusted_sender_entry: adw::EntryRow = builder.object("trusted_sender_entry").unwrap();
+        let trusted_senders: adw::PreferencesGroup = builder.object("trusted_senders").unwrap();
+        let rows: Rc<RefCell<Vec<adw::ActionRow>>> = Rc::new(RefCell::new(vec![]));
+        self.fill_trusted_senders(&trusted_senders, &rows);
+        let changed = RefCell::new(Some(settings.connect_changed(
+          Some(SETTINGS_TRUSTED_SENDERS),
+          clone!(
+            #[weak(rename_to = win)]
+            self,
+            #[weak]
+            trusted_senders,
+            #[strong]
+            rows,
+            move |_, _| {
+              win.fill_trusted_senders(&trusted_senders, &rows);
+            }
+          ),
+        )));
+        trusted_sender_entry.connect_apply(clone!(
+          #[weak(rename_to = win)]
+          self,
+          move |entry| {
+            if let Some(trusted) = TrustedSenders::normalize(&entry.text()) {
+              let mut entries = win.get_settings_trusted_senders();
+              if !entries.contains(&trusted) {
+                entries.push(trusted);
+                win.set_settings_trusted_senders(entries);
+              }
+            }
+            entry.set_text("");
+          }
+        ));
+
         let prefs: adw::PreferencesDialog = builder.object("preferences").unwrap();
         prefs.present(Some(self));
         prefs.connect_closed(clone!(
           #[weak(rename_to = win)]
           self,
+          #[strong]
+          settings,
           move |_| {
             log::debug!("show_preferences() => done");
-            win
-              .imp()
-              .service
-              .set_show_file_name(win.get_settings_show_file_name());
+            if let Some(changed) = changed.take() {
+              settings.disconnect(changed);
+            }
+            let show_file_name = win.get_settings_show_file_name();
+            for tab in win.imp().tabs.borrow().iter() {
+              tab.service.set_show_file_name(show_file_name);
+            }
             win.set_force_css(win.get_settings_force_css());
           }
         ));